@@ -0,0 +1,180 @@
+use crate::models::{
+    Annotation, AnnotationInput, AnnotationKind, AnnotationUpdate, BookEntry, ChapterChange,
+};
+use crate::positions;
+use crate::storage;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use tauri::{AppHandle, Manager};
+
+/// Annotations grouped by book id.
+pub type AnnotationStore = HashMap<String, Vec<Annotation>>;
+
+/// Kept in Tauri state so that commands, re-imports and backup restores do
+/// not overwrite each other's changes to `annotations.json`. Hold the guard
+/// from `lock` from reading the store until it is written.
+#[derive(Default)]
+pub struct AnnotationState(Mutex<()>);
+
+pub fn lock(app: &AppHandle) -> Result<MutexGuard<'_, ()>, String> {
+    app.state::<AnnotationState>()
+        .inner()
+        .0
+        .lock()
+        .map_err(|_| "Annotation lock poisoned".to_string())
+}
+
+pub fn create(
+    store: &mut AnnotationStore,
+    book: &BookEntry,
+    input: AnnotationInput,
+) -> Result<Annotation, String> {
+    if input.kind != AnnotationKind::Bookmark
+        && input
+            .position
+            .sentence_text
+            .as_deref()
+            .is_none_or(|text| text.trim().is_empty())
+    {
+        return Err("Highlights and notes need the selected text.".to_string());
+    }
+    if input.kind == AnnotationKind::Note
+        && input.note.as_deref().is_none_or(|note| note.trim().is_empty())
+    {
+        return Err("Notes cannot be empty.".to_string());
+    }
+
+    let locator = positions::locate(book, &input.position)?;
+    let now = storage::now_iso();
+    let annotation = Annotation {
+        id: format!("annotation-{}", chrono::Utc::now().timestamp_micros()),
+        book_id: book.id.clone(),
+        kind: input.kind,
+        locator,
+        color: match input.kind {
+            AnnotationKind::Bookmark => None,
+            _ => input.color,
+        },
+        note: input.note.filter(|note| !note.trim().is_empty()),
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    let annotations = store.entry(book.id.clone()).or_default();
+    annotations.push(annotation.clone());
+    sort(annotations);
+    Ok(annotation)
+}
+
+pub fn update(
+    store: &mut AnnotationStore,
+    id: &str,
+    update: AnnotationUpdate,
+) -> Result<Annotation, String> {
+    let annotation = store
+        .values_mut()
+        .flat_map(|annotations| annotations.iter_mut())
+        .find(|annotation| annotation.id == id)
+        .ok_or_else(|| format!("Annotation {} not found", id))?;
+    if annotation.kind != AnnotationKind::Bookmark {
+        annotation.color = update.color.or(annotation.color);
+    }
+    // An update without a note keeps the current one; an empty note clears
+    // it, which only notes themselves cannot do without.
+    if let Some(note) = update.note {
+        if note.trim().is_empty() {
+            if annotation.kind == AnnotationKind::Note {
+                return Err("Notes cannot be empty.".to_string());
+            }
+            annotation.note = None;
+        } else {
+            annotation.note = Some(note);
+        }
+    }
+    annotation.updated_at = storage::now_iso();
+    Ok(annotation.clone())
+}
+
+pub fn delete(store: &mut AnnotationStore, id: &str) -> bool {
+    let mut removed = false;
+    for annotations in store.values_mut() {
+        let before = annotations.len();
+        annotations.retain(|annotation| annotation.id != id);
+        removed |= annotations.len() != before;
+    }
+    store.retain(|_, annotations| !annotations.is_empty());
+    removed
+}
+
+/// Lists a book's annotations re-anchored against its current text, in
+/// reading order.
+pub fn list(store: &AnnotationStore, book: &BookEntry) -> Vec<Annotation> {
+    let mut annotations: Vec<Annotation> = store
+        .get(&book.id)
+        .into_iter()
+        .flatten()
+        .cloned()
+        .map(|mut annotation| {
+            if let Some(position) = positions::resolve(book, &annotation.locator) {
+                annotation.locator = position.locator;
+            }
+            annotation
+        })
+        .collect();
+    sort(&mut annotations);
+    annotations
+}
+
+/// Adds annotations saved elsewhere and returns how many were new. When both
+/// sides have the same annotation, the most recently updated copy wins.
+pub fn merge(store: &mut AnnotationStore, book_id: &str, incoming: Vec<Annotation>) -> usize {
+    let mut added = 0;
+    for mut annotation in incoming {
+        // Ids are looked up across books, so a copy of a book imported
+        // alongside the original needs its own ids.
+        let taken_elsewhere = store.iter().any(|(id, annotations)| {
+            id != book_id && annotations.iter().any(|existing| existing.id == annotation.id)
+        });
+        if taken_elsewhere {
+            annotation.id = format!("{}-{}", annotation.id, book_id);
+        }
+        annotation.book_id = book_id.to_string();
+
+        let annotations = store.entry(book_id.to_string()).or_default();
+        match annotations
+            .iter_mut()
+            .find(|existing| existing.id == annotation.id)
+        {
+            Some(existing) => {
+                if annotation.updated_at > existing.updated_at {
+                    *existing = annotation;
+                }
+            }
+            None => {
+                annotations.push(annotation);
+                added += 1;
+            }
+        }
+    }
+    if let Some(annotations) = store.get_mut(book_id) {
+        sort(annotations);
+    }
+    added
+}
+
+pub fn remap(store: &mut AnnotationStore, book: &BookEntry, changes: &[ChapterChange]) {
+    if let Some(annotations) = store.get_mut(&book.id) {
+        for annotation in annotations.iter_mut() {
+            positions::remap_locator(&mut annotation.locator, book, changes);
+        }
+        sort(annotations);
+    }
+}
+
+fn sort(annotations: &mut [Annotation]) {
+    annotations.sort_by(|a, b| {
+        a.locator
+            .progress
+            .total_cmp(&b.locator.progress)
+            .then_with(|| a.created_at.cmp(&b.created_at))
+    });
+}
@@ -0,0 +1,437 @@
+use crate::config;
+use crate::models::{AudioCacheStats, BookAudioUsage};
+use crate::storage;
+use crate::tts::{SpeechInput, SpeechPart, SynthesizedAudio};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use tauri::AppHandle;
+use unicode_normalization::UnicodeNormalization;
+
+const INDEX_FILE: &str = "index.json";
+const DEFAULT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+/// Cache hits only rewrite the index when they refresh an entry by at least
+/// this much, so playing cached audio doesn't rewrite it for every sentence.
+const TOUCH_PERSIST_SECONDS: i64 = 60 * 60;
+
+/// The audio cache index, loaded on first use. Synthesized clips are stored
+/// as `audio-cache/<key>.<ext>`, named after everything that shaped them.
+#[derive(Default)]
+pub struct AudioCacheState(Mutex<Option<AudioCache>>);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AudioCache {
+    max_bytes: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl Default for AudioCache {
+    fn default() -> Self {
+        AudioCache {
+            max_bytes: DEFAULT_MAX_BYTES,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    file: String,
+    mime: String,
+    bytes: u64,
+    provider: String,
+    created_at: i64,
+    last_used: i64,
+    /// Books that played this clip. A sentence shared by several books is
+    /// counted against each of them.
+    #[serde(default)]
+    books: Vec<String>,
+}
+
+/// Identifies a clip by provider, the provider's options (voice, model,
+/// settings, format), the text with its pauses and emphasis, and the
+/// pronunciations found in it, ignoring differences that don't change the
+/// audio such as key order or surrounding whitespace.
+pub fn key(provider: &str, options: &Value, input: &SpeechInput) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(provider.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(canonical_json(options).as_bytes());
+    hasher.update(b"\n");
+    for (index, part) in input.parts.iter().enumerate() {
+        if index > 0 {
+            hasher.update(b"\n");
+        }
+        match part {
+            SpeechPart::Text { text, emphasis } => {
+                if *emphasis {
+                    hasher.update(b"<emphasis>");
+                }
+                hasher.update(normalize_text(text).as_bytes());
+            }
+            SpeechPart::Pause(millis) => hasher.update(format!("<pause {}>", millis).as_bytes()),
+        }
+    }
+    for entry in &input.pronunciations {
+        hasher.update(b"\n");
+        hasher.update(
+            serde_json::json!([entry.grapheme, entry.alias, entry.ipa, entry.case_sensitive])
+                .to_string()
+                .as_bytes(),
+        );
+    }
+    hex::encode(hasher.finalize())
+}
+
+fn normalize_text(text: &str) -> String {
+    text.nfc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// JSON with sorted keys and without nulls, so equal options hash equally.
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut fields: Vec<(&String, &Value)> =
+                map.iter().filter(|(_, value)| !value.is_null()).collect();
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            let fields: Vec<String> = fields
+                .into_iter()
+                .map(|(name, value)| {
+                    format!("{}:{}", Value::from(name.as_str()), canonical_json(value))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        Value::Null => "{}".to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Returns the cached clip for `key`, crediting it to `book_id`.
+pub fn get(
+    app: &AppHandle,
+    state: &AudioCacheState,
+    key: &str,
+    book_id: Option<&str>,
+) -> Result<Option<SynthesizedAudio>, String> {
+    with_cache(app, state, |cache, dir| {
+        let Some(entry) = cache.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        let Ok(bytes) = fs::read(dir.join(&entry.file)) else {
+            cache.entries.remove(key);
+            save(dir, cache)?;
+            return Ok(None);
+        };
+
+        let now = now();
+        let mut changed = now - entry.last_used >= TOUCH_PERSIST_SECONDS;
+        entry.last_used = now;
+        if let Some(book_id) = book_id {
+            if !entry.books.iter().any(|book| book == book_id) {
+                entry.books.push(book_id.to_string());
+                changed = true;
+            }
+        }
+        let audio = SynthesizedAudio {
+            bytes,
+            mime: entry.mime.clone(),
+        };
+        if changed {
+            save(dir, cache)?;
+        }
+        Ok(Some(audio))
+    })
+}
+
+/// Stores a freshly synthesized clip, evicting the least recently used
+/// clips if the cache grows past its limit.
+pub fn put(
+    app: &AppHandle,
+    state: &AudioCacheState,
+    key: &str,
+    provider: &str,
+    book_id: Option<&str>,
+    audio: &SynthesizedAudio,
+) -> Result<(), String> {
+    with_cache(app, state, |cache, dir| {
+        let file = format!("{}.{}", key, extension(&audio.mime));
+        storage::write_atomic(&dir.join(&file), &audio.bytes)?;
+        let now = now();
+        cache.entries.insert(
+            key.to_string(),
+            CacheEntry {
+                file,
+                mime: audio.mime.clone(),
+                bytes: audio.bytes.len() as u64,
+                provider: provider.to_string(),
+                created_at: now,
+                last_used: now,
+                books: book_id.map(|id| vec![id.to_string()]).unwrap_or_default(),
+            },
+        );
+        evict(dir, cache);
+        save(dir, cache)
+    })
+}
+
+pub fn stats(app: &AppHandle, state: &AudioCacheState) -> Result<AudioCacheStats, String> {
+    with_cache(app, state, |cache, _| Ok(summarize(cache)))
+}
+
+/// Sets the cache size limit in bytes; a limit of 0 turns caching off.
+pub fn set_limit(
+    app: &AppHandle,
+    state: &AudioCacheState,
+    max_bytes: u64,
+) -> Result<AudioCacheStats, String> {
+    with_cache(app, state, |cache, dir| {
+        cache.max_bytes = max_bytes;
+        evict(dir, cache);
+        save(dir, cache)?;
+        Ok(summarize(cache))
+    })
+}
+
+/// Deletes the clips played by `book_id`, keeping those other books still
+/// use, or the whole cache when no book is given.
+pub fn purge(
+    app: &AppHandle,
+    state: &AudioCacheState,
+    book_id: Option<&str>,
+) -> Result<AudioCacheStats, String> {
+    with_cache(app, state, |cache, dir| {
+        let mut removed = Vec::new();
+        for (key, entry) in cache.entries.iter_mut() {
+            match book_id {
+                Some(book_id) => {
+                    let before = entry.books.len();
+                    entry.books.retain(|book| book != book_id);
+                    if entry.books.is_empty() && before > 0 {
+                        removed.push(key.clone());
+                    }
+                }
+                None => removed.push(key.clone()),
+            }
+        }
+        for key in removed {
+            remove(dir, cache, &key);
+        }
+        save(dir, cache)?;
+        Ok(summarize(cache))
+    })
+}
+
+/// Books with at least one cached clip.
+pub fn books_with_audio(
+    app: &AppHandle,
+    state: &AudioCacheState,
+) -> Result<HashSet<String>, String> {
+    with_cache(app, state, |cache, _| {
+        Ok(cache
+            .entries
+            .values()
+            .flat_map(|entry| entry.books.iter().cloned())
+            .collect())
+    })
+}
+
+/// Forgets the loaded index so the next use rescans the cache directory,
+/// e.g. after a backup restored audio files into it.
+pub fn reload(state: &AudioCacheState) -> Result<(), String> {
+    let mut guard = state
+        .0
+        .lock()
+        .map_err(|_| "Audio cache lock poisoned".to_string())?;
+    *guard = None;
+    Ok(())
+}
+
+fn with_cache<T>(
+    app: &AppHandle,
+    state: &AudioCacheState,
+    f: impl FnOnce(&mut AudioCache, &Path) -> Result<T, String>,
+) -> Result<T, String> {
+    let dir = config::get_audio_cache_dir(app)?;
+    let mut guard = state
+        .0
+        .lock()
+        .map_err(|_| "Audio cache lock poisoned".to_string())?;
+    if guard.is_none() {
+        *guard = Some(load(&dir)?);
+    }
+    match guard.as_mut() {
+        Some(cache) => f(cache, &dir),
+        None => Err("Audio cache not loaded".to_string()),
+    }
+}
+
+/// Reads the index and reconciles it with the directory: entries whose file
+/// is gone are dropped and clips without an entry are adopted.
+fn load(dir: &Path) -> Result<AudioCache, String> {
+    let mut cache: AudioCache = storage::read_json(&dir.join(INDEX_FILE))?;
+    let listing =
+        fs::read_dir(dir).map_err(|error| format!("Failed to read {}: {error}", dir.display()))?;
+
+    let mut files = HashSet::new();
+    let mut changed = false;
+    for item in listing.flatten() {
+        let name = item.file_name().to_string_lossy().to_string();
+        let Some((key, ext)) = name
+            .split_once('.')
+            .map(|(key, ext)| (key.to_string(), ext.to_string()))
+        else {
+            continue;
+        };
+        if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) || ext.contains('.') {
+            continue;
+        }
+        files.insert(name.clone());
+        if cache.entries.contains_key(&key) {
+            continue;
+        }
+        let Ok(metadata) = item.metadata() else {
+            continue;
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_else(now);
+        cache.entries.insert(
+            key,
+            CacheEntry {
+                file: name,
+                mime: mime(&ext).to_string(),
+                bytes: metadata.len(),
+                provider: String::new(),
+                created_at: modified,
+                last_used: modified,
+                books: Vec::new(),
+            },
+        );
+        changed = true;
+    }
+
+    let before = cache.entries.len();
+    cache.entries.retain(|_, entry| files.contains(&entry.file));
+    changed |= cache.entries.len() != before;
+    if changed {
+        evict(dir, &mut cache);
+        save(dir, &cache)?;
+    }
+    Ok(cache)
+}
+
+fn save(dir: &Path, cache: &AudioCache) -> Result<(), String> {
+    storage::write_json(&dir.join(INDEX_FILE), cache)
+}
+
+fn evict(dir: &Path, cache: &mut AudioCache) {
+    let mut total: u64 = cache.entries.values().map(|entry| entry.bytes).sum();
+    if total <= cache.max_bytes {
+        return;
+    }
+    let mut by_age: Vec<(i64, String)> = cache
+        .entries
+        .iter()
+        .map(|(key, entry)| (entry.last_used, key.clone()))
+        .collect();
+    by_age.sort();
+    for (_, key) in by_age {
+        if total <= cache.max_bytes {
+            break;
+        }
+        total -= remove(dir, cache, &key);
+    }
+}
+
+/// Removes a clip and its file, returning the bytes freed.
+fn remove(dir: &Path, cache: &mut AudioCache, key: &str) -> u64 {
+    let Some(entry) = cache.entries.remove(key) else {
+        return 0;
+    };
+    let path = dir.join(&entry.file);
+    if let Err(error) = fs::remove_file(&path) {
+        if path.exists() {
+            println!(
+                "DEBUG WARNING: Failed to delete cached audio {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+    entry.bytes
+}
+
+fn summarize(cache: &AudioCache) -> AudioCacheStats {
+    let mut books: HashMap<&str, BookAudioUsage> = HashMap::new();
+    for entry in cache.entries.values() {
+        for book_id in &entry.books {
+            let usage = books.entry(book_id).or_insert_with(|| BookAudioUsage {
+                book_id: book_id.clone(),
+                bytes: 0,
+                clips: 0,
+            });
+            usage.bytes += entry.bytes;
+            usage.clips += 1;
+        }
+    }
+    let mut books: Vec<BookAudioUsage> = books.into_values().collect();
+    books.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| a.book_id.cmp(&b.book_id))
+    });
+
+    AudioCacheStats {
+        total_bytes: cache.entries.values().map(|entry| entry.bytes).sum(),
+        max_bytes: cache.max_bytes,
+        clips: cache.entries.len(),
+        books,
+    }
+}
+
+fn extension(mime: &str) -> &'static str {
+    match mime {
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        "audio/flac" => "flac",
+        "audio/ogg" => "ogg",
+        "audio/opus" => "opus",
+        "audio/aac" => "aac",
+        "audio/pcm" => "pcm",
+        _ => "mp3",
+    }
+}
+
+fn mime(extension: &str) -> &'static str {
+    match extension {
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "ogg" => "audio/ogg",
+        "opus" => "audio/opus",
+        "aac" => "audio/aac",
+        "pcm" => "audio/pcm",
+        _ => "audio/mpeg",
+    }
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
@@ -0,0 +1,577 @@
+use crate::annotations::{self, AnnotationStore};
+use crate::lexicon::LexiconStore;
+use crate::library;
+use crate::models::{
+    BackupImportOptions, BackupImportSummary, BackupManifest, BookActivity, BookEntry,
+    ChapterChange, ConflictResolution, DuplicateReason, LexiconEntry, OpdsServerSettings,
+    SavedVoice, Shelves,
+};
+use crate::normalize::NormalizationStore;
+use crate::positions::{self, PositionStore};
+use crate::reimport;
+use crate::shelves;
+use crate::sources;
+use crate::stats::StatsStore;
+use crate::storage;
+use crate::watcher::WatchStore;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+const BACKUP_FORMAT: &str = "rebook-backup";
+/// Version of the archive layout. Bump it when entries are added or change
+/// shape; restores refuse archives newer than this. The library document
+/// inside carries its own schema version and is migrated on restore.
+pub const BACKUP_VERSION: u64 = 2;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const LIBRARY_ENTRY: &str = "library.json";
+const POSITIONS_ENTRY: &str = "positions.json";
+const ANNOTATIONS_ENTRY: &str = "annotations.json";
+const SHELVES_ENTRY: &str = "shelves.json";
+const ACTIVITY_ENTRY: &str = "reading-activity.json";
+const VOICES_ENTRY: &str = "voices.json";
+const SETTINGS_ENTRY: &str = "settings.json";
+const LEXICON_ENTRY: &str = "lexicon.json";
+const STATS_ENTRY: &str = "stats.json";
+const NORMALIZATION_ENTRY: &str = "normalization.json";
+const WATCH_FOLDERS_ENTRY: &str = "watch-folders.json";
+const OPDS_SERVER_ENTRY: &str = "opds-server.json";
+const SOURCES_DIR: &str = "sources";
+const AUDIO_DIR: &str = "audio";
+
+/// Where each piece of backed-up data lives in the app data dir.
+pub struct DataPaths {
+    pub library: PathBuf,
+    pub positions: PathBuf,
+    pub annotations: PathBuf,
+    pub shelves: PathBuf,
+    pub activity: PathBuf,
+    pub lexicon: PathBuf,
+    pub stats: PathBuf,
+    pub normalization: PathBuf,
+    pub watch_folders: PathBuf,
+    pub opds_server: PathBuf,
+    pub sources_dir: PathBuf,
+    pub audio_dir: PathBuf,
+}
+
+/// State the frontend keeps in local storage rather than on disk.
+pub struct ClientState {
+    pub settings: Option<serde_json::Value>,
+    pub voices: Vec<SavedVoice>,
+}
+
+/// Writes the whole library into a zip archive at `destination`. The archive
+/// is written next to the destination first so a failed export never leaves
+/// a truncated backup behind.
+pub fn export(
+    paths: &DataPaths,
+    client: &ClientState,
+    include_audio: bool,
+    destination: &Path,
+) -> Result<BackupManifest, String> {
+    let books = library::load(&paths.library)?.books;
+    let manifest = BackupManifest {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: storage::now_iso(),
+        book_count: books.len(),
+        includes_audio: include_audio,
+    };
+
+    let tmp_path = storage::sibling_path(destination, "tmp");
+    let result = write_archive(&tmp_path, paths, client, &books, &manifest)
+        .and_then(|_| {
+            fs::rename(&tmp_path, destination).map_err(|error| {
+                format!("Failed to replace {}: {error}", destination.display())
+            })
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result.map(|_| manifest)
+}
+
+/// Merges a backup archive into the current library. Books already in the
+/// library are handled according to `options.conflict`; reading positions,
+/// annotations, activity, collections, pronunciations, reading sessions and
+/// normalization settings from the backup are merged with the existing ones
+/// rather than replacing them. Watched folders and the catalog server
+/// settings are only restored with `options.restore_settings`.
+pub fn import(
+    paths: &DataPaths,
+    source: &Path,
+    options: &BackupImportOptions,
+) -> Result<BackupImportSummary, String> {
+    let file = File::open(source)
+        .map_err(|error| format!("Failed to open {}: {error}", source.display()))?;
+    let mut zip = ZipArchive::new(file)
+        .map_err(|error| format!("Invalid backup archive: {error}"))?;
+
+    let manifest: BackupManifest = read_entry(&mut zip, MANIFEST_ENTRY)?
+        .filter(|manifest: &BackupManifest| manifest.format == BACKUP_FORMAT)
+        .ok_or_else(|| format!("{} is not a rebook backup", source.display()))?;
+    if manifest.version > BACKUP_VERSION {
+        return Err(format!(
+            "Backup version {} is newer than supported version {}",
+            manifest.version, BACKUP_VERSION
+        ));
+    }
+
+    let library_json = read_entry_bytes(&mut zip, LIBRARY_ENTRY)?
+        .ok_or_else(|| "Backup has no library".to_string())?;
+    let (incoming_books, skipped) = library::from_json(&library_json, LIBRARY_ENTRY)?;
+    let mut incoming_positions: PositionStore =
+        read_entry(&mut zip, POSITIONS_ENTRY)?.unwrap_or_default();
+    let mut incoming_annotations: AnnotationStore =
+        read_entry(&mut zip, ANNOTATIONS_ENTRY)?.unwrap_or_default();
+    let mut incoming_activity: HashMap<String, BookActivity> =
+        read_entry(&mut zip, ACTIVITY_ENTRY)?.unwrap_or_default();
+    let incoming_shelves: Shelves = read_entry(&mut zip, SHELVES_ENTRY)?.unwrap_or_default();
+    let incoming_lexicon: LexiconStore = read_entry(&mut zip, LEXICON_ENTRY)?.unwrap_or_default();
+    let incoming_stats: StatsStore = read_entry(&mut zip, STATS_ENTRY)?.unwrap_or_default();
+    let incoming_normalization: NormalizationStore =
+        read_entry(&mut zip, NORMALIZATION_ENTRY)?.unwrap_or_default();
+
+    let mut books = library::load(&paths.library)?.books;
+    let mut position_store: PositionStore = storage::read_json(&paths.positions)?;
+    let mut annotation_store: AnnotationStore = storage::read_json(&paths.annotations)?;
+    let mut activity: HashMap<String, BookActivity> = storage::read_json(&paths.activity)?;
+    let mut shelf_store: Shelves = storage::read_json(&paths.shelves)?;
+    let mut lexicon_store: LexiconStore = storage::read_json(&paths.lexicon)?;
+    let mut stats_store: StatsStore = storage::read_json(&paths.stats)?;
+    let mut normalization_store: NormalizationStore = storage::read_json(&paths.normalization)?;
+
+    let mut summary = BackupImportSummary {
+        skipped,
+        ..BackupImportSummary::default()
+    };
+    let mut id_map: HashMap<String, String> = HashMap::new();
+
+    for incoming in incoming_books {
+        let backup_id = incoming.id.clone();
+        let existing = find_existing(&books, &incoming);
+        // Chapter mapping from the backup's edition to the one that stays in
+        // the library, when the two may differ.
+        let mut incoming_changes: Option<Vec<ChapterChange>> = None;
+
+        let index = match (existing, options.conflict) {
+            (Some(index), ConflictResolution::KeepExisting) => {
+                let local = &mut books[index];
+                incoming_changes = Some(reimport::diff_chapters(
+                    &incoming.chapters,
+                    &local.chapters,
+                ));
+                local.tags = merged_tags(&local.tags, &incoming.tags);
+                summary.books_merged += 1;
+                index
+            }
+            (Some(index), ConflictResolution::UseBackup) => {
+                let local = &mut books[index];
+                let changes = reimport::diff_chapters(&local.chapters, &incoming.chapters);
+                let tags = merged_tags(&local.tags, &incoming.tags);
+                *local = BookEntry {
+                    id: local.id.clone(),
+                    imported_at: local.imported_at.clone(),
+                    tags,
+                    ..incoming
+                };
+                positions::remap(&mut position_store, local, &changes);
+                annotations::remap(&mut annotation_store, local, &changes);
+                summary.books_replaced += 1;
+                index
+            }
+            (_, _) => {
+                let mut entry = incoming;
+                entry.id = unique_id(&books, &entry.id);
+                books.push(entry);
+                summary.books_added += 1;
+                books.len() - 1
+            }
+        };
+
+        let book = &books[index];
+        id_map.insert(backup_id.clone(), book.id.clone());
+
+        if let Some(mut saved) = incoming_positions.remove(&backup_id) {
+            if let Some(changes) = &incoming_changes {
+                for record in saved.current.iter_mut().chain(saved.history.iter_mut()) {
+                    positions::remap_locator(&mut record.locator, book, changes);
+                }
+            }
+            positions::merge(&mut position_store, &book.id, saved);
+        }
+        if let Some(mut saved) = incoming_annotations.remove(&backup_id) {
+            if let Some(changes) = &incoming_changes {
+                for annotation in saved.iter_mut() {
+                    positions::remap_locator(&mut annotation.locator, book, changes);
+                }
+            }
+            summary.annotations_added += annotations::merge(&mut annotation_store, &book.id, saved);
+        }
+        if let Some(saved) = incoming_activity.remove(&backup_id) {
+            merge_activity(&mut activity, &book.id, saved);
+        }
+    }
+
+    merge_shelves(&mut shelf_store, incoming_shelves, &id_map);
+    merge_lexicon(&mut lexicon_store, incoming_lexicon, &id_map);
+    merge_stats(&mut stats_store, incoming_stats, &id_map);
+    for (backup_id, settings) in incoming_normalization {
+        let book_id = id_map.get(&backup_id).cloned().unwrap_or(backup_id);
+        normalization_store.entry(book_id).or_insert(settings);
+    }
+    summary.audio_files_restored = restore_files(&mut zip, paths)?;
+    summary.voices = read_entry(&mut zip, VOICES_ENTRY)?.unwrap_or_default();
+    if options.restore_settings {
+        summary.settings = read_entry(&mut zip, SETTINGS_ENTRY)?;
+        if let Some(incoming) = read_entry::<WatchStore, _>(&mut zip, WATCH_FOLDERS_ENTRY)? {
+            let mut watch_store: WatchStore = storage::read_json(&paths.watch_folders)?;
+            for folder in incoming.folders {
+                if !watch_store
+                    .folders
+                    .iter()
+                    .any(|existing| existing.path == folder.path)
+                {
+                    watch_store.folders.push(folder);
+                }
+            }
+            storage::write_json(&paths.watch_folders, &watch_store)?;
+        }
+        if let Some(settings) = read_entry::<OpdsServerSettings, _>(&mut zip, OPDS_SERVER_ENTRY)? {
+            storage::write_json(&paths.opds_server, &settings)?;
+        }
+    }
+
+    storage::write_json(&paths.positions, &position_store)?;
+    storage::write_json(&paths.annotations, &annotation_store)?;
+    storage::write_json(&paths.activity, &activity)?;
+    storage::write_json(&paths.shelves, &shelf_store)?;
+    storage::write_json(&paths.lexicon, &lexicon_store)?;
+    storage::write_json(&paths.stats, &stats_store)?;
+    storage::write_json(&paths.normalization, &normalization_store)?;
+    library::save(&paths.library, &books)?;
+    summary.library = books;
+    Ok(summary)
+}
+
+fn write_archive(
+    path: &Path,
+    paths: &DataPaths,
+    client: &ClientState,
+    books: &[BookEntry],
+    manifest: &BackupManifest,
+) -> Result<(), String> {
+    let file = File::create(path)
+        .map_err(|error| format!("Failed to create {}: {error}", path.display()))?;
+    let mut zip = ZipWriter::new(file);
+
+    add_json(&mut zip, MANIFEST_ENTRY, manifest)?;
+    add_bytes(&mut zip, LIBRARY_ENTRY, &library::to_json(books)?)?;
+    add_json(
+        &mut zip,
+        POSITIONS_ENTRY,
+        &storage::read_json::<PositionStore>(&paths.positions)?,
+    )?;
+    add_json(
+        &mut zip,
+        ANNOTATIONS_ENTRY,
+        &storage::read_json::<AnnotationStore>(&paths.annotations)?,
+    )?;
+    add_json(
+        &mut zip,
+        ACTIVITY_ENTRY,
+        &storage::read_json::<HashMap<String, BookActivity>>(&paths.activity)?,
+    )?;
+    add_json(
+        &mut zip,
+        SHELVES_ENTRY,
+        &storage::read_json::<Shelves>(&paths.shelves)?,
+    )?;
+    add_json(
+        &mut zip,
+        LEXICON_ENTRY,
+        &storage::read_json::<LexiconStore>(&paths.lexicon)?,
+    )?;
+    add_json(
+        &mut zip,
+        STATS_ENTRY,
+        &storage::read_json::<StatsStore>(&paths.stats)?,
+    )?;
+    add_json(
+        &mut zip,
+        NORMALIZATION_ENTRY,
+        &storage::read_json::<NormalizationStore>(&paths.normalization)?,
+    )?;
+    add_json(
+        &mut zip,
+        WATCH_FOLDERS_ENTRY,
+        &storage::read_json::<WatchStore>(&paths.watch_folders)?,
+    )?;
+    add_json(
+        &mut zip,
+        OPDS_SERVER_ENTRY,
+        &storage::read_json::<OpdsServerSettings>(&paths.opds_server)?,
+    )?;
+    add_json(&mut zip, VOICES_ENTRY, &client.voices)?;
+    if let Some(settings) = &client.settings {
+        add_json(&mut zip, SETTINGS_ENTRY, settings)?;
+    }
+
+    let mut added_sources = HashSet::new();
+    for hash in books.iter().filter_map(|book| book.content_hash.as_deref()) {
+        let source = sources::path_for(&paths.sources_dir, hash);
+        if added_sources.insert(hash) && source.exists() {
+            add_file(&mut zip, &format!("{SOURCES_DIR}/{hash}.epub"), &source)?;
+        }
+    }
+    if manifest.includes_audio {
+        for file in storage::list_files(&paths.audio_dir)? {
+            let Ok(relative) = file.strip_prefix(&paths.audio_dir) else {
+                continue;
+            };
+            let parts: Vec<String> = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy().to_string())
+                .collect();
+            add_file(&mut zip, &format!("{AUDIO_DIR}/{}", parts.join("/")), &file)?;
+        }
+    }
+
+    let file = zip
+        .finish()
+        .map_err(|error| format!("Failed to finish backup archive: {error}"))?;
+    file.sync_all()
+        .map_err(|error| format!("Failed to sync {}: {error}", path.display()))
+}
+
+fn add_json<T: Serialize + ?Sized>(
+    zip: &mut ZipWriter<File>,
+    name: &str,
+    value: &T,
+) -> Result<(), String> {
+    let json = serde_json::to_vec(value)
+        .map_err(|error| format!("Failed to serialize {name}: {error}"))?;
+    add_bytes(zip, name, &json)
+}
+
+fn add_bytes(zip: &mut ZipWriter<File>, name: &str, bytes: &[u8]) -> Result<(), String> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(name, options)
+        .map_err(|error| format!("Failed to add {name} to backup: {error}"))?;
+    zip.write_all(bytes)
+        .map_err(|error| format!("Failed to write {name} to backup: {error}"))
+}
+
+/// EPUBs and audio are already compressed, so they are stored as-is.
+fn add_file(zip: &mut ZipWriter<File>, name: &str, path: &Path) -> Result<(), String> {
+    let mut file = File::open(path)
+        .map_err(|error| format!("Failed to open {}: {error}", path.display()))?;
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+    zip.start_file(name, options)
+        .map_err(|error| format!("Failed to add {name} to backup: {error}"))?;
+    io::copy(&mut file, zip)
+        .map_err(|error| format!("Failed to write {name} to backup: {error}"))?;
+    Ok(())
+}
+
+fn read_entry_bytes<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Option<Vec<u8>>, String> {
+    let mut file = match zip.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(error) => return Err(format!("Failed to read {name} from backup: {error}")),
+    };
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)
+        .map_err(|error| format!("Failed to read {name} from backup: {error}"))?;
+    Ok(Some(bytes))
+}
+
+fn read_entry<T: DeserializeOwned, R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Option<T>, String> {
+    read_entry_bytes(zip, name)?
+        .map(|bytes| {
+            serde_json::from_slice(&bytes)
+                .map_err(|error| format!("Failed to parse {name} from backup: {error}"))
+        })
+        .transpose()
+}
+
+/// Copies source EPUBs and cached audio out of the archive, leaving files
+/// that already exist untouched. Returns how many audio files were restored.
+fn restore_files<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    paths: &DataPaths,
+) -> Result<usize, String> {
+    let mut audio_restored = 0;
+    for index in 0..zip.len() {
+        let mut file = zip
+            .by_index(index)
+            .map_err(|error| format!("Failed to read backup entry: {error}"))?;
+        if file.is_dir() {
+            continue;
+        }
+        let Some(relative) = file.enclosed_name().map(Path::to_path_buf) else {
+            continue;
+        };
+        let (target, is_audio) = if let Ok(rest) = relative.strip_prefix(SOURCES_DIR) {
+            (paths.sources_dir.join(rest), false)
+        } else if let Ok(rest) = relative.strip_prefix(AUDIO_DIR) {
+            (paths.audio_dir.join(rest), true)
+        } else {
+            continue;
+        };
+        if target.exists() {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|error| format!("Failed to create {}: {error}", parent.display()))?;
+        }
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|error| format!("Failed to read {} from backup: {error}", file.name()))?;
+        storage::write_atomic(&target, &bytes)?;
+        if is_audio {
+            audio_restored += 1;
+        }
+    }
+    Ok(audio_restored)
+}
+
+/// The same file wins over the same identifier, which may be another edition.
+fn find_existing(books: &[BookEntry], incoming: &BookEntry) -> Option<usize> {
+    let matches = reimport::find_duplicates(
+        books,
+        incoming.content_hash.as_deref(),
+        incoming.identifier.as_deref(),
+    );
+    let best = matches
+        .iter()
+        .find(|candidate| matches!(candidate.reason, DuplicateReason::SameFile))
+        .or(matches.first())?;
+    books.iter().position(|book| book.id == best.book_id)
+}
+
+fn unique_id(books: &[BookEntry], id: &str) -> String {
+    let mut candidate = id.to_string();
+    let mut copy = 1;
+    while books.iter().any(|book| book.id == candidate) {
+        copy += 1;
+        candidate = format!("{}-{}", id, copy);
+    }
+    candidate
+}
+
+fn merged_tags(existing: &[String], incoming: &[String]) -> Vec<String> {
+    shelves::normalize_tags(existing.iter().chain(incoming).cloned().collect())
+}
+
+/// Adds the backup's pronunciations that the current lexicon has no entry
+/// for, by id or by word.
+fn merge_lexicon(
+    store: &mut LexiconStore,
+    incoming: LexiconStore,
+    id_map: &HashMap<String, String>,
+) {
+    merge_lexicon_entries(&mut store.global, incoming.global);
+    for (backup_id, entries) in incoming.books {
+        let book_id = id_map.get(&backup_id).cloned().unwrap_or(backup_id);
+        merge_lexicon_entries(store.books.entry(book_id).or_default(), entries);
+    }
+}
+
+fn merge_lexicon_entries(entries: &mut Vec<LexiconEntry>, incoming: Vec<LexiconEntry>) {
+    for entry in incoming {
+        let known = entries.iter().any(|existing| {
+            existing.id == entry.id
+                || existing.grapheme.to_lowercase() == entry.grapheme.to_lowercase()
+        });
+        if !known {
+            entries.push(entry);
+        }
+    }
+}
+
+/// Adds the backup's reading sessions that are not stored yet.
+fn merge_stats(store: &mut StatsStore, incoming: StatsStore, id_map: &HashMap<String, String>) {
+    let known: HashSet<String> = store
+        .sessions
+        .iter()
+        .map(|session| session.id.clone())
+        .collect();
+    for mut session in incoming.sessions {
+        if known.contains(&session.id) {
+            continue;
+        }
+        if let Some(book_id) = id_map.get(&session.book_id) {
+            session.book_id = book_id.clone();
+        }
+        store.sessions.push(session);
+    }
+    store
+        .sessions
+        .sort_by(|a, b| a.started_at.cmp(&b.started_at));
+}
+
+fn merge_activity(
+    activity: &mut HashMap<String, BookActivity>,
+    book_id: &str,
+    incoming: BookActivity,
+) {
+    let keep_existing = activity
+        .get(book_id)
+        .is_some_and(|existing| existing.last_read_at >= incoming.last_read_at);
+    if !keep_existing {
+        activity.insert(book_id.to_string(), incoming);
+    }
+}
+
+/// Collections with the same id gain the backup's books; everything else is
+/// added. Book ids are rewritten to where each backed-up book ended up.
+fn merge_shelves(shelves: &mut Shelves, incoming: Shelves, id_map: &HashMap<String, String>) {
+    for mut collection in incoming.collections {
+        collection.book_ids = collection
+            .book_ids
+            .iter()
+            .filter_map(|id| id_map.get(id).cloned())
+            .collect();
+        match shelves
+            .collections
+            .iter_mut()
+            .find(|existing| existing.id == collection.id)
+        {
+            Some(existing) => {
+                for id in collection.book_ids {
+                    if !existing.book_ids.contains(&id) {
+                        existing.book_ids.push(id);
+                    }
+                }
+            }
+            None => shelves.collections.push(collection),
+        }
+    }
+    for shelf in incoming.smart_shelves {
+        if !shelves
+            .smart_shelves
+            .iter()
+            .any(|existing| existing.id == shelf.id)
+        {
+            shelves.smart_shelves.push(shelf);
+        }
+    }
+}
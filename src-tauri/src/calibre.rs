@@ -0,0 +1,333 @@
+use crate::config;
+use crate::covers;
+use crate::epub;
+use crate::importer::{self, ImportOutcome};
+use crate::library;
+use crate::models::{Book, BookEntry, ImportProgress, LibraryImportSummary};
+use crate::search;
+use crate::shelves;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rusqlite::{Connection, OpenFlags};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+
+pub const IMPORT_PROGRESS_EVENT: &str = "calibre-import-progress";
+
+/// Formats the EPUB parser can read, best first. KEPUB is an EPUB with extra
+/// markup for Kobo readers.
+const SUPPORTED_FORMATS: &[&str] = &["EPUB", "KEPUB"];
+
+/// Calibre stores ISO 639-2 codes; books parsed from EPUBs mostly carry the
+/// two-letter form, which is what shelves and the search stemmers expect.
+const LANGUAGE_CODES: &[(&str, &str)] = &[
+    ("ara", "ar"),
+    ("dan", "da"),
+    ("deu", "de"),
+    ("ell", "el"),
+    ("eng", "en"),
+    ("fin", "fi"),
+    ("fra", "fr"),
+    ("hun", "hu"),
+    ("ita", "it"),
+    ("nld", "nl"),
+    ("nor", "no"),
+    ("por", "pt"),
+    ("ron", "ro"),
+    ("rus", "ru"),
+    ("spa", "es"),
+    ("swe", "sv"),
+    ("tam", "ta"),
+    ("tur", "tr"),
+];
+
+#[derive(Default)]
+struct CalibreBook {
+    id: i64,
+    title: String,
+    uuid: Option<String>,
+    author_sort: Option<String>,
+    folder: PathBuf,
+    series_index: Option<f64>,
+    has_cover: bool,
+    authors: Vec<String>,
+    series: Option<String>,
+    tags: Vec<String>,
+    identifiers: Vec<(String, String)>,
+    comments: Option<String>,
+    languages: Vec<String>,
+    /// Format name (e.g. "EPUB") and file path.
+    formats: Vec<(String, PathBuf)>,
+}
+
+/// Imports every book of the Calibre library at `root`. Books are matched
+/// against the library like any other import, then take Calibre's
+/// metadata, which users curate there by hand. Progress is reported with
+/// `calibre-import-progress` events.
+///
+/// The books are read first; the library is only loaded once they all are,
+/// under the library lock, so changes made during a long import are kept.
+pub fn import_library(app: &AppHandle, root: &Path) -> Result<LibraryImportSummary, String> {
+    let calibre_books = read_library(root)?;
+    let mut summary = LibraryImportSummary::default();
+    let mut parsed = Vec::new();
+
+    let total = calibre_books.len();
+    for (done, calibre_book) in calibre_books.iter().enumerate() {
+        emit_progress(app, done, total, &calibre_book.title);
+        match read_book(app, calibre_book) {
+            Ok(book) => parsed.push((calibre_book, book)),
+            Err(error) => summary
+                .skipped
+                .push(format!("\"{}\": {}", calibre_book.title, error)),
+        }
+    }
+    emit_progress(app, total, total, "");
+
+    let library_path = config::get_library_path(app)?;
+    let guard = library::lock(app)?;
+    let mut books = library::load(&library_path)?.books;
+    let mut updates = Vec::new();
+    for (calibre_book, book) in parsed {
+        match merge_book(app, &mut books, calibre_book, book) {
+            ImportOutcome::Added(_) => summary.added += 1,
+            ImportOutcome::Updated(update) => {
+                summary.updated += 1;
+                updates.push(update);
+            }
+            ImportOutcome::Unchanged(_) => summary.unchanged += 1,
+        }
+    }
+    library::save(&library_path, &books)?;
+    drop(guard);
+
+    search::update_index(app, &books);
+    importer::remap_stores(app, &updates.iter().collect::<Vec<_>>())?;
+    summary.library = books;
+    Ok(summary)
+}
+
+fn read_book(app: &AppHandle, calibre_book: &CalibreBook) -> Result<Book, String> {
+    let path = best_format(calibre_book)?;
+    let bytes =
+        fs::read(path).map_err(|error| format!("Failed to read {}: {error}", path.display()))?;
+    let book = epub::parse_epub(bytes.clone())?;
+    importer::keep_source(app, &book, &bytes);
+    Ok(book)
+}
+
+fn merge_book(
+    app: &AppHandle,
+    books: &mut Vec<BookEntry>,
+    calibre_book: &CalibreBook,
+    book: Book,
+) -> ImportOutcome {
+    let outcome = importer::merge_book(books, book, None);
+    let id = &outcome.book().id;
+    if let Some(entry) = books.iter_mut().find(|entry| &entry.id == id) {
+        apply_metadata(entry, calibre_book);
+        covers::keep_thumbnails(app, entry.cover_base64.as_deref());
+    }
+    outcome
+}
+
+fn best_format(book: &CalibreBook) -> Result<&Path, String> {
+    SUPPORTED_FORMATS
+        .iter()
+        .find_map(|wanted| {
+            book.formats
+                .iter()
+                .find(|(format, path)| format.eq_ignore_ascii_case(wanted) && path.exists())
+                .map(|(_, path)| path.as_path())
+        })
+        .ok_or_else(|| {
+            if book.formats.is_empty() {
+                "no book files".to_string()
+            } else {
+                let available: Vec<&str> =
+                    book.formats.iter().map(|(format, _)| format.as_str()).collect();
+                format!("no EPUB available (has {})", available.join(", "))
+            }
+        })
+}
+
+fn apply_metadata(entry: &mut BookEntry, book: &CalibreBook) {
+    if !book.title.trim().is_empty() {
+        entry.title = book.title.clone();
+    }
+    if !book.authors.is_empty() {
+        entry.author = Some(book.authors.join(" & "));
+        entry.author_sort = book.author_sort.clone();
+    }
+    // Calibre gives every book a series index, even without a series.
+    if book.series.is_some() {
+        entry.series = book.series.clone();
+        entry.series_index = book.series_index;
+    }
+    entry.tags = shelves::normalize_tags(entry.tags.iter().chain(&book.tags).cloned().collect());
+    if let Some(description) = book.comments.as_deref().and_then(epub::description_text) {
+        entry.description = Some(description);
+    }
+    if entry.language.is_none() {
+        entry.language = book.languages.first().map(|code| language_tag(code));
+    }
+    if entry.identifier.is_none() {
+        entry.identifier = preferred_identifier(book);
+    }
+    if let Some(cover) = read_cover(book) {
+        entry.cover_base64 = Some(cover);
+        entry.cover_mime = Some("image/jpeg".to_string());
+    }
+}
+
+fn preferred_identifier(book: &CalibreBook) -> Option<String> {
+    book.identifiers
+        .iter()
+        .find(|(kind, _)| kind.eq_ignore_ascii_case("isbn"))
+        .map(|(_, value)| value.as_str())
+        .or(book.uuid.as_deref())
+        .and_then(epub::normalize_identifier)
+}
+
+fn language_tag(code: &str) -> String {
+    LANGUAGE_CODES
+        .iter()
+        .find(|(calibre, _)| calibre.eq_ignore_ascii_case(code))
+        .map_or_else(|| code.to_string(), |(_, tag)| tag.to_string())
+}
+
+fn read_cover(book: &CalibreBook) -> Option<String> {
+    if !book.has_cover {
+        return None;
+    }
+    fs::read(book.folder.join("cover.jpg"))
+        .ok()
+        .map(|bytes| STANDARD.encode(bytes))
+}
+
+fn emit_progress(app: &AppHandle, done: usize, total: usize, title: &str) {
+    let progress = ImportProgress {
+        done,
+        total,
+        title: title.to_string(),
+    };
+    if let Err(error) = app.emit(IMPORT_PROGRESS_EVENT, progress) {
+        println!("DEBUG WARNING: Failed to emit {}: {}", IMPORT_PROGRESS_EVENT, error);
+    }
+}
+
+fn read_library(root: &Path) -> Result<Vec<CalibreBook>, String> {
+    let db_path = root.join("metadata.db");
+    if !db_path.exists() {
+        return Err(format!(
+            "{} is not a Calibre library (metadata.db not found)",
+            root.display()
+        ));
+    }
+    // Read-only, so a running Calibre keeps its lock on the database.
+    let connection = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|error| format!("Failed to open {}: {error}", db_path.display()))?;
+
+    let mut books = {
+        let mut statement = connection
+            .prepare("SELECT id, title, path, series_index, has_cover, uuid, author_sort FROM books ORDER BY id")
+            .map_err(db_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(CalibreBook {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    folder: root.join(row.get::<_, String>(2)?),
+                    series_index: row.get(3)?,
+                    has_cover: row.get::<_, Option<bool>>(4)?.unwrap_or(false),
+                    uuid: row.get(5)?,
+                    author_sort: row.get(6)?,
+                    ..CalibreBook::default()
+                })
+            })
+            .map_err(db_error)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(db_error)?
+    };
+
+    for book in books.iter_mut() {
+        book.authors = strings(
+            &connection,
+            "SELECT authors.name FROM authors
+             JOIN books_authors_link ON books_authors_link.author = authors.id
+             WHERE books_authors_link.book = ?1 ORDER BY books_authors_link.id",
+            book.id,
+        )?;
+        book.series = strings(
+            &connection,
+            "SELECT series.name FROM series
+             JOIN books_series_link ON books_series_link.series = series.id
+             WHERE books_series_link.book = ?1",
+            book.id,
+        )?
+        .into_iter()
+        .next();
+        book.tags = strings(
+            &connection,
+            "SELECT tags.name FROM tags
+             JOIN books_tags_link ON books_tags_link.tag = tags.id
+             WHERE books_tags_link.book = ?1 ORDER BY tags.name",
+            book.id,
+        )?;
+        book.languages = strings(
+            &connection,
+            "SELECT languages.lang_code FROM languages
+             JOIN books_languages_link ON books_languages_link.lang_code = languages.id
+             WHERE books_languages_link.book = ?1 ORDER BY books_languages_link.item_order",
+            book.id,
+        )?;
+        book.comments = strings(
+            &connection,
+            "SELECT text FROM comments WHERE book = ?1",
+            book.id,
+        )?
+        .into_iter()
+        .next();
+        book.identifiers = pairs(
+            &connection,
+            "SELECT type, val FROM identifiers WHERE book = ?1",
+            book.id,
+        )?;
+        book.formats = pairs(
+            &connection,
+            "SELECT format, name FROM data WHERE book = ?1",
+            book.id,
+        )?
+        .into_iter()
+        .map(|(format, name)| {
+            let file = format!("{}.{}", name, format.to_lowercase());
+            (format, book.folder.join(file))
+        })
+        .collect();
+    }
+    Ok(books)
+}
+
+fn strings(connection: &Connection, sql: &str, book_id: i64) -> Result<Vec<String>, String> {
+    let mut statement = connection.prepare(sql).map_err(db_error)?;
+    let rows = statement
+        .query_map([book_id], |row| row.get::<_, String>(0))
+        .map_err(db_error)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(db_error)
+}
+
+fn pairs(
+    connection: &Connection,
+    sql: &str,
+    book_id: i64,
+) -> Result<Vec<(String, String)>, String> {
+    let mut statement = connection.prepare(sql).map_err(db_error)?;
+    let rows = statement
+        .query_map([book_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(db_error)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(db_error)
+}
+
+fn db_error(error: rusqlite::Error) -> String {
+    format!("Failed to read Calibre library: {error}")
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Lets the frontend stop work it started, such as a synthesis it no longer
+/// needs after the reader paused or seeked.
+#[derive(Clone, Default)]
+pub struct Cancellation(Arc<CancellationInner>);
+
+#[derive(Default)]
+struct CancellationInner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once `cancel` is called.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Runs `future` to completion, or drops it when cancelled and returns
+    /// `None`. Dropping a request future aborts the request.
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => None,
+            output = future => Some(output),
+        }
+    }
+}
+
+/// Running syntheses by the id the frontend gave them, kept in Tauri state.
+#[derive(Default)]
+pub struct SynthesisJobs(Mutex<HashMap<String, Cancellation>>);
+
+impl SynthesisJobs {
+    /// Registers a job, cancelling any still running under the same id.
+    pub fn start(&self, id: &str) -> Result<Cancellation, String> {
+        let cancellation = Cancellation::default();
+        let previous = self.lock()?.insert(id.to_string(), cancellation.clone());
+        if let Some(previous) = previous {
+            previous.cancel();
+        }
+        Ok(cancellation)
+    }
+
+    /// Forgets a job once it is done, unless a newer one took its id.
+    pub fn finish(&self, id: &str, cancellation: &Cancellation) {
+        if let Ok(mut jobs) = self.lock() {
+            if jobs
+                .get(id)
+                .is_some_and(|current| Arc::ptr_eq(&current.0, &cancellation.0))
+            {
+                jobs.remove(id);
+            }
+        }
+    }
+
+    /// Cancels the job `id`, or every job without one. Returns how many were
+    /// running.
+    pub fn cancel(&self, id: Option<&str>) -> Result<usize, String> {
+        let mut jobs = self.lock()?;
+        let cancelled: Vec<Cancellation> = match id {
+            Some(id) => jobs.remove(id).into_iter().collect(),
+            None => jobs.drain().map(|(_, cancellation)| cancellation).collect(),
+        };
+        for cancellation in &cancelled {
+            cancellation.cancel();
+        }
+        Ok(cancelled.len())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, Cancellation>>, String> {
+        self.0
+            .lock()
+            .map_err(|_| "Synthesis jobs lock poisoned".to_string())
+    }
+}
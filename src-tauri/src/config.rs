@@ -9,6 +9,71 @@ pub fn load_env() {
 }
 
 pub fn get_library_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "library.json")
+}
+
+pub fn get_search_index_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "search-index.json")
+}
+
+pub fn get_shelves_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "shelves.json")
+}
+
+pub fn get_activity_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "reading-activity.json")
+}
+
+pub fn get_stats_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "stats.json")
+}
+
+pub fn get_normalization_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "normalization.json")
+}
+
+pub fn get_lexicon_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "lexicon.json")
+}
+
+pub fn get_positions_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "positions.json")
+}
+
+pub fn get_annotations_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "annotations.json")
+}
+
+pub fn get_watch_folders_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "watch-folders.json")
+}
+
+pub fn get_opds_server_path(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_file_path(app, "opds-server.json")
+}
+
+pub fn get_sources_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_dir_path(app, "sources")
+}
+
+pub fn get_covers_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_dir_path(app, "covers")
+}
+
+pub fn get_audio_cache_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    get_data_dir_path(app, "audio-cache")
+}
+
+fn get_data_dir_path(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    let path = get_data_file_path(app, name)?;
+    if !path.exists() {
+        fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create {} dir: {}", name, e))?;
+    }
+    Ok(path)
+}
+
+fn get_data_file_path(app: &AppHandle, name: &str) -> Result<PathBuf, String> {
     let mut path = app.path().app_data_dir()
         .map_err(|e| format!("Failed to get app data dir: {}", e))?;
     
@@ -17,7 +82,7 @@ pub fn get_library_path(app: &AppHandle) -> Result<PathBuf, String> {
             .map_err(|e| format!("Failed to create app data dir: {}", e))?;
     }
     
-    path.push("library.json");
+    path.push(name);
     Ok(path)
 }
 
@@ -32,3 +97,45 @@ pub fn minimax_api_key() -> Option<String> {
 pub fn elevenlabs_api_key() -> Option<String> {
     env::var("REBOOK_ELEVENLABS_API_KEY").ok()
 }
+
+/// Overrides the MiniMax API origin, e.g. to point at a mock server.
+pub fn minimax_api_base() -> String {
+    env::var("REBOOK_MINIMAX_API_BASE")
+        .ok()
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "https://api.minimaxi.com".to_string())
+}
+
+/// Overrides the ElevenLabs API origin, e.g. to point at a mock server.
+pub fn elevenlabs_api_base() -> String {
+    env::var("REBOOK_ELEVENLABS_API_BASE")
+        .ok()
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "https://api.elevenlabs.io".to_string())
+}
+
+pub fn http_proxy() -> Option<String> {
+    env::var("REBOOK_HTTP_PROXY").ok().filter(|value| !value.is_empty())
+}
+
+/// A numeric `REBOOK_HTTP_*` setting, such as `REBOOK_HTTP_MAX_RETRIES`.
+pub fn http_setting(name: &str) -> Option<u64> {
+    let key = format!("REBOOK_HTTP_{}", name);
+    let value = env::var(&key).ok()?;
+    match value.trim().parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            println!("DEBUG WARNING: Ignoring invalid {}: {}", key, value);
+            None
+        }
+    }
+}
+
+/// A `REBOOK_LOCAL_TTS_*` setting for the local speech engine, such as
+/// `REBOOK_LOCAL_TTS_COMMAND`.
+pub fn local_tts_setting(name: &str) -> Option<String> {
+    env::var(format!("REBOOK_LOCAL_TTS_{}", name))
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
@@ -0,0 +1,299 @@
+use crate::config;
+use crate::epub;
+use crate::library;
+use crate::opds;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tauri::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use tauri::http::{Request, Response, StatusCode};
+use tauri::{AppHandle, Manager};
+
+/// Covers are served as `cover://localhost/<book id>/<size>`.
+pub const COVER_SCHEME: &str = "cover";
+
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Clone, Copy)]
+pub enum CoverSize {
+    /// Library grid cards.
+    Grid,
+    /// Book details and e-reader catalogs.
+    Detail,
+}
+
+impl CoverSize {
+    const ALL: [CoverSize; 2] = [CoverSize::Grid, CoverSize::Detail];
+
+    fn name(self) -> &'static str {
+        match self {
+            CoverSize::Grid => "grid",
+            CoverSize::Detail => "detail",
+        }
+    }
+
+    fn bounds(self) -> (u32, u32) {
+        match self {
+            CoverSize::Grid => (240, 360),
+            CoverSize::Detail => (600, 900),
+        }
+    }
+
+    fn parse(name: &str) -> Option<CoverSize> {
+        CoverSize::ALL.into_iter().find(|size| size.name() == name)
+    }
+}
+
+/// Which cover each book has, so requests don't reload the library. Rebuilt
+/// whenever `library.json` changes on disk.
+#[derive(Default)]
+pub struct CoverState(Mutex<CoverIndex>);
+
+#[derive(Default)]
+struct CoverIndex {
+    library_modified: Option<SystemTime>,
+    books: HashMap<String, CoverInfo>,
+}
+
+struct CoverInfo {
+    /// Hash of the cover, naming its thumbnails; `None` without a cover.
+    key: Option<String>,
+    title: String,
+    author: Option<String>,
+}
+
+/// Generates the thumbnails of a newly imported cover. Thumbnails are
+/// otherwise made on first use, so failing here never fails an import.
+pub fn keep_thumbnails(app: &AppHandle, cover_base64: Option<&str>) {
+    let Some(cover) = cover_base64 else {
+        return;
+    };
+    let result = config::get_covers_dir(app).and_then(|dir| generate(&dir, cover));
+    if let Err(error) = result {
+        println!(
+            "DEBUG WARNING: Failed to create cover thumbnails: {}",
+            error
+        );
+    }
+}
+
+/// Returns a book's cover at `size` with its MIME type, or a generated
+/// placeholder when the book has no usable cover.
+pub fn thumbnail(
+    app: &AppHandle,
+    book_id: &str,
+    size: CoverSize,
+) -> Result<(Vec<u8>, &'static str), String> {
+    let (path, title, author) = thumbnail_source(app, book_id, size)?;
+    if let Some(bytes) = path.and_then(|path| fs::read(path).ok()) {
+        return Ok((bytes, "image/jpeg"));
+    }
+    let svg = placeholder(&title, author.as_deref());
+    Ok((svg.into_bytes(), "image/svg+xml"))
+}
+
+/// The MIME type `thumbnail` answers with for the same book and size.
+pub fn thumbnail_type(app: &AppHandle, book_id: &str, size: CoverSize) -> &'static str {
+    match thumbnail_source(app, book_id, size) {
+        Ok((Some(_), _, _)) => "image/jpeg",
+        _ => "image/svg+xml",
+    }
+}
+
+/// The thumbnail file of a book's cover, created if missing, along with
+/// the title and author for a placeholder. The file is `None` when the book
+/// has no cover or its thumbnails cannot be made.
+fn thumbnail_source(
+    app: &AppHandle,
+    book_id: &str,
+    size: CoverSize,
+) -> Result<(Option<PathBuf>, String, Option<String>), String> {
+    let (key, title, author) = {
+        let state = app.state::<CoverState>();
+        let mut index = state
+            .0
+            .lock()
+            .map_err(|_| "Cover index lock poisoned".to_string())?;
+        refresh(app, &mut index)?;
+        let info = index
+            .books
+            .get(book_id)
+            .ok_or_else(|| format!("Book {} not found in library", book_id))?;
+        (info.key.clone(), info.title.clone(), info.author.clone())
+    };
+
+    let Some(key) = key else {
+        return Ok((None, title, author));
+    };
+    let dir = config::get_covers_dir(app)?;
+    let path = thumbnail_path(&dir, &key, size);
+    if !path.exists() {
+        let created = load_cover(app, book_id).and_then(|cover| generate(&dir, &cover));
+        if let Err(error) = created {
+            println!(
+                "DEBUG WARNING: Failed to create cover thumbnails: {}",
+                error
+            );
+        }
+    }
+    Ok((path.exists().then_some(path), title, author))
+}
+
+/// Answers `cover://` requests from the webview.
+pub fn handle(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let path = opds::decode_component(request.uri().path().trim_start_matches('/'));
+    let (book_id, size) = path.rsplit_once('/').unwrap_or((path.as_str(), "grid"));
+    let result = CoverSize::parse(size)
+        .ok_or_else(|| format!("Unknown cover size {}", size))
+        .and_then(|size| thumbnail(app, book_id, size));
+
+    let response = match result {
+        Ok((bytes, mime)) => Response::builder()
+            .header(CONTENT_TYPE, mime)
+            // Covers change when a book is re-imported or edited.
+            .header(CACHE_CONTROL, "no-cache")
+            .body(bytes),
+        Err(error) => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(error.into_bytes()),
+    };
+    response.unwrap_or_default()
+}
+
+fn refresh(app: &AppHandle, index: &mut CoverIndex) -> Result<(), String> {
+    let path = config::get_library_path(app)?;
+    let modified = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    if modified.is_some() && modified == index.library_modified {
+        return Ok(());
+    }
+    let books = library::load(&path)?.books;
+    index.books = books
+        .into_iter()
+        .map(|book| {
+            let info = CoverInfo {
+                key: book.cover_base64.as_deref().map(cover_key),
+                title: book.title,
+                author: book.author,
+            };
+            (book.id, info)
+        })
+        .collect();
+    index.library_modified = modified;
+    Ok(())
+}
+
+fn load_cover(app: &AppHandle, book_id: &str) -> Result<String, String> {
+    library::load(&config::get_library_path(app)?)?
+        .books
+        .into_iter()
+        .find(|book| book.id == book_id)
+        .and_then(|book| book.cover_base64)
+        .ok_or_else(|| format!("Book {} has no cover", book_id))
+}
+
+/// Thumbnails are named after the cover they were made from, so books
+/// sharing a cover share them and a changed cover never shows a stale one.
+fn cover_key(cover_base64: &str) -> String {
+    hex::encode(Sha256::digest(cover_base64.as_bytes()))[..32].to_string()
+}
+
+fn thumbnail_path(dir: &Path, key: &str, size: CoverSize) -> PathBuf {
+    dir.join(format!("{}-{}.jpg", key, size.name()))
+}
+
+fn generate(dir: &Path, cover_base64: &str) -> Result<(), String> {
+    let key = cover_key(cover_base64);
+    if CoverSize::ALL
+        .iter()
+        .all(|size| thumbnail_path(dir, &key, *size).exists())
+    {
+        return Ok(());
+    }
+    let bytes = STANDARD
+        .decode(cover_base64)
+        .map_err(|error| format!("Invalid cover data: {error}"))?;
+    let image = image::load_from_memory(&bytes)
+        .map_err(|error| format!("Unsupported cover image: {error}"))?;
+
+    for size in CoverSize::ALL {
+        let (width, height) = size.bounds();
+        let resized = if image.width() > width || image.height() > height {
+            image.resize(width, height, FilterType::Lanczos3)
+        } else {
+            image.clone()
+        };
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(resized.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))
+            .map_err(|error| format!("Failed to encode thumbnail: {error}"))?;
+
+        let path = thumbnail_path(dir, &key, size);
+        let temp = path.with_extension("jpg.tmp");
+        fs::write(&temp, &jpeg)
+            .map_err(|error| format!("Failed to write {}: {error}", temp.display()))?;
+        fs::rename(&temp, &path)
+            .map_err(|error| format!("Failed to write {}: {error}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// A cover showing the title and author on a color derived from the title.
+fn placeholder(title: &str, author: Option<&str>) -> String {
+    let hue = Sha256::digest(title.as_bytes())[0] as u32 * 360 / 256;
+    let mut text = String::new();
+    for (index, line) in wrap(title, 14, 5).iter().enumerate() {
+        text.push_str(&format!(
+            "<text x=\"200\" y=\"{}\" font-size=\"40\" font-weight=\"700\">{}</text>",
+            150 + index * 52,
+            epub::escape_xml(line)
+        ));
+    }
+    if let Some(author) = author.filter(|author| !author.trim().is_empty()) {
+        for (index, line) in wrap(author, 22, 2).iter().enumerate() {
+            text.push_str(&format!(
+                "<text x=\"200\" y=\"{}\" font-size=\"26\" opacity=\"0.85\">{}</text>",
+                500 + index * 34,
+                epub::escape_xml(line)
+            ));
+        }
+    }
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 400 600\" width=\"400\" height=\"600\">\
+         <rect width=\"400\" height=\"600\" fill=\"hsl({0}, 45%, 38%)\"/>\
+         <rect x=\"20\" y=\"20\" width=\"360\" height=\"560\" fill=\"none\" stroke=\"hsl({0}, 45%, 70%)\" stroke-width=\"3\"/>\
+         <g fill=\"#ffffff\" font-family=\"Georgia, serif\" text-anchor=\"middle\">{1}</g></svg>",
+        hue, text
+    )
+}
+
+/// Breaks `text` into lines of about `width` characters, ending with an
+/// ellipsis when it needs more than `max_lines`.
+fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+    lines
+}
@@ -1,9 +1,48 @@
 use crate::config;
-use crate::models::{ElevenLabsCloneRequest, ElevenLabsCloneResponse};
+use crate::http::Http;
+use crate::lexicon::Rendering;
+use crate::models::{ClonedVoice, ProviderVoice, TtsCapabilities, VoiceCloneRequest};
+use crate::prosody::{self, Markup};
+use crate::tts::{AudioSink, SpeechInput, SynthesizedAudio, TtsProvider};
+use async_trait::async_trait;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 use reqwest::multipart::{Form, Part};
-use serde::Deserialize;
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub struct ElevenLabsProvider {
+    http: Http,
+}
+
+impl ElevenLabsProvider {
+    pub fn new(http: Http) -> Self {
+        Self { http }
+    }
+}
+
+/// Models that read inline `<phoneme>` tags; the others speak them aloud, so
+/// they get alias spellings instead.
+const PHONEME_MODELS: &[&str] = &[
+    "eleven_flash_v2",
+    "eleven_turbo_v2",
+    "eleven_monolingual_v1",
+];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ElevenLabsOptions {
+    pub voice_id: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ElevenLabsTtsPayload<'a> {
+    text: &'a str,
+    model_id: &'a str,
+}
 
 #[derive(Deserialize)]
 struct ElevenLabsVoiceResponse {
@@ -11,43 +50,228 @@ struct ElevenLabsVoiceResponse {
     name: Option<String>,
 }
 
-pub async fn create_clone(
-    request: ElevenLabsCloneRequest,
-) -> Result<ElevenLabsCloneResponse, String> {
-    let api_key = config::elevenlabs_api_key()
+#[derive(Deserialize)]
+struct ElevenLabsVoicesResponse {
+    voices: Vec<ElevenLabsVoice>,
+}
+
+#[derive(Deserialize)]
+struct ElevenLabsVoice {
+    voice_id: String,
+    name: Option<String>,
+    category: Option<String>,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+#[async_trait]
+impl TtsProvider for ElevenLabsProvider {
+    type Options = ElevenLabsOptions;
+
+    fn id(&self) -> &'static str {
+        "elevenlabs"
+    }
+
+    fn name(&self) -> &'static str {
+        "ElevenLabs"
+    }
+
+    fn capabilities(&self) -> TtsCapabilities {
+        TtsCapabilities {
+            voice_listing: true,
+            voice_cloning: true,
+            streaming: true,
+            output_formats: vec!["mp3".to_string()],
+            max_characters: Some(10_000),
+            configured: config::elevenlabs_api_key().is_some_and(|key| !key.is_empty()),
+        }
+    }
+
+    async fn synthesize(
+        &self,
+        input: &SpeechInput,
+        options: ElevenLabsOptions,
+    ) -> Result<SynthesizedAudio, String> {
+        let _permit = self.http.permit(self.id()).await?;
+        let response = tts_response(&self.http, input, &options, false).await?;
+        let bytes = self
+            .http
+            .bytes(response)
+            .await
+            .map_err(|error| format!("ElevenLabs audio read failed: {error}"))?;
+
+        Ok(SynthesizedAudio {
+            bytes,
+            mime: "audio/mpeg".to_string(),
+        })
+    }
+
+    async fn synthesize_stream(
+        &self,
+        input: &SpeechInput,
+        options: ElevenLabsOptions,
+        sink: &AudioSink<'_>,
+    ) -> Result<(), String> {
+        let _permit = self.http.permit(self.id()).await?;
+        let mut response = tts_response(&self.http, input, &options, true).await?;
+        while let Some(chunk) = self
+            .http
+            .chunk(&mut response)
+            .await
+            .map_err(|error| format!("ElevenLabs audio read failed: {error}"))?
+        {
+            if chunk.is_empty() {
+                continue;
+            }
+            sink(SynthesizedAudio {
+                bytes: chunk,
+                mime: "audio/mpeg".to_string(),
+            })?;
+        }
+        Ok(())
+    }
+
+    async fn list_voices(&self, _options: ElevenLabsOptions) -> Result<Vec<ProviderVoice>, String> {
+        let api_key = api_key()?;
+        let request = self
+            .http
+            .client()
+            .get(endpoint("voices"))
+            .header("xi-api-key", api_key);
+        let response = self
+            .http
+            .send(request)
+            .await
+            .map_err(|error| format!("ElevenLabs voice request failed: {error}"))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("ElevenLabs voices returned {}: {}", status, body));
+        }
+
+        let body: ElevenLabsVoicesResponse = response
+            .json()
+            .await
+            .map_err(|error| format!("ElevenLabs voices parse failed: {error}"))?;
+
+        let voices = body
+            .voices
+            .into_iter()
+            .map(|mut voice| ProviderVoice {
+                name: voice.name.unwrap_or_else(|| voice.voice_id.clone()),
+                language: voice.labels.remove("language"),
+                is_cloned: voice.category.as_deref() == Some("cloned"),
+                voice_id: voice.voice_id,
+            })
+            .collect();
+        Ok(voices)
+    }
+
+    async fn clone_voice(&self, request: VoiceCloneRequest) -> Result<ClonedVoice, String> {
+        let api_key = api_key()?;
+        let requested_name = request.name.clone();
+        let bytes = STANDARD
+            .decode(request.audio_base64.as_bytes())
+            .map_err(|error| format!("Invalid audio base64: {error}"))?;
+        let file_part = Part::bytes(bytes).file_name(request.filename.clone());
+        let form = Form::new()
+            .text("name", request.name)
+            .part("files", file_part);
+
+        let request = self
+            .http
+            .client()
+            .post(endpoint("voices/add"))
+            .header("xi-api-key", api_key)
+            .multipart(form);
+        let response = self
+            .http
+            .send(request)
+            .await
+            .map_err(|error| format!("ElevenLabs clone request failed: {error}"))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("ElevenLabs clone returned {}: {}", status, body));
+        }
+
+        let body: ElevenLabsVoiceResponse = response
+            .json()
+            .await
+            .map_err(|error| format!("ElevenLabs clone parse failed: {error}"))?;
+
+        Ok(ClonedVoice {
+            voice_id: body.voice_id,
+            name: body.name.unwrap_or(requested_name),
+            demo_audio: None,
+        })
+    }
+}
+
+/// Sends a text-to-speech request, to the `/stream` endpoint when `stream`
+/// is set, which returns the same MP3 but flushes it as it is generated.
+async fn tts_response(
+    http: &Http,
+    input: &SpeechInput,
+    options: &ElevenLabsOptions,
+    stream: bool,
+) -> Result<Response, String> {
+    let api_key = api_key()?;
+    let voice_id = options
+        .voice_id
+        .as_ref()
         .filter(|value| !value.is_empty())
-        .ok_or_else(|| "Missing REBOOK_ELEVENLABS_API_KEY in environment.".to_string())?;
-    let requested_name = request.name.clone();
-    let bytes = STANDARD
-        .decode(request.audio_base64.as_bytes())
-        .map_err(|error| format!("Invalid audio base64: {error}"))?;
-    let file_part = Part::bytes(bytes).file_name(request.filename.clone());
-    let form = Form::new().text("name", request.name).part("files", file_part);
-
-    let response = reqwest::Client::new()
-        .post("https://api.elevenlabs.io/v1/voices/add")
+        .ok_or_else(|| "ElevenLabs voice_id required.".to_string())?;
+    let model_id = options
+        .model
+        .as_deref()
+        .filter(|value| !value.is_empty())
+        .unwrap_or("eleven_multilingual_v2");
+
+    let rendering = if PHONEME_MODELS.contains(&model_id) {
+        Rendering::Phoneme
+    } else {
+        Rendering::Alias
+    };
+    let text = prosody::render(input, Markup::BreakTags, Some(rendering));
+    let payload = ElevenLabsTtsPayload {
+        text: &text,
+        model_id,
+    };
+    let path = format!(
+        "text-to-speech/{voice_id}{}",
+        if stream { "/stream" } else { "" }
+    );
+    let request = http
+        .client()
+        .post(endpoint(&path))
         .header("xi-api-key", api_key)
-        .multipart(form)
-        .send()
+        .json(&payload);
+    let response = http
+        .send(request)
         .await
-        .map_err(|error| format!("ElevenLabs clone request failed: {error}"))?;
+        .map_err(|error| format!("ElevenLabs TTS request failed: {error}"))?;
 
     let status = response.status();
     if !status.is_success() {
         let body = response.text().await.unwrap_or_default();
-        return Err(format!(
-            "ElevenLabs clone returned {}: {}",
-            status, body
-        ));
+        return Err(format!("ElevenLabs TTS returned {}: {}", status, body));
     }
+    Ok(response)
+}
 
-    let body: ElevenLabsVoiceResponse = response
-        .json()
-        .await
-        .map_err(|error| format!("ElevenLabs clone parse failed: {error}"))?;
+fn endpoint(path: &str) -> String {
+    format!(
+        "{}/v1/{}",
+        config::elevenlabs_api_base().trim_end_matches('/'),
+        path
+    )
+}
 
-    Ok(ElevenLabsCloneResponse {
-        voice_id: body.voice_id,
-        name: body.name.unwrap_or(requested_name),
-    })
+fn api_key() -> Result<String, String> {
+    config::elevenlabs_api_key()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| "Missing REBOOK_ELEVENLABS_API_KEY in environment.".to_string())
 }
@@ -1,21 +1,27 @@
-use crate::models::{Book, Chapter};
+use crate::models::{Book, Chapter, LexiconEntry, PronunciationHint};
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
-use roxmltree::Document;
+use regex::Regex;
+use roxmltree::{Document, ParsingOptions};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
 use zip::ZipArchive;
 
-pub fn parse_epub(base64: String) -> Result<Book, String> {
+const SSML_NAMESPACE: &str = "http://www.w3.org/2001/10/synthesis";
+
+pub fn decode_base64(base64: &str) -> Result<Vec<u8>, String> {
+    STANDARD.decode(base64.as_bytes()).map_err(|error| {
+        let err = format!("Invalid base64: {error}");
+        println!("DEBUG ERROR: {}", err);
+        err
+    })
+}
+
+pub fn parse_epub(bytes: Vec<u8>) -> Result<Book, String> {
     println!("DEBUG: Starting EPUB parse...");
-    let bytes = STANDARD
-        .decode(base64.as_bytes())
-        .map_err(|error| {
-            let err = format!("Invalid base64: {error}");
-            println!("DEBUG ERROR: {}", err);
-            err
-        })?;
+    let content_hash = hash_bytes(&bytes);
     let reader = Cursor::new(bytes);
     let mut zip = ZipArchive::new(reader)
         .map_err(|error| {
@@ -31,7 +37,18 @@ pub fn parse_epub(base64: String) -> Result<Book, String> {
     let opf_xml = read_zip_file(&mut zip, &opf_path)?;
 
     println!("DEBUG: Parsing OPF...");
-    let (title, author, manifest, spine, cover) = parse_opf(&opf_xml)?;
+    let OpfPackage {
+        title,
+        author,
+        language,
+        identifier,
+        description,
+        series,
+        series_index,
+        manifest,
+        spine,
+        cover,
+    } = parse_opf(&opf_xml)?;
     println!("DEBUG: Found title: {:?}, author: {:?}, spine count: {}", title, author, spine.len());
 
     // Pre-calculate image map for the whole book to avoid re-reading ZIP
@@ -51,6 +68,17 @@ pub fn parse_epub(base64: String) -> Result<Book, String> {
     }
     println!("DEBUG: Image map contains {} images", image_map.len());
 
+    let mut lexicon = Vec::new();
+    for item in manifest.values() {
+        if item.media_type.as_deref() == Some("application/pls+xml") {
+            let path = resolve_relative_path(&opf_path, &item.href);
+            match read_zip_file(&mut zip, &path) {
+                Ok(xml) => parse_pls(&xml, &path, &mut lexicon),
+                Err(err) => println!("DEBUG WARNING: Failed to read lexicon at {}: {}", path, err),
+            }
+        }
+    }
+
     let mut chapters = Vec::new();
 
     for (index, idref) in spine.iter().enumerate() {
@@ -82,6 +110,8 @@ pub fn parse_epub(base64: String) -> Result<Book, String> {
             continue;
         }
         let word_count = clean_text.split_whitespace().count();
+        let chapter_hash = hash_text(&clean_text);
+        let pronunciations = pronunciation_hints(&content, &clean_text);
         chapters.push(Chapter {
             id: format!("chapter-{}", index + 1),
             title: chapter_title,
@@ -89,6 +119,8 @@ pub fn parse_epub(base64: String) -> Result<Book, String> {
             html: Some(processed_html),
             source_href: Some(href.to_string()),
             word_count,
+            content_hash: Some(chapter_hash),
+            pronunciations,
         });
     }
 
@@ -136,18 +168,33 @@ pub fn parse_epub(base64: String) -> Result<Book, String> {
         chapters,
         cover_base64,
         cover_mime,
+        language,
+        series,
+        series_index,
+        content_hash: Some(content_hash),
+        identifier,
+        description,
+        lexicon,
     })
 }
 
-fn read_zip_file(zip: &mut ZipArchive<Cursor<Vec<u8>>>, path: &str) -> Result<String, String> {
-    match zip.by_name(path) {
-        Ok(mut file) => {
-            let mut bytes = Vec::new();
-            file.read_to_end(&mut bytes)
-                .map_err(|error| format!("Failed reading {path}: {error}"))?;
-            return Ok(String::from_utf8_lossy(&bytes).to_string());
-        }
-        Err(_) => {}
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Hashes chapter text with whitespace collapsed, so re-wrapping by a newer
+/// html2text does not make an unchanged chapter look edited.
+pub fn hash_text(text: &str) -> String {
+    let normalized = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    hash_bytes(normalized.as_bytes())
+}
+
+pub fn read_zip_file(zip: &mut ZipArchive<Cursor<Vec<u8>>>, path: &str) -> Result<String, String> {
+    if let Ok(mut file) = zip.by_name(path) {
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|error| format!("Failed reading {path}: {error}"))?;
+        return Ok(String::from_utf8_lossy(&bytes).to_string());
     }
 
     let decoded = percent_decode_path(path);
@@ -164,14 +211,11 @@ fn read_zip_bytes(
     zip: &mut ZipArchive<Cursor<Vec<u8>>>,
     path: &str,
 ) -> Result<Vec<u8>, String> {
-    match zip.by_name(path) {
-        Ok(mut file) => {
-            let mut bytes = Vec::new();
-            file.read_to_end(&mut bytes)
-                .map_err(|error| format!("Failed reading {path}: {error}"))?;
-            return Ok(bytes);
-        }
-        Err(_) => {}
+    if let Ok(mut file) = zip.by_name(path) {
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|error| format!("Failed reading {path}: {error}"))?;
+        return Ok(bytes);
     }
 
     let decoded = percent_decode_path(path);
@@ -184,7 +228,7 @@ fn read_zip_bytes(
     Ok(bytes)
 }
 
-fn find_rootfile(container_xml: &str) -> Result<String, String> {
+pub fn find_rootfile(container_xml: &str) -> Result<String, String> {
     let document = Document::parse(container_xml)
         .map_err(|error| format!("Invalid container.xml: {error}"))?;
     let rootfile = document
@@ -202,18 +246,20 @@ struct ManifestItem {
     properties: Option<String>,
 }
 
-fn parse_opf(
-    opf_xml: &str,
-) -> Result<
-    (
-        Option<String>,
-        Option<String>,
-        HashMap<String, ManifestItem>,
-        Vec<String>,
-        Option<(String, Option<String>)>,
-    ),
-    String,
-> {
+struct OpfPackage {
+    title: Option<String>,
+    author: Option<String>,
+    language: Option<String>,
+    identifier: Option<String>,
+    description: Option<String>,
+    series: Option<String>,
+    series_index: Option<f64>,
+    manifest: HashMap<String, ManifestItem>,
+    spine: Vec<String>,
+    cover: Option<(String, Option<String>)>,
+}
+
+fn parse_opf(opf_xml: &str) -> Result<OpfPackage, String> {
     let document =
         Document::parse(opf_xml).map_err(|error| format!("Invalid OPF file: {error}"))?;
     let title = document
@@ -226,6 +272,19 @@ fn parse_opf(
         .find(|node| node.is_element() && node.tag_name().name() == "creator")
         .and_then(|node| node.text())
         .map(|text| text.trim().to_string());
+    let language = document
+        .descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == "language")
+        .and_then(|node| node.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty());
+    let identifier = find_unique_identifier(&document);
+    let description = document
+        .descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == "description")
+        .and_then(|node| node.text())
+        .and_then(description_text);
+    let (series, series_index) = find_series(&document);
 
     let mut manifest = HashMap::new();
     for item in document
@@ -252,7 +311,106 @@ fn parse_opf(
 
     let cover = find_cover(&document, &manifest);
 
-    Ok((title, author, manifest, spine, cover))
+    Ok(OpfPackage {
+        title,
+        author,
+        language,
+        identifier,
+        description,
+        series,
+        series_index,
+        manifest,
+        spine,
+        cover,
+    })
+}
+
+/// Reads series information from EPUB 3 `belongs-to-collection` metadata or
+/// the `calibre:series` meta tags Calibre writes into EPUB 2 packages.
+fn find_series(document: &Document) -> (Option<String>, Option<f64>) {
+    let metas = document
+        .descendants()
+        .filter(|node| node.is_element() && node.tag_name().name() == "meta")
+        .collect::<Vec<_>>();
+
+    if let Some(collection) = metas
+        .iter()
+        .find(|node| node.attribute("property") == Some("belongs-to-collection"))
+    {
+        let name = collection.text().map(|text| text.trim().to_string());
+        let index = collection.attribute("id").and_then(|id| {
+            let refines = format!("#{id}");
+            metas
+                .iter()
+                .find(|node| {
+                    node.attribute("refines") == Some(refines.as_str())
+                        && node.attribute("property") == Some("group-position")
+                })
+                .and_then(|node| node.text())
+                .and_then(|text| text.trim().parse::<f64>().ok())
+        });
+        if name.as_deref().is_some_and(|name| !name.is_empty()) {
+            return (name, index);
+        }
+    }
+
+    let content = |name: &str| {
+        metas
+            .iter()
+            .find(|node| node.attribute("name") == Some(name))
+            .and_then(|node| node.attribute("content"))
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let series = content("calibre:series");
+    let index = series
+        .as_ref()
+        .and(content("calibre:series_index"))
+        .and_then(|value| value.parse::<f64>().ok());
+    (series, index)
+}
+
+/// Resolves `package@unique-identifier` to its `dc:identifier`, falling back
+/// to the first identifier. Scheme prefixes are stripped so the same ISBN or
+/// UUID matches across editions that spell it differently.
+fn find_unique_identifier(document: &Document) -> Option<String> {
+    let identifiers = document
+        .descendants()
+        .filter(|node| node.is_element() && node.tag_name().name() == "identifier")
+        .collect::<Vec<_>>();
+    let unique_id = document.root_element().attribute("unique-identifier");
+    let node = unique_id
+        .and_then(|id| {
+            identifiers
+                .iter()
+                .find(|node| node.attribute("id") == Some(id))
+        })
+        .or_else(|| identifiers.first())?;
+    normalize_identifier(node.text()?)
+}
+
+/// Lowercases an identifier and strips URN-style prefixes so the same ISBN or
+/// UUID compares equal however it was written.
+pub fn normalize_identifier(raw: &str) -> Option<String> {
+    let raw = raw.trim().to_lowercase();
+    let normalized = ["urn:uuid:", "urn:isbn:", "uuid:", "isbn:"]
+        .iter()
+        .find_map(|prefix| raw.strip_prefix(prefix))
+        .unwrap_or(&raw)
+        .trim()
+        .to_string();
+    (!normalized.is_empty()).then_some(normalized)
+}
+
+/// Book descriptions are often escaped HTML; they are kept as plain text.
+pub fn description_text(raw: &str) -> Option<String> {
+    let text = if raw.contains('<') {
+        html2text::from_read(raw.as_bytes(), 120)
+    } else {
+        raw.to_string()
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
 }
 
 fn find_cover(
@@ -350,6 +508,133 @@ fn mime_from_bytes(bytes: &[u8]) -> Option<String> {
     None
 }
 
+/// Reads the lexemes of a Pronunciation Lexicon Specification document.
+/// Providers only take IPA, so phonemes in other alphabets are dropped and
+/// their lexemes kept only if they have an alias.
+fn parse_pls(xml: &str, path: &str, entries: &mut Vec<LexiconEntry>) {
+    let document = match Document::parse(xml) {
+        Ok(document) => document,
+        Err(err) => {
+            println!("DEBUG WARNING: Invalid lexicon at {}: {}", path, err);
+            return;
+        }
+    };
+    let root = document.root_element();
+    let alphabet = root.attribute("alphabet").unwrap_or("ipa");
+    for lexeme in root
+        .children()
+        .filter(|node| node.is_element() && node.tag_name().name() == "lexeme")
+    {
+        let children = || {
+            lexeme
+                .children()
+                .filter(|node| node.is_element())
+                .filter(|node| node.text().is_some_and(|text| !text.trim().is_empty()))
+        };
+        let ipa = children()
+            .find(|node| {
+                node.tag_name().name() == "phoneme"
+                    && is_ipa(node.attribute("alphabet").unwrap_or(alphabet))
+            })
+            .and_then(|node| node.text())
+            .map(|text| text.trim().to_string());
+        let alias = children()
+            .find(|node| node.tag_name().name() == "alias")
+            .and_then(|node| node.text())
+            .map(|text| text.trim().to_string());
+        if ipa.is_none() && alias.is_none() {
+            continue;
+        }
+        for grapheme in children().filter(|node| node.tag_name().name() == "grapheme") {
+            entries.push(LexiconEntry {
+                id: format!("publisher-{}", entries.len() + 1),
+                grapheme: grapheme.text().unwrap_or_default().trim().to_string(),
+                alias: alias.clone(),
+                ipa: ipa.clone(),
+                case_sensitive: true,
+            });
+        }
+    }
+}
+
+/// Parses a chapter document. Chapters often start with an XHTML doctype,
+/// which roxmltree rejects unless DTDs are allowed.
+pub fn parse_xhtml(content: &str) -> Result<Document<'_>, roxmltree::Error> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    Document::parse_with_options(content, options)
+}
+
+/// Finds the `ssml:ph` pronunciations in a chapter and locates each marked
+/// word in the chapter's plain text, in document order. Hints in other
+/// alphabets than IPA, or whose words html2text didn't keep, are skipped.
+fn pronunciation_hints(content: &str, text: &str) -> Vec<PronunciationHint> {
+    if !content.contains(SSML_NAMESPACE) {
+        return Vec::new();
+    }
+    let Ok(document) = parse_xhtml(content) else {
+        return Vec::new();
+    };
+    let mut hints = Vec::new();
+    let mut cursor = 0;
+    for node in document.descendants().filter(|node| node.is_element()) {
+        let Some(ph) = node.attribute((SSML_NAMESPACE, "ph")) else {
+            continue;
+        };
+        // A hint covers everything inside it, including other hints.
+        if node
+            .ancestors()
+            .skip(1)
+            .any(|ancestor| ancestor.has_attribute((SSML_NAMESPACE, "ph")))
+        {
+            continue;
+        }
+        let alphabet = node
+            .ancestors()
+            .find_map(|ancestor| ancestor.attribute((SSML_NAMESPACE, "alphabet")))
+            .unwrap_or("ipa");
+        if !is_ipa(alphabet) {
+            println!("DEBUG WARNING: Skipping {} pronunciation hint", alphabet);
+            continue;
+        }
+        let marked: String = node
+            .descendants()
+            .filter(|node| node.is_text())
+            .filter_map(|node| node.text())
+            .collect();
+        let words: Vec<&str> = marked.split_whitespace().collect();
+        if words.is_empty() || ph.trim().is_empty() {
+            continue;
+        }
+        let pattern = words
+            .iter()
+            .map(|word| regex::escape(word))
+            .collect::<Vec<_>>()
+            .join(r"\s+");
+        let Some(found) = Regex::new(&pattern)
+            .ok()
+            .and_then(|pattern| pattern.find_at(text, cursor))
+        else {
+            continue;
+        };
+        let start = text[..found.start()].chars().count();
+        hints.push(PronunciationHint {
+            start,
+            end: start + found.as_str().chars().count(),
+            grapheme: words.join(" "),
+            ipa: ph.trim().to_string(),
+        });
+        cursor = found.end();
+    }
+    hints
+}
+
+fn is_ipa(alphabet: &str) -> bool {
+    alphabet.trim().eq_ignore_ascii_case("ipa")
+}
+
 fn extract_title(content: &str) -> Option<String> {
     let document = Document::parse(content).ok()?;
     document
@@ -433,7 +718,7 @@ fn extract_first_image_src(content: &str) -> Option<String> {
         .map(|src| src.trim().to_string())
 }
 
-fn resolve_relative_path(opf_path: &str, href: &str) -> String {
+pub fn resolve_relative_path(opf_path: &str, href: &str) -> String {
     let base = Path::new(opf_path)
         .parent()
         .unwrap_or_else(|| Path::new(""));
@@ -441,6 +726,14 @@ fn resolve_relative_path(opf_path: &str, href: &str) -> String {
     normalize_path(&joined)
 }
 
+/// Escapes text for use in XML content and attribute values.
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 fn normalize_path(path: &Path) -> String {
     let mut normalized = PathBuf::new();
     for component in path.components() {
@@ -0,0 +1,357 @@
+use crate::epub;
+use crate::models::{Annotation, AnnotationKind, BookEntry, Chapter, ExportFormat};
+use crate::positions;
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+/// Characters per location number, matching the granularity readers expect
+/// from Kindle-style locations.
+const CHARS_PER_LOCATION: usize = 150;
+/// Text after a page break used to find the break again in `Chapter::text`.
+const PAGE_ANCHOR_CHARS: usize = 30;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedBook<'a> {
+    title: &'a str,
+    author: Option<&'a str>,
+    annotations: Vec<ExportedAnnotation<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedAnnotation<'a> {
+    kind: AnnotationKind,
+    chapter_id: &'a str,
+    chapter_title: &'a str,
+    text: &'a str,
+    note: Option<&'a str>,
+    color: Option<String>,
+    location: usize,
+    percent: u32,
+    page: Option<String>,
+    cfi: Option<&'a str>,
+    created_at: &'a str,
+    updated_at: &'a str,
+}
+
+/// Renders a book's highlights and notes. Bookmarks carry no selected text
+/// and are left out of exports.
+pub fn render(
+    book: &BookEntry,
+    annotations: &[Annotation],
+    format: ExportFormat,
+) -> Result<String, String> {
+    let exported = ExportedBook {
+        title: &book.title,
+        author: book.author.as_deref(),
+        annotations: collect(book, annotations),
+    };
+    match format {
+        ExportFormat::Markdown => Ok(render_markdown(&exported)),
+        ExportFormat::Csv => Ok(render_csv(&exported)),
+        ExportFormat::Readwise => Ok(render_readwise(&exported)),
+        ExportFormat::Json => serde_json::to_string_pretty(&exported)
+            .map_err(|error| format!("Failed to serialize annotations: {error}")),
+    }
+}
+
+pub fn file_name(book: &BookEntry, format: ExportFormat) -> String {
+    let stem: String = book
+        .title
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .to_string();
+    let stem = if stem.is_empty() {
+        book.id.clone()
+    } else {
+        stem
+    };
+    let suffix = match format {
+        ExportFormat::Readwise => "-readwise",
+        _ => "",
+    };
+    format!("{stem}{suffix}.{}", extension(format))
+}
+
+/// Writes one export per book with annotations into a zip at `path`.
+pub fn write_zip(
+    path: &Path,
+    books: &[BookEntry],
+    annotations_for: impl Fn(&BookEntry) -> Vec<Annotation>,
+    format: ExportFormat,
+) -> Result<usize, String> {
+    let file = File::create(path)
+        .map_err(|error| format!("Failed to create {}: {error}", path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut used_names = Vec::new();
+    let mut count = 0;
+    for book in books {
+        let annotations = annotations_for(book);
+        if !annotations
+            .iter()
+            .any(|annotation| annotation.kind != AnnotationKind::Bookmark)
+        {
+            continue;
+        }
+        let mut name = file_name(book, format);
+        if used_names.contains(&name) {
+            name = format!("{}-{}", book.id, name);
+        }
+        used_names.push(name.clone());
+        let content = render(book, &annotations, format)?;
+        zip.start_file(name.as_str(), options)
+            .map_err(|error| format!("Failed to add {name} to archive: {error}"))?;
+        zip.write_all(content.as_bytes())
+            .map_err(|error| format!("Failed to write {name} to archive: {error}"))?;
+        count += 1;
+    }
+    zip.finish()
+        .map_err(|error| format!("Failed to finish archive: {error}"))?;
+    Ok(count)
+}
+
+fn collect<'a>(book: &'a BookEntry, annotations: &'a [Annotation]) -> Vec<ExportedAnnotation<'a>> {
+    let page_labels: Vec<Vec<(usize, String)>> = book.chapters.iter().map(page_labels).collect();
+    let chapter_starts: Vec<usize> = book
+        .chapters
+        .iter()
+        .scan(0, |total, chapter| {
+            let start = *total;
+            *total += chapter.text.chars().count();
+            Some(start)
+        })
+        .collect();
+
+    annotations
+        .iter()
+        .filter(|annotation| annotation.kind != AnnotationKind::Bookmark)
+        .map(|annotation| {
+            let locator = &annotation.locator;
+            let chapter_index = book
+                .chapters
+                .iter()
+                .position(|chapter| chapter.id == locator.chapter_id);
+            let absolute =
+                chapter_index.map_or(0, |index| chapter_starts[index]) + locator.char_offset;
+            let page = chapter_index.and_then(|index| {
+                page_labels[index]
+                    .iter()
+                    .take_while(|(offset, _)| *offset <= locator.char_offset)
+                    .last()
+                    .map(|(_, label)| label.clone())
+            });
+            ExportedAnnotation {
+                kind: annotation.kind,
+                chapter_id: &locator.chapter_id,
+                chapter_title: chapter_index
+                    .map_or("", |index| book.chapters[index].title.as_str()),
+                text: locator
+                    .quote
+                    .as_ref()
+                    .map_or("", |quote| quote.exact.as_str()),
+                note: annotation.note.as_deref(),
+                color: annotation
+                    .color
+                    .map(|color| format!("{color:?}").to_lowercase()),
+                location: absolute / CHARS_PER_LOCATION + 1,
+                percent: (locator.progress * 100.0).round() as u32,
+                page,
+                cfi: locator.cfi.as_deref(),
+                created_at: &annotation.created_at,
+                updated_at: &annotation.updated_at,
+            }
+        })
+        .collect()
+}
+
+/// Print page labels in a chapter, from `epub:type="pagebreak"` or
+/// `role="doc-pagebreak"` markers, as (character offset, label) pairs.
+fn page_labels(chapter: &Chapter) -> Vec<(usize, String)> {
+    let Some(document) = chapter
+        .html
+        .as_deref()
+        .and_then(|html| epub::parse_xhtml(html).ok())
+    else {
+        return Vec::new();
+    };
+    let mut labels = Vec::new();
+    let mut search_from = 0;
+    for node in document.descendants().filter(|node| node.is_element()) {
+        let is_break = node.attributes().any(|attr| {
+            attr.name() == "type" && attr.value().split_whitespace().any(|t| t == "pagebreak")
+        }) || node.attribute("role") == Some("doc-pagebreak");
+        if !is_break {
+            continue;
+        }
+        let label = node
+            .attribute("title")
+            .or_else(|| node.attribute("aria-label"))
+            .map(str::to_string)
+            .or_else(|| node.text().map(|text| text.trim().to_string()))
+            .filter(|label| !label.is_empty());
+        let Some(label) = label else {
+            continue;
+        };
+        // Both iterators start with the node they are called on, which is
+        // skipped so that only text after the marker is taken.
+        let following: String = node
+            .next_siblings()
+            .skip(1)
+            .chain(
+                node.ancestors()
+                    .skip(1)
+                    .flat_map(|ancestor| ancestor.next_siblings().skip(1)),
+            )
+            .flat_map(|sibling| sibling.descendants())
+            .filter(|child| child.is_text())
+            .filter_map(|child| child.text())
+            .collect::<Vec<_>>()
+            .join(" ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(PAGE_ANCHOR_CHARS)
+            .collect();
+        let offset = if following.is_empty() {
+            chapter.text.chars().count()
+        } else {
+            match positions::find_text_offset(&chapter.text, &following, search_from) {
+                Some(offset) => offset,
+                None => continue,
+            }
+        };
+        search_from = offset;
+        labels.push((offset, label));
+    }
+    labels
+}
+
+fn render_markdown(book: &ExportedBook) -> String {
+    let mut output = format!("# {}\n", book.title);
+    if let Some(author) = book.author {
+        output.push_str(&format!("\n*{}*\n", author));
+    }
+    let mut current_chapter: Option<&str> = None;
+    for annotation in &book.annotations {
+        if current_chapter != Some(annotation.chapter_id) {
+            current_chapter = Some(annotation.chapter_id);
+            let heading = if annotation.chapter_title.is_empty() {
+                annotation.chapter_id
+            } else {
+                annotation.chapter_title
+            };
+            output.push_str(&format!("\n## {}\n", heading));
+        }
+        output.push('\n');
+        for line in annotation.text.lines() {
+            output.push_str(&format!("> {}\n", line.trim_end()));
+        }
+        if let Some(note) = annotation.note {
+            output.push_str(&format!("\n**Note:** {}\n", note));
+        }
+        let mut details = vec![format!(
+            "Location {} ({}%)",
+            annotation.location, annotation.percent
+        )];
+        if let Some(page) = &annotation.page {
+            details.push(format!("Page {}", page));
+        }
+        if let Some(color) = &annotation.color {
+            details.push(color.clone());
+        }
+        details.push(annotation.created_at.to_string());
+        output.push_str(&format!("\n<sub>{}</sub>\n", details.join(" · ")));
+    }
+    output
+}
+
+fn render_csv(book: &ExportedBook) -> String {
+    let mut rows = vec![csv_row(&[
+        "Book", "Author", "Chapter", "Kind", "Color", "Text", "Note", "Location", "Percent",
+        "Page", "CFI", "Created", "Updated",
+    ])];
+    for annotation in &book.annotations {
+        rows.push(csv_row(&[
+            book.title,
+            book.author.unwrap_or(""),
+            annotation.chapter_title,
+            &format!("{:?}", annotation.kind).to_lowercase(),
+            annotation.color.as_deref().unwrap_or(""),
+            annotation.text,
+            annotation.note.unwrap_or(""),
+            &annotation.location.to_string(),
+            &annotation.percent.to_string(),
+            annotation.page.as_deref().unwrap_or(""),
+            annotation.cfi.unwrap_or(""),
+            annotation.created_at,
+            annotation.updated_at,
+        ]));
+    }
+    rows.concat()
+}
+
+/// Readwise's CSV import columns: Highlight, Title, Author, URL, Note,
+/// Location, Date (as `YYYY-MM-DD HH:MM:SS`).
+fn render_readwise(book: &ExportedBook) -> String {
+    let mut rows = vec![csv_row(&[
+        "Highlight",
+        "Title",
+        "Author",
+        "URL",
+        "Note",
+        "Location",
+        "Date",
+    ])];
+    for annotation in &book.annotations {
+        let date = chrono::DateTime::parse_from_rfc3339(annotation.created_at)
+            .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        rows.push(csv_row(&[
+            annotation.text,
+            book.title,
+            book.author.unwrap_or(""),
+            "",
+            annotation.note.unwrap_or(""),
+            &annotation.location.to_string(),
+            &date,
+        ]));
+    }
+    rows.concat()
+}
+
+fn csv_row(fields: &[&str]) -> String {
+    let escaped: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    format!("{}\r\n", escaped.join(","))
+}
+
+fn extension(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Markdown => "md",
+        ExportFormat::Csv | ExportFormat::Readwise => "csv",
+        ExportFormat::Json => "json",
+    }
+}
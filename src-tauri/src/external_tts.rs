@@ -0,0 +1,152 @@
+use crate::config;
+use crate::http::Http;
+use crate::lexicon::Rendering;
+use crate::models::TtsCapabilities;
+use crate::prosody::{self, Markup};
+use crate::tts::{SpeechInput, SynthesizedAudio, TtsProvider};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+
+/// A self-hosted service answering `POST <base>/synthesize`, with either
+/// raw audio or `{ audioBase64, mime }`.
+pub struct ExternalProvider {
+    http: Http,
+}
+
+impl ExternalProvider {
+    pub fn new(http: Http) -> Self {
+        Self { http }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalOptions {
+    pub api_base_url: String,
+    pub voice_id: Option<String>,
+    pub output_format: Option<String>,
+    /// Send a `<speak>` document with breaks, emphasis and phonemes
+    /// instead of plain text, for services that read SSML.
+    #[serde(default)]
+    pub ssml: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExternalTtsPayload<'a> {
+    text: &'a str,
+    voice_id: Option<&'a str>,
+    format: Option<&'a str>,
+    ssml: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExternalTtsResponse {
+    audio_base64: String,
+    mime: Option<String>,
+}
+
+#[async_trait]
+impl TtsProvider for ExternalProvider {
+    type Options = ExternalOptions;
+
+    fn id(&self) -> &'static str {
+        "external"
+    }
+
+    fn name(&self) -> &'static str {
+        "External service"
+    }
+
+    fn capabilities(&self) -> TtsCapabilities {
+        TtsCapabilities {
+            voice_listing: false,
+            voice_cloning: false,
+            streaming: false,
+            output_formats: Vec::new(),
+            // The API key is optional; the service URL comes with each request.
+            max_characters: None,
+            configured: true,
+        }
+    }
+
+    async fn synthesize(
+        &self,
+        input: &SpeechInput,
+        options: ExternalOptions,
+    ) -> Result<SynthesizedAudio, String> {
+        let url = build_endpoint(&options);
+        let markup = if options.ssml {
+            Markup::Ssml
+        } else {
+            Markup::Plain
+        };
+        let text = prosody::render(input, markup, Some(Rendering::Alias));
+        let payload = ExternalTtsPayload {
+            text: &text,
+            voice_id: options.voice_id.as_deref(),
+            format: options.output_format.as_deref(),
+            ssml: options.ssml,
+        };
+
+        let _permit = self.http.permit(self.id()).await?;
+        let mut req = self.http.client().post(url).json(&payload);
+        if let Some(key) = config::external_api_key()
+            .as_ref()
+            .filter(|value| !value.is_empty())
+        {
+            req = req.bearer_auth(key);
+        }
+
+        let response = self
+            .http
+            .send(req)
+            .await
+            .map_err(|error| format!("TTS request failed: {error}"))?;
+
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("TTS service returned {}: {}", status, body));
+        }
+
+        let body = self
+            .http
+            .bytes(response)
+            .await
+            .map_err(|error| format!("Failed reading audio response: {error}"))?;
+        if content_type.starts_with("audio/") {
+            return Ok(SynthesizedAudio {
+                bytes: body,
+                mime: content_type,
+            });
+        }
+
+        let body: ExternalTtsResponse = serde_json::from_slice(&body)
+            .map_err(|error| format!("Failed parsing TTS JSON response: {error}"))?;
+        let bytes = STANDARD
+            .decode(body.audio_base64.as_bytes())
+            .map_err(|error| format!("Invalid audio base64: {error}"))?;
+
+        Ok(SynthesizedAudio {
+            bytes,
+            mime: body.mime.unwrap_or_else(|| "audio/mpeg".to_string()),
+        })
+    }
+}
+
+fn build_endpoint(options: &ExternalOptions) -> String {
+    let base = options.api_base_url.trim_end_matches('/');
+    format!("{base}/synthesize")
+}
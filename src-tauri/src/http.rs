@@ -0,0 +1,248 @@
+use crate::config;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Server-requested waits longer than this are not worth holding playback
+/// for; the response is returned as-is instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// How the shared client connects, retries and limits concurrent requests.
+/// Each field can be set with a `REBOOK_HTTP_*` variable.
+#[derive(Debug, Clone)]
+pub struct HttpSettings {
+    pub connect_timeout: Duration,
+    /// Longest wait for response headers, and then between body chunks.
+    pub read_timeout: Duration,
+    pub proxy: Option<String>,
+    /// Retries after the first attempt.
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Requests each provider may have in flight at once.
+    pub concurrency: usize,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(60),
+            proxy: None,
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+            concurrency: 2,
+        }
+    }
+}
+
+impl HttpSettings {
+    pub fn from_config() -> Self {
+        let defaults = Self::default();
+        let seconds = |name: &str, default: Duration| {
+            config::http_setting(name)
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        };
+        let millis = |name: &str, default: Duration| {
+            config::http_setting(name)
+                .map(Duration::from_millis)
+                .unwrap_or(default)
+        };
+        Self {
+            connect_timeout: seconds("CONNECT_TIMEOUT_SECS", defaults.connect_timeout),
+            read_timeout: seconds("READ_TIMEOUT_SECS", defaults.read_timeout),
+            proxy: config::http_proxy(),
+            max_retries: config::http_setting("MAX_RETRIES")
+                .map(|value| value.min(10) as u32)
+                .unwrap_or(defaults.max_retries),
+            base_delay: millis("RETRY_BASE_MS", defaults.base_delay),
+            max_delay: millis("RETRY_MAX_MS", defaults.max_delay),
+            concurrency: config::http_setting("CONCURRENCY")
+                .map(|value| value.max(1) as usize)
+                .unwrap_or(defaults.concurrency),
+        }
+    }
+}
+
+/// The HTTP client the TTS providers and OPDS catalogs share, kept in Tauri
+/// state and handed to each provider. Requests are retried on connection
+/// failures, timeouts, 429 and 5xx responses, with exponential backoff and
+/// jitter unless the server sends `Retry-After`.
+///
+/// Nothing here checks for cancellation: dropping a future returned by
+/// `send`, `chunk` or `bytes`, as `Cancellation::run` does, aborts the
+/// request or the backoff wait in progress.
+#[derive(Clone)]
+pub struct Http(Arc<HttpInner>);
+
+struct HttpInner {
+    client: Client,
+    settings: HttpSettings,
+    limits: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl Http {
+    pub fn new(settings: HttpSettings) -> Result<Self, String> {
+        let mut builder = Client::builder().connect_timeout(settings.connect_timeout);
+        if let Some(proxy) = &settings.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|error| format!("Invalid proxy {}: {}", proxy, error))?;
+            builder = builder.proxy(proxy);
+        }
+        let client = builder
+            .build()
+            .map_err(|error| format!("Failed to create HTTP client: {error}"))?;
+        Ok(Self(Arc::new(HttpInner {
+            client,
+            settings,
+            limits: Mutex::new(HashMap::new()),
+        })))
+    }
+
+    /// The client configured from the environment, falling back to one
+    /// without a proxy if the configured proxy is invalid.
+    pub fn from_config() -> Self {
+        let settings = HttpSettings::from_config();
+        Self::new(settings.clone()).unwrap_or_else(|error| {
+            println!("DEBUG WARNING: {}; connecting without a proxy", error);
+            Self::new(HttpSettings {
+                proxy: None,
+                ..settings
+            })
+            .expect("HTTP client without a proxy")
+        })
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.0.client
+    }
+
+    /// Waits for a free request slot for `provider`. Hold the permit until
+    /// the response body has been read.
+    pub async fn permit(&self, provider: &str) -> Result<OwnedSemaphorePermit, String> {
+        let semaphore = {
+            let mut limits = self
+                .0
+                .limits
+                .lock()
+                .map_err(|_| "HTTP limits lock poisoned".to_string())?;
+            limits
+                .entry(provider.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.0.settings.concurrency)))
+                .clone()
+        };
+        semaphore
+            .acquire_owned()
+            .await
+            .map_err(|error| format!("HTTP request slot unavailable: {error}"))
+    }
+
+    /// Sends `request`, retrying it while it can be cloned; multipart
+    /// uploads are sent once. Once retries run out the last response is
+    /// returned whatever its status, so callers report errors as before.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, String> {
+        let settings = &self.0.settings;
+        let timed_out = || format!("no response after {}s", settings.read_timeout.as_secs());
+        let mut request = request;
+        let mut attempt = 0;
+        loop {
+            let Some(retry) = request
+                .try_clone()
+                .filter(|_| attempt < settings.max_retries)
+            else {
+                return match tokio::time::timeout(settings.read_timeout, request.send()).await {
+                    Ok(result) => result.map_err(|error| error.to_string()),
+                    Err(_) => Err(timed_out()),
+                };
+            };
+            let (delay, failure) =
+                match tokio::time::timeout(settings.read_timeout, request.send()).await {
+                    Ok(Ok(response)) if is_retryable_status(response.status()) => {
+                        let delay = match retry_after(&response) {
+                            Some(delay) if delay > MAX_RETRY_AFTER => return Ok(response),
+                            Some(delay) => delay,
+                            None => self.backoff(attempt),
+                        };
+                        (delay, response.status().to_string())
+                    }
+                    Ok(Ok(response)) => return Ok(response),
+                    Ok(Err(error)) if error.is_connect() || error.is_timeout() => {
+                        (self.backoff(attempt), error.to_string())
+                    }
+                    Ok(Err(error)) => return Err(error.to_string()),
+                    Err(_) => (self.backoff(attempt), timed_out()),
+                };
+            request = retry;
+            attempt += 1;
+            println!(
+                "DEBUG WARNING: HTTP request failed ({}), retry {} of {} in {}ms",
+                failure,
+                attempt,
+                settings.max_retries,
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// The next piece of the response body, or `None` at its end.
+    pub async fn chunk(&self, response: &mut Response) -> Result<Option<Vec<u8>>, String> {
+        let read_timeout = self.0.settings.read_timeout;
+        match tokio::time::timeout(read_timeout, response.chunk()).await {
+            Ok(Ok(chunk)) => Ok(chunk.map(|chunk| chunk.to_vec())),
+            Ok(Err(error)) => Err(error.to_string()),
+            Err(_) => Err(format!("no data for {}s", read_timeout.as_secs())),
+        }
+    }
+
+    /// The whole response body, failing if it stalls.
+    pub async fn bytes(&self, mut response: Response) -> Result<Vec<u8>, String> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.chunk(&mut response).await? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Exponential backoff with jitter: a random wait between half and all
+    /// of `base_delay * 2^attempt`, capped at `max_delay`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let settings = &self.0.settings;
+        let delay = settings
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(settings.max_delay);
+        let random = RandomState::new().build_hasher().finish();
+        delay / 2 + (delay / 2).mul_f64((random % 1000) as f64 / 1000.0)
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// `Retry-After` as either seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
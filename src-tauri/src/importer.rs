@@ -0,0 +1,200 @@
+use crate::annotations::{self, AnnotationStore};
+use crate::config;
+use crate::covers;
+use crate::epub;
+use crate::library;
+use crate::models::{Book, BookEntry, BookUpdate, DuplicateReason, ImportResult, ImportResultKind};
+use crate::positions::{self, PositionStore};
+use crate::reimport;
+use crate::search;
+use crate::sources;
+use crate::storage;
+use tauri::AppHandle;
+
+pub enum ImportOutcome {
+    Added(BookEntry),
+    Updated(BookUpdate),
+    /// The exact file was already in the library.
+    Unchanged(BookEntry),
+}
+
+impl ImportOutcome {
+    pub fn book(&self) -> &BookEntry {
+        match self {
+            ImportOutcome::Added(book) | ImportOutcome::Unchanged(book) => book,
+            ImportOutcome::Updated(update) => &update.book,
+        }
+    }
+
+    pub fn into_result(self) -> ImportResult {
+        let (kind, book) = match self {
+            ImportOutcome::Added(book) => (ImportResultKind::Added, book),
+            ImportOutcome::Updated(update) => (ImportResultKind::Updated, update.book),
+            ImportOutcome::Unchanged(book) => (ImportResultKind::Unchanged, book),
+        };
+        ImportResult { kind, book }
+    }
+}
+
+/// Imports an EPUB without asking: a file already in the library is left
+/// alone, another edition of a library book replaces it in place, and
+/// anything else is added as a new book. `previous` is the book this file
+/// was imported as before, if known, and is updated when the file changed.
+pub fn import_epub(
+    app: &AppHandle,
+    bytes: Vec<u8>,
+    previous: Option<&str>,
+) -> Result<ImportOutcome, String> {
+    let book = epub::parse_epub(bytes.clone())?;
+    keep_source(app, &book, &bytes);
+
+    let path = config::get_library_path(app)?;
+    let _guard = library::lock(app)?;
+    let mut books = library::load(&path)?.books;
+    let outcome = merge_book(&mut books, book, previous);
+    if !matches!(outcome, ImportOutcome::Unchanged(_)) {
+        library::save(&path, &books)?;
+        search::update_index(app, &books);
+        covers::keep_thumbnails(app, outcome.book().cover_base64.as_deref());
+    }
+    if let ImportOutcome::Updated(update) = &outcome {
+        remap_stores(app, &[update])?;
+    }
+    Ok(outcome)
+}
+
+/// Applies the rules of `import_epub` to an in-memory library so batch
+/// importers can save once at the end. Callers must pass any returned
+/// update to `remap_stores` after saving.
+pub fn merge_book(
+    books: &mut Vec<BookEntry>,
+    book: Book,
+    previous: Option<&str>,
+) -> ImportOutcome {
+    let duplicates = reimport::find_duplicates(
+        books,
+        book.content_hash.as_deref(),
+        book.identifier.as_deref(),
+    );
+    if let Some(same) = duplicates
+        .iter()
+        .find(|candidate| matches!(candidate.reason, DuplicateReason::SameFile))
+    {
+        if let Some(existing) = books.iter().find(|entry| entry.id == same.book_id) {
+            return ImportOutcome::Unchanged(existing.clone());
+        }
+    }
+    let replaces = previous
+        .filter(|id| books.iter().any(|entry| entry.id == *id))
+        .or(duplicates.first().map(|existing| existing.book_id.as_str()));
+    if let Some(entry) = replaces.and_then(|id| books.iter_mut().find(|entry| entry.id == id)) {
+        let chapters = reimport::update_entry(entry, book);
+        return ImportOutcome::Updated(BookUpdate {
+            book: entry.clone(),
+            chapters,
+        });
+    }
+
+    let entry = new_entry(books, book);
+    books.insert(0, entry.clone());
+    ImportOutcome::Added(entry)
+}
+
+/// Keeps the original file for backups. Failing to do so never fails an
+/// import.
+pub fn keep_source(app: &AppHandle, book: &Book, bytes: &[u8]) {
+    let Some(hash) = &book.content_hash else {
+        return;
+    };
+    let stored = config::get_sources_dir(app).and_then(|dir| sources::store(&dir, hash, bytes));
+    if let Err(error) = stored {
+        println!("DEBUG WARNING: Failed to keep original EPUB: {}", error);
+    }
+}
+
+/// Replaces a library book with a new edition and carries reading positions
+/// and annotations over to its chapters.
+pub fn update_book(app: &AppHandle, book_id: &str, book: Book) -> Result<BookUpdate, String> {
+    let path = config::get_library_path(app)?;
+    let _guard = library::lock(app)?;
+    let mut books = library::load(&path)?.books;
+    let entry = books
+        .iter_mut()
+        .find(|entry| entry.id == book_id)
+        .ok_or_else(|| format!("Book {} not found in library", book_id))?;
+    let chapters = reimport::update_entry(entry, book);
+    let update = BookUpdate {
+        book: entry.clone(),
+        chapters,
+    };
+    library::save(&path, &books)?;
+    search::update_index(app, &books);
+    remap_stores(app, &[&update])?;
+    Ok(update)
+}
+
+/// Moves reading positions and annotations of updated books onto their new
+/// chapters.
+pub fn remap_stores(app: &AppHandle, updates: &[&BookUpdate]) -> Result<(), String> {
+    if updates.is_empty() {
+        return Ok(());
+    }
+    let positions_path = config::get_positions_path(app)?;
+    let guard = positions::lock(app)?;
+    let mut store: PositionStore = storage::read_json(&positions_path)?;
+    for update in updates {
+        positions::remap(&mut store, &update.book, &update.chapters);
+    }
+    storage::write_json(&positions_path, &store)?;
+    drop(guard);
+
+    let annotations_path = config::get_annotations_path(app)?;
+    let _guard = annotations::lock(app)?;
+    let mut annotations: AnnotationStore = storage::read_json(&annotations_path)?;
+    for update in updates {
+        annotations::remap(&mut annotations, &update.book, &update.chapters);
+    }
+    storage::write_json(&annotations_path, &annotations)
+}
+
+/// Removes a book from the library, returning it if it was there.
+pub fn remove_book(app: &AppHandle, book_id: &str) -> Result<Option<BookEntry>, String> {
+    let path = config::get_library_path(app)?;
+    let _guard = library::lock(app)?;
+    let mut books = library::load(&path)?.books;
+    let Some(index) = books.iter().position(|entry| entry.id == book_id) else {
+        return Ok(None);
+    };
+    let removed = books.remove(index);
+    library::save(&path, &books)?;
+    search::update_index(app, &books);
+    Ok(Some(removed))
+}
+
+/// Builds a library entry the same way the frontend does for a manual import.
+fn new_entry(books: &[BookEntry], book: Book) -> BookEntry {
+    let mut millis = chrono::Utc::now().timestamp_millis();
+    while books.iter().any(|entry| entry.id == format!("book-{}", millis)) {
+        millis += 1;
+    }
+    let imported = reimport::imported_metadata(&book);
+    BookEntry {
+        id: format!("book-{}", millis),
+        title: book.title,
+        author: book.author,
+        author_sort: None,
+        cover_base64: book.cover_base64,
+        cover_mime: book.cover_mime,
+        chapters: book.chapters,
+        imported_at: storage::now_iso(),
+        language: book.language,
+        series: book.series,
+        series_index: book.series_index,
+        tags: Vec::new(),
+        content_hash: book.content_hash,
+        identifier: book.identifier,
+        description: book.description,
+        lexicon: book.lexicon,
+        imported: Some(imported),
+    }
+}
@@ -0,0 +1,297 @@
+use crate::config;
+use crate::epub;
+use crate::models::{BookEntry, LexiconEntry, LexiconEntryInput};
+use crate::storage;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tauri::AppHandle;
+
+/// Pronunciations that apply to every book, plus each book's own.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LexiconStore {
+    #[serde(default)]
+    pub global: Vec<LexiconEntry>,
+    #[serde(default)]
+    pub books: HashMap<String, Vec<LexiconEntry>>,
+}
+
+/// How a provider wants pronunciations written into the text it is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rendering {
+    /// Replace the word with its alias spelling.
+    Alias,
+    /// Wrap the word in an SSML `<phoneme>` tag when it has IPA, falling
+    /// back to the alias otherwise.
+    Phoneme,
+    /// Like `Phoneme`, with all other text escaped for an SSML document.
+    Ssml,
+}
+
+/// The entries of one scope: a book's when `book_id` is given, otherwise
+/// the global ones.
+pub fn entries<'a>(store: &'a LexiconStore, book_id: Option<&str>) -> &'a [LexiconEntry] {
+    match book_id {
+        Some(book_id) => store.books.get(book_id).map(Vec::as_slice).unwrap_or(&[]),
+        None => &store.global,
+    }
+}
+
+/// The pronunciations in effect for a book: the reader's entries for it,
+/// then the publisher's, then the global entries. Each layer overrides the
+/// ones after it for the same word.
+pub fn for_book(
+    app: &AppHandle,
+    book_id: Option<&str>,
+    publisher: Vec<LexiconEntry>,
+) -> Result<Vec<LexiconEntry>, String> {
+    let store: LexiconStore = storage::read_json(&config::get_lexicon_path(app)?)?;
+    let own = book_id
+        .map(|book_id| entries(&store, Some(book_id)).to_vec())
+        .unwrap_or_default();
+
+    let mut effective: Vec<LexiconEntry> = Vec::new();
+    for layer in [own, publisher, store.global] {
+        let overridden: HashSet<String> = effective
+            .iter()
+            .map(|entry| entry.grapheme.to_lowercase())
+            .collect();
+        effective.extend(
+            layer
+                .into_iter()
+                .filter(|entry| !overridden.contains(&entry.grapheme.to_lowercase())),
+        );
+    }
+    Ok(effective)
+}
+
+/// The pronunciations a book's publisher shipped for part of a chapter: the
+/// inline hints between `start` and `end` (or the whole chapter), then the
+/// book's PLS entries.
+pub fn from_publisher(
+    book: &BookEntry,
+    chapter_id: &str,
+    start: Option<usize>,
+    end: Option<usize>,
+) -> Vec<LexiconEntry> {
+    let mut entries: Vec<LexiconEntry> = Vec::new();
+    if let Some(chapter) = book
+        .chapters
+        .iter()
+        .find(|chapter| chapter.id == chapter_id)
+    {
+        let start = start.unwrap_or(0);
+        let end = end.unwrap_or(usize::MAX);
+        for hint in &chapter.pronunciations {
+            // The first hint for a word wins if it is marked differently
+            // elsewhere in the same stretch of text.
+            if hint.start < start
+                || hint.end > end
+                || entries.iter().any(|entry| entry.grapheme == hint.grapheme)
+            {
+                continue;
+            }
+            entries.push(LexiconEntry {
+                id: format!("{}:{}-{}", chapter_id, hint.start, hint.end),
+                grapheme: hint.grapheme.clone(),
+                alias: None,
+                ipa: Some(hint.ipa.clone()),
+                case_sensitive: true,
+            });
+        }
+    }
+    entries.extend(book.lexicon.iter().cloned());
+    entries
+}
+
+/// Adds an entry, or updates the one with the same id.
+pub fn upsert(
+    store: &mut LexiconStore,
+    book_id: Option<&str>,
+    input: LexiconEntryInput,
+) -> Result<LexiconEntry, String> {
+    let entry = validate(input)?;
+    let scope = match book_id {
+        Some(book_id) => store.books.entry(book_id.to_string()).or_default(),
+        None => &mut store.global,
+    };
+    match scope.iter_mut().find(|existing| existing.id == entry.id) {
+        Some(existing) => *existing = entry.clone(),
+        None => scope.push(entry.clone()),
+    }
+    Ok(entry)
+}
+
+pub fn remove(store: &mut LexiconStore, book_id: Option<&str>, id: &str) -> Result<(), String> {
+    let scope = match book_id {
+        Some(book_id) => store
+            .books
+            .get_mut(book_id)
+            .ok_or_else(|| format!("Pronunciation {} not found", id))?,
+        None => &mut store.global,
+    };
+    let before = scope.len();
+    scope.retain(|entry| entry.id != id);
+    if scope.len() == before {
+        return Err(format!("Pronunciation {} not found", id));
+    }
+    if let Some(book_id) = book_id {
+        if store.books.get(book_id).is_some_and(Vec::is_empty) {
+            store.books.remove(book_id);
+        }
+    }
+    Ok(())
+}
+
+/// Checks an entry from the frontend, giving new entries an id.
+pub fn validate(input: LexiconEntryInput) -> Result<LexiconEntry, String> {
+    let grapheme = input.grapheme.trim().to_string();
+    if grapheme.is_empty() {
+        return Err("Pronunciation needs a word to match".to_string());
+    }
+    let alias = input
+        .alias
+        .map(|alias| alias.trim().to_string())
+        .filter(|alias| !alias.is_empty());
+    let ipa = input
+        .ipa
+        .map(|ipa| ipa.trim().trim_matches('/').to_string())
+        .filter(|ipa| !ipa.is_empty());
+    if alias.is_none() && ipa.is_none() {
+        return Err(format!(
+            "Pronunciation for {} needs an alias or IPA",
+            grapheme
+        ));
+    }
+    Ok(LexiconEntry {
+        id: input
+            .id
+            .unwrap_or_else(|| format!("pronunciation-{}", chrono::Utc::now().timestamp_micros())),
+        grapheme,
+        alias,
+        ipa,
+        case_sensitive: input.case_sensitive,
+    })
+}
+
+/// The entries whose word occurs in `text`.
+pub fn matching(text: &str, entries: &[LexiconEntry]) -> Vec<LexiconEntry> {
+    let Some((pattern, order)) = pattern(entries) else {
+        return Vec::new();
+    };
+    let mut found = HashSet::new();
+    for caps in pattern.captures_iter(text) {
+        if let Some(index) = matched_entry(&caps, &order) {
+            found.insert(index);
+        }
+    }
+    let mut indices: Vec<usize> = found.into_iter().collect();
+    indices.sort();
+    indices
+        .into_iter()
+        .map(|index| entries[index].clone())
+        .collect()
+}
+
+/// Writes `entries` into `text` for providers that take plain text or
+/// SSML.
+pub fn apply(text: &str, entries: &[LexiconEntry], rendering: Rendering) -> String {
+    let escape = |text: &str| match rendering {
+        Rendering::Ssml => epub::escape_xml(text),
+        _ => text.to_string(),
+    };
+    let Some((pattern, order)) = pattern(entries) else {
+        return escape(text);
+    };
+    let mut out = String::new();
+    let mut last = 0;
+    for caps in pattern.captures_iter(text) {
+        let matched = caps.get(0).unwrap();
+        out.push_str(&escape(&text[last..matched.start()]));
+        last = matched.end();
+        let word = matched.as_str();
+        let Some(entry) = matched_entry(&caps, &order).map(|index| &entries[index]) else {
+            out.push_str(&escape(word));
+            continue;
+        };
+        match (&entry.ipa, &entry.alias, rendering) {
+            (Some(ipa), _, Rendering::Phoneme | Rendering::Ssml) => out.push_str(&format!(
+                "<phoneme alphabet=\"ipa\" ph=\"{}\">{}</phoneme>",
+                epub::escape_xml(ipa),
+                epub::escape_xml(word)
+            )),
+            (_, Some(alias), _) => out.push_str(&escape(alias)),
+            _ => out.push_str(&escape(word)),
+        }
+    }
+    out.push_str(&escape(&text[last..]));
+    out
+}
+
+/// Each distinct spelling in `text` that an entry with an alias matched,
+/// paired with that alias, for providers that take a replacement dictionary
+/// alongside the text.
+pub fn aliases(text: &str, entries: &[LexiconEntry]) -> Vec<(String, String)> {
+    let Some((pattern, order)) = pattern(entries) else {
+        return Vec::new();
+    };
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for caps in pattern.captures_iter(text) {
+        let Some(entry) = matched_entry(&caps, &order).map(|index| &entries[index]) else {
+            continue;
+        };
+        let Some(alias) = &entry.alias else {
+            continue;
+        };
+        let word = &caps[0];
+        if !pairs.iter().any(|(existing, _)| existing == word) {
+            pairs.push((word.to_string(), alias.clone()));
+        }
+    }
+    pairs
+}
+
+/// One regex matching every entry as a whole word, longest words first so
+/// "Gil-galad" wins over "Gil". Each entry gets its own capture group;
+/// `order` maps group positions back to entry indices.
+fn pattern(entries: &[LexiconEntry]) -> Option<(Regex, Vec<usize>)> {
+    if entries.is_empty() {
+        return None;
+    }
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(entries[index].grapheme.chars().count()));
+
+    let alternatives: Vec<String> = order
+        .iter()
+        .map(|&index| {
+            let grapheme = &entries[index].grapheme;
+            let starts_word = grapheme.starts_with(char::is_alphanumeric);
+            let ends_word = grapheme.ends_with(char::is_alphanumeric);
+            format!(
+                "({}{}{}{})",
+                if starts_word { r"\b" } else { "" },
+                if entries[index].case_sensitive {
+                    ""
+                } else {
+                    "(?i)"
+                },
+                regex::escape(grapheme),
+                if ends_word { r"\b" } else { "" },
+            )
+        })
+        .collect();
+    match Regex::new(&alternatives.join("|")) {
+        Ok(pattern) => Some((pattern, order)),
+        Err(error) => {
+            println!("DEBUG WARNING: Invalid pronunciation lexicon: {}", error);
+            None
+        }
+    }
+}
+
+fn matched_entry(caps: &Captures, order: &[usize]) -> Option<usize> {
+    (0..order.len())
+        .find(|position| caps.get(position + 1).is_some())
+        .map(|position| order[position])
+}
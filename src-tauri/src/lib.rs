@@ -1,84 +1,1039 @@
+mod annotations;
+mod audio_cache;
+mod backup;
+mod calibre;
+mod cancellation;
 mod config;
+mod covers;
 mod epub;
 mod elevenlabs;
+mod export;
+mod external_tts;
+mod http;
+mod importer;
+mod lexicon;
+mod library;
+mod local_tts;
+mod metadata;
 mod minimax;
 mod models;
+mod narration;
+mod normalize;
+mod opds;
+mod opds_server;
+mod positions;
+mod prosody;
+mod reimport;
+mod search;
+mod segment;
+mod shelves;
+mod sources;
+mod stats;
+mod storage;
 mod tts;
+mod watcher;
 
+use crate::annotations::{AnnotationState, AnnotationStore};
+use crate::audio_cache::AudioCacheState;
+use crate::backup::{ClientState, DataPaths};
+use crate::cancellation::{Cancellation, SynthesisJobs};
+use crate::covers::CoverState;
+use crate::elevenlabs::ElevenLabsProvider;
+use crate::external_tts::ExternalProvider;
+use crate::http::Http;
+use crate::lexicon::LexiconStore;
+use crate::library::LibraryState;
+use crate::local_tts::LocalProvider;
 use crate::models::{
-    AudioClip, Book, BookEntry, ElevenLabsCloneRequest, ElevenLabsCloneResponse,
-    MinimaxCloneRequest, MinimaxCloneResponse, MinimaxUploadRequest, MinimaxUploadResponse,
-    TtsRequest,
+    Annotation, AnnotationInput, AnnotationKind, AnnotationUpdate, AudioCacheStats, AudioClip,
+    AudioStreamEvent, BackupImportOptions, BackupImportSummary, BackupManifest, Book, BookActivity,
+    BookEntry, BookStats, BookUpdate, ClonedVoice, Collection, DatedTotals, DuplicateMatch,
+    ExportFormat, ImportResult, LexiconEntry, LexiconEntryInput, LibraryImportSummary,
+    LoadedLibrary, MetadataUpdate, NarrationPlan, NormalizationSettings, NowPlaying,
+    OpdsCredentials, OpdsFeed, OpdsServerSettings, OpdsServerStatus, PositionInput, PositionRecord,
+    ProviderVoice, ResolvedPosition, SavedVoice, SearchHit, SentenceSpan, Session, ShelfQuery,
+    ShelfSort, Shelves, SmartShelf, StatsOverview, TtsProviderInfo, TtsRequest, VoiceCloneRequest,
+    WatchedFolder,
 };
-use std::fs;
+use crate::minimax::MinimaxProvider;
+use crate::narration::NarrationState;
+use crate::opds_server::OpdsServerState;
+use crate::positions::{PositionState, PositionStore};
+use crate::search::SearchState;
+use crate::shelves::{ShelfContext, ShelfState};
+use crate::stats::{StatsState, StatsStore};
+use crate::tts::{NarratedBookState, TtsRegistry};
+use crate::watcher::{WatchState, WatchStore};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::{Manager, State};
 
 #[tauri::command]
-fn parse_epub(base64: String) -> Result<Book, String> {
-    epub::parse_epub(base64)
+fn parse_epub(app: tauri::AppHandle, base64: String) -> Result<Book, String> {
+    let bytes = epub::decode_base64(&base64)?;
+    let book = epub::parse_epub(bytes.clone())?;
+    importer::keep_source(&app, &book, &bytes);
+    covers::keep_thumbnails(&app, book.cover_base64.as_deref());
+    Ok(book)
 }
 
 #[tauri::command]
 fn save_library(app: tauri::AppHandle, library: Vec<BookEntry>) -> Result<(), String> {
     let path = config::get_library_path(&app)?;
-    let json = serde_json::to_string(&library)
-        .map_err(|e| format!("Failed to serialize library: {}", e))?;
-    fs::write(path, json)
-        .map_err(|e| format!("Failed to write library file: {}", e))?;
+    let _guard = library::lock(&app)?;
+    let library = library::save_from_frontend(&path, library)?;
+    search::update_index(&app, &library);
     Ok(())
 }
 
 #[tauri::command]
-fn load_library(app: tauri::AppHandle) -> Result<Vec<BookEntry>, String> {
+fn load_library(app: tauri::AppHandle) -> Result<LoadedLibrary, String> {
     let path = config::get_library_path(&app)?;
-    if !path.exists() {
-        return Ok(Vec::new());
+    let mut loaded = library::load(&path)?;
+    loaded.recovery = library::take_recovery(&app)?.or(loaded.recovery);
+    Ok(loaded)
+}
+
+#[tauri::command]
+fn find_duplicate_books(
+    app: tauri::AppHandle,
+    content_hash: Option<String>,
+    identifier: Option<String>,
+) -> Result<Vec<DuplicateMatch>, String> {
+    let path = config::get_library_path(&app)?;
+    let library = library::load(&path)?.books;
+    Ok(reimport::find_duplicates(
+        &library,
+        content_hash.as_deref(),
+        identifier.as_deref(),
+    ))
+}
+
+#[tauri::command]
+fn update_existing_book(
+    app: tauri::AppHandle,
+    book_id: String,
+    book: Book,
+) -> Result<BookUpdate, String> {
+    importer::update_book(&app, &book_id, book)
+}
+
+#[tauri::command]
+fn search_library(
+    app: tauri::AppHandle,
+    search: State<'_, SearchState>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<SearchHit>, String> {
+    let library_path = config::get_library_path(&app)?;
+    let index_path = config::get_search_index_path(&app)?;
+    let books = library::load(&library_path)?.books;
+    search::sync_and_save(&search, &index_path, &books)?;
+    let guard = search
+        .0
+        .lock()
+        .map_err(|_| "Search index lock poisoned".to_string())?;
+    Ok(guard
+        .as_ref()
+        .map(|index| index.search(&books, &query, limit))
+        .unwrap_or_default())
+}
+
+#[tauri::command]
+fn get_shelves(app: tauri::AppHandle) -> Result<Shelves, String> {
+    storage::read_json(&config::get_shelves_path(&app)?)
+}
+
+#[tauri::command]
+fn create_collection(app: tauri::AppHandle, name: String) -> Result<Collection, String> {
+    let path = config::get_shelves_path(&app)?;
+    let _guard = shelves::lock(&app)?;
+    let mut shelves: Shelves = storage::read_json(&path)?;
+    let collection = shelves::create_collection(&mut shelves, name);
+    storage::write_json(&path, &shelves)?;
+    Ok(collection)
+}
+
+#[tauri::command]
+fn update_collection(app: tauri::AppHandle, collection: Collection) -> Result<Collection, String> {
+    let path = config::get_shelves_path(&app)?;
+    let _guard = shelves::lock(&app)?;
+    let mut shelves: Shelves = storage::read_json(&path)?;
+    let existing = shelves
+        .collections
+        .iter_mut()
+        .find(|item| item.id == collection.id)
+        .ok_or_else(|| format!("Collection {} not found", collection.id))?;
+    existing.name = collection.name;
+    existing.book_ids = collection.book_ids;
+    let updated = existing.clone();
+    storage::write_json(&path, &shelves)?;
+    Ok(updated)
+}
+
+#[tauri::command]
+fn delete_collection(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    let path = config::get_shelves_path(&app)?;
+    let _guard = shelves::lock(&app)?;
+    let mut shelves: Shelves = storage::read_json(&path)?;
+    shelves.collections.retain(|item| item.id != id);
+    storage::write_json(&path, &shelves)
+}
+
+#[tauri::command]
+fn create_smart_shelf(
+    app: tauri::AppHandle,
+    name: String,
+    query: ShelfQuery,
+    sort: Option<ShelfSort>,
+) -> Result<SmartShelf, String> {
+    let path = config::get_shelves_path(&app)?;
+    let _guard = shelves::lock(&app)?;
+    let mut shelves: Shelves = storage::read_json(&path)?;
+    let shelf = shelves::create_smart_shelf(&mut shelves, name, query, sort.unwrap_or_default());
+    storage::write_json(&path, &shelves)?;
+    Ok(shelf)
+}
+
+#[tauri::command]
+fn update_smart_shelf(app: tauri::AppHandle, shelf: SmartShelf) -> Result<SmartShelf, String> {
+    let path = config::get_shelves_path(&app)?;
+    let _guard = shelves::lock(&app)?;
+    let mut shelves: Shelves = storage::read_json(&path)?;
+    let existing = shelves
+        .smart_shelves
+        .iter_mut()
+        .find(|item| item.id == shelf.id)
+        .ok_or_else(|| format!("Smart shelf {} not found", shelf.id))?;
+    *existing = shelf.clone();
+    storage::write_json(&path, &shelves)?;
+    Ok(shelf)
+}
+
+#[tauri::command]
+fn delete_smart_shelf(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    let path = config::get_shelves_path(&app)?;
+    let _guard = shelves::lock(&app)?;
+    let mut shelves: Shelves = storage::read_json(&path)?;
+    shelves.smart_shelves.retain(|item| item.id != id);
+    storage::write_json(&path, &shelves)
+}
+
+#[tauri::command]
+fn evaluate_smart_shelf(app: tauri::AppHandle, id: String) -> Result<Vec<String>, String> {
+    let shelves: Shelves = storage::read_json(&config::get_shelves_path(&app)?)?;
+    let shelf = shelves
+        .smart_shelves
+        .iter()
+        .find(|item| item.id == id)
+        .ok_or_else(|| format!("Smart shelf {} not found", id))?;
+    evaluate_shelf_query(&app, &shelves, &shelf.query, shelf.sort)
+}
+
+#[tauri::command]
+fn query_library(
+    app: tauri::AppHandle,
+    query: ShelfQuery,
+    sort: Option<ShelfSort>,
+) -> Result<Vec<String>, String> {
+    let shelves: Shelves = storage::read_json(&config::get_shelves_path(&app)?)?;
+    evaluate_shelf_query(&app, &shelves, &query, sort.unwrap_or_default())
+}
+
+#[tauri::command]
+fn set_book_tags(
+    app: tauri::AppHandle,
+    book_id: String,
+    tags: Vec<String>,
+) -> Result<BookEntry, String> {
+    let path = config::get_library_path(&app)?;
+    let _guard = library::lock(&app)?;
+    let mut books = library::load(&path)?.books;
+    let entry = books
+        .iter_mut()
+        .find(|entry| entry.id == book_id)
+        .ok_or_else(|| format!("Book {} not found in library", book_id))?;
+    entry.tags = shelves::normalize_tags(tags);
+    let updated = entry.clone();
+    library::save(&path, &books)?;
+    Ok(updated)
+}
+
+#[tauri::command]
+fn update_book_metadata(
+    app: tauri::AppHandle,
+    book_id: String,
+    metadata: MetadataUpdate,
+    write_to_file: Option<bool>,
+) -> Result<BookEntry, String> {
+    metadata::edit_book(&app, &book_id, write_to_file.unwrap_or(false), false, |entry| {
+        metadata::apply(entry, metadata)
+    })
+}
+
+#[tauri::command]
+fn replace_book_cover(
+    app: tauri::AppHandle,
+    book_id: String,
+    path: String,
+    write_to_file: Option<bool>,
+) -> Result<BookEntry, String> {
+    let (cover, mime) = metadata::read_cover(Path::new(&path))?;
+    let updated = metadata::edit_book(&app, &book_id, write_to_file.unwrap_or(false), true, |entry| {
+        entry.cover_base64 = Some(cover);
+        entry.cover_mime = Some(mime);
+        Ok(())
+    })?;
+    covers::keep_thumbnails(&app, updated.cover_base64.as_deref());
+    Ok(updated)
+}
+
+#[tauri::command]
+fn save_reading_position(
+    app: tauri::AppHandle,
+    stats: State<'_, StatsState>,
+    book_id: String,
+    position: PositionInput,
+) -> Result<PositionRecord, String> {
+    let book = find_book(&app, &book_id)?;
+    let locator = positions::locate(&book, &position)?;
+    let progress = locator.progress;
+
+    let path = config::get_positions_path(&app)?;
+    let guard = positions::lock(&app)?;
+    let mut store: PositionStore = storage::read_json(&path)?;
+    let record = positions::record(&mut store, &book_id, locator);
+    storage::write_json(&path, &store)?;
+    drop(guard);
+
+    let activity_path = config::get_activity_path(&app)?;
+    let mut activity: HashMap<String, BookActivity> = storage::read_json(&activity_path)?;
+    activity.insert(
+        book_id,
+        BookActivity {
+            progress,
+            last_read_at: Some(record.saved_at.clone()),
+        },
+    );
+    storage::write_json(&activity_path, &activity)?;
+
+    let recorded = config::get_stats_path(&app)
+        .and_then(|path| stats::record_reading(&stats, &path, &book, progress));
+    if let Err(error) = recorded {
+        println!("DEBUG WARNING: Failed to record reading statistics: {}", error);
+    }
+    Ok(record)
+}
+
+#[tauri::command]
+fn restore_reading_position(
+    app: tauri::AppHandle,
+    book_id: String,
+) -> Result<Option<ResolvedPosition>, String> {
+    let store: PositionStore = storage::read_json(&config::get_positions_path(&app)?)?;
+    let Some(record) = store.get(&book_id).and_then(|entry| entry.current.as_ref()) else {
+        return Ok(None);
+    };
+    let book = find_book(&app, &book_id)?;
+    Ok(positions::resolve(&book, &record.locator))
+}
+
+#[tauri::command]
+fn get_position_history(
+    app: tauri::AppHandle,
+    book_id: String,
+) -> Result<Vec<PositionRecord>, String> {
+    let store: PositionStore = storage::read_json(&config::get_positions_path(&app)?)?;
+    Ok(store
+        .get(&book_id)
+        .map(|entry| entry.history.clone())
+        .unwrap_or_default())
+}
+
+#[tauri::command]
+fn create_annotation(
+    app: tauri::AppHandle,
+    book_id: String,
+    annotation: AnnotationInput,
+) -> Result<Annotation, String> {
+    let book = find_book(&app, &book_id)?;
+    let path = config::get_annotations_path(&app)?;
+    let _guard = annotations::lock(&app)?;
+    let mut store: AnnotationStore = storage::read_json(&path)?;
+    let created = annotations::create(&mut store, &book, annotation)?;
+    storage::write_json(&path, &store)?;
+    Ok(created)
+}
+
+#[tauri::command]
+fn update_annotation(
+    app: tauri::AppHandle,
+    id: String,
+    update: AnnotationUpdate,
+) -> Result<Annotation, String> {
+    let path = config::get_annotations_path(&app)?;
+    let _guard = annotations::lock(&app)?;
+    let mut store: AnnotationStore = storage::read_json(&path)?;
+    let updated = annotations::update(&mut store, &id, update)?;
+    storage::write_json(&path, &store)?;
+    Ok(updated)
+}
+
+#[tauri::command]
+fn delete_annotation(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    let path = config::get_annotations_path(&app)?;
+    let _guard = annotations::lock(&app)?;
+    let mut store: AnnotationStore = storage::read_json(&path)?;
+    if !annotations::delete(&mut store, &id) {
+        return Err(format!("Annotation {} not found", id));
     }
-    let json = fs::read_to_string(path)
-        .map_err(|e| format!("Failed to read library file: {}", e))?;
-    let library: Vec<BookEntry> = serde_json::from_str(&json)
-        .map_err(|e| format!("Failed to deserialize library: {}", e))?;
-    Ok(library)
+    storage::write_json(&path, &store)
+}
+
+#[tauri::command]
+fn list_annotations(app: tauri::AppHandle, book_id: String) -> Result<Vec<Annotation>, String> {
+    let book = find_book(&app, &book_id)?;
+    let store: AnnotationStore = storage::read_json(&config::get_annotations_path(&app)?)?;
+    Ok(annotations::list(&store, &book))
+}
+
+/// Writes the book's highlights and notes into `directory` and returns the
+/// path of the created file.
+#[tauri::command]
+fn export_annotations(
+    app: tauri::AppHandle,
+    book_id: String,
+    format: ExportFormat,
+    directory: String,
+) -> Result<String, String> {
+    let book = find_book(&app, &book_id)?;
+    let store: AnnotationStore = storage::read_json(&config::get_annotations_path(&app)?)?;
+    let content = export::render(&book, &annotations::list(&store, &book), format)?;
+    let path = PathBuf::from(directory).join(export::file_name(&book, format));
+    storage::write_atomic(&path, content.as_bytes())?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Writes a zip with one export per annotated book to `path` and returns how
+/// many books it contains.
+#[tauri::command]
+fn export_all_annotations(
+    app: tauri::AppHandle,
+    format: ExportFormat,
+    path: String,
+) -> Result<usize, String> {
+    let books = library::load(&config::get_library_path(&app)?)?.books;
+    let store: AnnotationStore = storage::read_json(&config::get_annotations_path(&app)?)?;
+    export::write_zip(
+        &PathBuf::from(path),
+        &books,
+        |book| annotations::list(&store, book),
+        format,
+    )
+}
+
+/// Writes a backup archive to `path`. Settings and voices are passed in by
+/// the frontend, which keeps them in local storage.
+#[tauri::command]
+fn export_backup(
+    app: tauri::AppHandle,
+    path: String,
+    settings: Option<serde_json::Value>,
+    voices: Vec<SavedVoice>,
+    include_audio: bool,
+) -> Result<BackupManifest, String> {
+    let client = ClientState { settings, voices };
+    backup::export(&data_paths(&app)?, &client, include_audio, &PathBuf::from(path))
+}
+
+/// Merges a backup archive into the library. The returned summary carries
+/// the merged library, which replaces the frontend's copy.
+#[tauri::command]
+fn import_backup(
+    app: tauri::AppHandle,
+    path: String,
+    options: Option<BackupImportOptions>,
+) -> Result<BackupImportSummary, String> {
+    let options = options.unwrap_or_default();
+    // Stores are locked in the order the importer takes them: the library,
+    // then positions, annotations and shelves.
+    let guard = library::lock(&app)?;
+    let positions_guard = positions::lock(&app)?;
+    let annotations_guard = annotations::lock(&app)?;
+    let shelves_guard = shelves::lock(&app)?;
+    let summary = backup::import(&data_paths(&app)?, &PathBuf::from(path), &options)?;
+    drop(shelves_guard);
+    drop(annotations_guard);
+    drop(positions_guard);
+    drop(guard);
+    search::update_index(&app, &summary.library);
+    if summary.audio_files_restored > 0 {
+        audio_cache::reload(&app.state::<AudioCacheState>())?;
+    }
+    // Restored watched folders and catalog settings take effect right away.
+    if options.restore_settings {
+        watcher::restart(&app)?;
+        opds_server::restart(&app)?;
+    }
+    Ok(summary)
+}
+
+/// Imports a Calibre library folder. Runs off the main thread because large
+/// libraries take a while; progress arrives as `calibre-import-progress`
+/// events and the summary carries the updated library.
+#[tauri::command]
+async fn import_calibre_library(
+    app: tauri::AppHandle,
+    path: String,
+) -> Result<LibraryImportSummary, String> {
+    tauri::async_runtime::spawn_blocking(move || calibre::import_library(&app, Path::new(&path)))
+        .await
+        .map_err(|error| format!("Calibre import stopped: {error}"))?
+}
+
+#[tauri::command]
+async fn opds_browse(
+    http: State<'_, Http>,
+    url: String,
+    credentials: Option<OpdsCredentials>,
+) -> Result<OpdsFeed, String> {
+    opds::browse(&http, &url, credentials.as_ref()).await
+}
+
+#[tauri::command]
+async fn opds_search(
+    http: State<'_, Http>,
+    search_url: String,
+    query: String,
+    credentials: Option<OpdsCredentials>,
+) -> Result<OpdsFeed, String> {
+    opds::search(&http, &search_url, &query, credentials.as_ref()).await
+}
+
+#[tauri::command]
+async fn opds_download(
+    app: tauri::AppHandle,
+    http: State<'_, Http>,
+    url: String,
+    credentials: Option<OpdsCredentials>,
+) -> Result<ImportResult, String> {
+    opds::download(&app, &http, &url, credentials.as_ref()).await
+}
+
+#[tauri::command]
+fn get_opds_server_status(app: tauri::AppHandle) -> Result<OpdsServerStatus, String> {
+    opds_server::status(&app)
+}
+
+/// Saves the catalog server settings and restarts it with them.
+#[tauri::command]
+fn set_opds_server_settings(
+    app: tauri::AppHandle,
+    settings: OpdsServerSettings,
+) -> Result<OpdsServerStatus, String> {
+    opds_server::check_exposure(&settings)?;
+    opds_server::save_settings(&app, &settings)?;
+    opds_server::restart(&app)
+}
+
+#[tauri::command]
+fn get_watch_folders(app: tauri::AppHandle) -> Result<Vec<WatchedFolder>, String> {
+    let store: WatchStore = storage::read_json(&config::get_watch_folders_path(&app)?)?;
+    Ok(store.folders)
+}
+
+#[tauri::command]
+fn add_watch_folder(
+    app: tauri::AppHandle,
+    path: String,
+    remove_on_delete: Option<bool>,
+) -> Result<WatchedFolder, String> {
+    let folder = watcher::update_store(&app, |store| {
+        watcher::add_folder(store, &path, remove_on_delete.unwrap_or(false))
+    })?;
+    watcher::restart(&app)?;
+    Ok(folder)
+}
+
+#[tauri::command]
+fn update_watch_folder(
+    app: tauri::AppHandle,
+    folder: WatchedFolder,
+) -> Result<WatchedFolder, String> {
+    watcher::update_store(&app, |store| watcher::update_folder(store, folder))
+}
+
+#[tauri::command]
+fn remove_watch_folder(app: tauri::AppHandle, path: String) -> Result<(), String> {
+    watcher::update_store(&app, |store| watcher::remove_folder(store, &path))?;
+    watcher::restart(&app)
+}
+
+#[tauri::command]
+fn report_narration(
+    app: tauri::AppHandle,
+    narration: State<'_, NarrationState>,
+    stats: State<'_, StatsState>,
+    now_playing: Option<NowPlaying>,
+) -> Result<(), String> {
+    let recorded = config::get_stats_path(&app)
+        .and_then(|path| stats::record_listening(&stats, &path, now_playing.as_ref()));
+    if let Err(error) = recorded {
+        println!("DEBUG WARNING: Failed to record listening statistics: {}", error);
+    }
+    narration.set(now_playing)
+}
+
+#[tauri::command]
+fn get_stats_overview(app: tauri::AppHandle, days: Option<u32>) -> Result<StatsOverview, String> {
+    let store: StatsStore = storage::read_json(&config::get_stats_path(&app)?)?;
+    Ok(stats::overview(&store, days.unwrap_or(30)))
+}
+
+#[tauri::command]
+fn get_weekly_stats(app: tauri::AppHandle, weeks: Option<u32>) -> Result<Vec<DatedTotals>, String> {
+    let store: StatsStore = storage::read_json(&config::get_stats_path(&app)?)?;
+    Ok(stats::weekly(&store, weeks.unwrap_or(12)))
+}
+
+#[tauri::command]
+fn get_book_stats(app: tauri::AppHandle, book_id: String) -> Result<BookStats, String> {
+    let book = find_book(&app, &book_id)?;
+    let store: StatsStore = storage::read_json(&config::get_stats_path(&app)?)?;
+    let activity: HashMap<String, BookActivity> =
+        storage::read_json(&config::get_activity_path(&app)?)?;
+    Ok(stats::book_stats(&store, &book, activity.get(&book_id)))
+}
+
+#[tauri::command]
+fn get_top_books(
+    app: tauri::AppHandle,
+    limit: Option<usize>,
+    days: Option<u32>,
+) -> Result<Vec<BookStats>, String> {
+    let books = library::load(&config::get_library_path(&app)?)?.books;
+    let store: StatsStore = storage::read_json(&config::get_stats_path(&app)?)?;
+    let activity: HashMap<String, BookActivity> =
+        storage::read_json(&config::get_activity_path(&app)?)?;
+    Ok(stats::top_books(&store, &books, &activity, limit.unwrap_or(10), days))
+}
+
+#[tauri::command]
+fn get_sessions(
+    app: tauri::AppHandle,
+    book_id: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<Session>, String> {
+    let store: StatsStore = storage::read_json(&config::get_stats_path(&app)?)?;
+    Ok(stats::sessions(&store, book_id.as_deref(), limit.unwrap_or(50)))
+}
+
+#[tauri::command]
+fn bookmark_current_narration(
+    app: tauri::AppHandle,
+    narration: State<'_, NarrationState>,
+    note: Option<String>,
+) -> Result<Annotation, String> {
+    let now_playing = narration
+        .current()?
+        .ok_or_else(|| "Nothing is being narrated.".to_string())?;
+    let book = find_book(&app, &now_playing.book_id)?;
+    let input = AnnotationInput {
+        kind: AnnotationKind::Bookmark,
+        position: PositionInput {
+            chapter_id: now_playing.chapter_id,
+            sentence_text: Some(now_playing.sentence_text),
+            progress: now_playing.progress,
+            ..PositionInput::default()
+        },
+        color: None,
+        note,
+    };
+    let path = config::get_annotations_path(&app)?;
+    let _guard = annotations::lock(&app)?;
+    let mut store: AnnotationStore = storage::read_json(&path)?;
+    let created = annotations::create(&mut store, &book, input)?;
+    storage::write_json(&path, &store)?;
+    Ok(created)
+}
+
+fn find_book(app: &tauri::AppHandle, book_id: &str) -> Result<BookEntry, String> {
+    library::load(&config::get_library_path(app)?)?
+        .books
+        .into_iter()
+        .find(|entry| entry.id == book_id)
+        .ok_or_else(|| format!("Book {} not found in library", book_id))
+}
+
+fn data_paths(app: &tauri::AppHandle) -> Result<DataPaths, String> {
+    Ok(DataPaths {
+        library: config::get_library_path(app)?,
+        positions: config::get_positions_path(app)?,
+        annotations: config::get_annotations_path(app)?,
+        shelves: config::get_shelves_path(app)?,
+        activity: config::get_activity_path(app)?,
+        lexicon: config::get_lexicon_path(app)?,
+        stats: config::get_stats_path(app)?,
+        normalization: config::get_normalization_path(app)?,
+        watch_folders: config::get_watch_folders_path(app)?,
+        opds_server: config::get_opds_server_path(app)?,
+        sources_dir: config::get_sources_dir(app)?,
+        audio_dir: config::get_audio_cache_dir(app)?,
+    })
+}
+
+fn evaluate_shelf_query(
+    app: &tauri::AppHandle,
+    shelves: &Shelves,
+    query: &ShelfQuery,
+    sort: ShelfSort,
+) -> Result<Vec<String>, String> {
+    let books = library::load(&config::get_library_path(app)?)?.books;
+    let activity: HashMap<String, BookActivity> =
+        storage::read_json(&config::get_activity_path(app)?)?;
+    let cached_audio = audio_cache::books_with_audio(app, &app.state::<AudioCacheState>())?;
+    let context = ShelfContext {
+        shelves,
+        activity: &activity,
+        cached_audio: &cached_audio,
+    };
+    shelves::evaluate(&books, &context, query, sort)
+}
+
+#[tauri::command]
+fn get_audio_cache_stats(
+    app: tauri::AppHandle,
+    cache: State<AudioCacheState>,
+) -> Result<AudioCacheStats, String> {
+    audio_cache::stats(&app, &cache)
+}
+
+#[tauri::command]
+fn set_audio_cache_limit(
+    app: tauri::AppHandle,
+    cache: State<AudioCacheState>,
+    max_bytes: u64,
+) -> Result<AudioCacheStats, String> {
+    audio_cache::set_limit(&app, &cache, max_bytes)
+}
+
+/// Deletes cached audio for one book, or all of it when `book_id` is omitted.
+#[tauri::command]
+fn purge_audio_cache(
+    app: tauri::AppHandle,
+    cache: State<AudioCacheState>,
+    book_id: Option<String>,
+) -> Result<AudioCacheStats, String> {
+    audio_cache::purge(&app, &cache, book_id.as_deref())
+}
+
+#[tauri::command]
+fn split_sentences(text: String, language: Option<String>) -> Vec<SentenceSpan> {
+    segment::sentences(&text, language.as_deref())
+}
+
+/// Splits a chapter into sentences and groups them into chunks sized for
+/// `provider`'s request limit.
+#[tauri::command]
+fn plan_chapter_narration(
+    app: tauri::AppHandle,
+    registry: State<TtsRegistry>,
+    book_id: String,
+    chapter_id: String,
+    provider: Option<String>,
+    target_characters: Option<usize>,
+) -> Result<NarrationPlan, String> {
+    // Plans are fetched chapter by chapter, so the book is kept between them.
+    let book = tts::find_book(&app, &book_id)?
+        .ok_or_else(|| format!("Book {} not found in library", book_id))?;
+    let chapter = book
+        .chapters
+        .iter()
+        .find(|chapter| chapter.id == chapter_id)
+        .ok_or_else(|| format!("Chapter {} not found in book {}", chapter_id, book_id))?;
+    let max_characters = match provider {
+        Some(provider) => tts::capabilities(&registry, &provider)?.max_characters,
+        None => None,
+    };
+    let sentences = segment::sentences(&chapter.text, book.language.as_deref());
+    let chunks = segment::plan(
+        &chapter.id,
+        &sentences,
+        target_characters.unwrap_or(segment::DEFAULT_TARGET_CHARACTERS),
+        max_characters,
+    );
+    Ok(NarrationPlan {
+        chapter_id,
+        sentences,
+        chunks,
+    })
+}
+
+#[tauri::command]
+fn get_normalization_settings(
+    app: tauri::AppHandle,
+    book_id: String,
+) -> Result<NormalizationSettings, String> {
+    normalize::load_settings(&app, Some(&book_id))
+}
+
+#[tauri::command]
+fn set_normalization_settings(
+    app: tauri::AppHandle,
+    book_id: String,
+    settings: NormalizationSettings,
+) -> Result<(), String> {
+    normalize::save_settings(&app, &book_id, settings)
+}
+
+/// Shows what a provider would be sent for `text`. `settings` previews
+/// toggles before they are saved; otherwise the book's settings apply.
+#[tauri::command]
+fn preview_normalization(
+    app: tauri::AppHandle,
+    text: String,
+    book_id: Option<String>,
+    language: Option<String>,
+    settings: Option<NormalizationSettings>,
+) -> Result<String, String> {
+    let settings = match settings {
+        Some(settings) => settings,
+        None => normalize::load_settings(&app, book_id.as_deref())?,
+    };
+    Ok(normalize::normalize(&text, language.as_deref(), &settings))
+}
+
+/// The entries of one lexicon: a book's, or the global one without `book_id`.
+#[tauri::command]
+fn get_lexicon(
+    app: tauri::AppHandle,
+    book_id: Option<String>,
+) -> Result<Vec<LexiconEntry>, String> {
+    let store: LexiconStore = storage::read_json(&config::get_lexicon_path(&app)?)?;
+    Ok(lexicon::entries(&store, book_id.as_deref()).to_vec())
+}
+
+#[tauri::command]
+fn save_lexicon_entry(
+    app: tauri::AppHandle,
+    book_id: Option<String>,
+    entry: LexiconEntryInput,
+) -> Result<LexiconEntry, String> {
+    if let Some(book_id) = &book_id {
+        find_book(&app, book_id)?;
+    }
+    let path = config::get_lexicon_path(&app)?;
+    let mut store: LexiconStore = storage::read_json(&path)?;
+    let saved = lexicon::upsert(&mut store, book_id.as_deref(), entry)?;
+    storage::write_json(&path, &store)?;
+    Ok(saved)
+}
+
+#[tauri::command]
+fn delete_lexicon_entry(
+    app: tauri::AppHandle,
+    book_id: Option<String>,
+    id: String,
+) -> Result<(), String> {
+    let path = config::get_lexicon_path(&app)?;
+    let mut store: LexiconStore = storage::read_json(&path)?;
+    lexicon::remove(&mut store, book_id.as_deref(), &id)?;
+    storage::write_json(&path, &store)
+}
+
+/// Speaks `entry`'s word with `provider`, so a pronunciation can be tried
+/// before it is saved.
+#[tauri::command]
+async fn audition_pronunciation(
+    registry: State<'_, TtsRegistry>,
+    provider: String,
+    options: Option<serde_json::Value>,
+    entry: LexiconEntryInput,
+) -> Result<AudioClip, String> {
+    let entry = lexicon::validate(entry)?;
+    tts::audition(&registry, &provider, options.unwrap_or_default(), entry).await
+}
+
+#[tauri::command]
+fn tts_providers(registry: State<TtsRegistry>) -> Vec<TtsProviderInfo> {
+    tts::providers(&registry)
+}
+
+/// Synthesizes `request`. Given a `job_id`, the synthesis can be stopped
+/// with `cancel_tts`, and replaces any still running under that id.
+#[tauri::command]
+async fn tts_generate(
+    app: tauri::AppHandle,
+    registry: State<'_, TtsRegistry>,
+    cache: State<'_, AudioCacheState>,
+    jobs: State<'_, SynthesisJobs>,
+    job_id: Option<String>,
+    request: TtsRequest,
+) -> Result<AudioClip, String> {
+    let Some(job_id) = job_id else {
+        return tts::synthesize(&app, &registry, &cache, request, &Cancellation::default()).await;
+    };
+    let cancellation = jobs.start(&job_id)?;
+    let result = tts::synthesize(&app, &registry, &cache, request, &cancellation).await;
+    jobs.finish(&job_id, &cancellation);
+    result
 }
 
+/// Synthesizes like `tts_generate`, sending audio over `channel` as the
+/// provider produces it.
 #[tauri::command]
-async fn tts_generate(request: TtsRequest) -> Result<AudioClip, String> {
-    tts::synthesize(request).await
+async fn tts_stream(
+    app: tauri::AppHandle,
+    registry: State<'_, TtsRegistry>,
+    cache: State<'_, AudioCacheState>,
+    jobs: State<'_, SynthesisJobs>,
+    job_id: String,
+    request: TtsRequest,
+    channel: tauri::ipc::Channel<AudioStreamEvent>,
+) -> Result<(), String> {
+    let cancellation = jobs.start(&job_id)?;
+    let result = tts::stream(&app, &registry, &cache, request, &channel, &cancellation).await;
+    jobs.finish(&job_id, &cancellation);
+    result
 }
 
+/// Stops the synthesis `job_id`, or every one without an id.
 #[tauri::command]
-async fn minimax_upload_clone_audio(
-    request: MinimaxUploadRequest,
-) -> Result<MinimaxUploadResponse, String> {
-    minimax::upload_clone_audio(request).await
+fn cancel_tts(jobs: State<SynthesisJobs>, job_id: Option<String>) -> Result<usize, String> {
+    jobs.cancel(job_id.as_deref())
 }
 
 #[tauri::command]
-async fn minimax_create_clone(
-    request: MinimaxCloneRequest,
-) -> Result<MinimaxCloneResponse, String> {
-    minimax::create_clone(request).await
+async fn tts_list_voices(
+    registry: State<'_, TtsRegistry>,
+    provider: String,
+    options: Option<serde_json::Value>,
+) -> Result<Vec<ProviderVoice>, String> {
+    tts::list_voices(&registry, &provider, options.unwrap_or_default()).await
 }
 
 #[tauri::command]
-async fn elevenlabs_create_clone(
-    request: ElevenLabsCloneRequest,
-) -> Result<ElevenLabsCloneResponse, String> {
-    elevenlabs::create_clone(request).await
+async fn tts_clone_voice(
+    registry: State<'_, TtsRegistry>,
+    provider: String,
+    request: VoiceCloneRequest,
+) -> Result<ClonedVoice, String> {
+    tts::clone_voice(&registry, &provider, request).await
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     config::load_env();
+    let http = Http::from_config();
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .manage(LibraryState::default())
+        .manage(PositionState::default())
+        .manage(AnnotationState::default())
+        .manage(ShelfState::default())
+        .manage(SearchState::default())
+        .manage(NarrationState::default())
+        .manage(StatsState::default())
+        .manage(CoverState::default())
+        .manage(WatchState::default())
+        .manage(OpdsServerState::default())
+        .manage(AudioCacheState::default())
+        .manage(SynthesisJobs::default())
+        .manage(NarratedBookState::default())
+        .manage(
+            TtsRegistry::default()
+                .with(ExternalProvider::new(http.clone()))
+                .with(MinimaxProvider::new(http.clone()))
+                .with(ElevenLabsProvider::new(http.clone()))
+                .with(LocalProvider::default()),
+        )
+        .manage(http)
+        .setup(|app| {
+            let recovered = config::get_library_path(app.handle())
+                .and_then(|path| library::recover_at_startup(app.handle(), &path));
+            if let Err(error) = recovered {
+                println!("DEBUG WARNING: Failed to recover library: {}", error);
+            }
+            if let Err(error) = watcher::restart(app.handle()) {
+                println!("DEBUG WARNING: Failed to start folder watcher: {}", error);
+            }
+            if let Err(error) = opds_server::restart(app.handle()) {
+                println!("DEBUG WARNING: Failed to start OPDS server: {}", error);
+            }
+            Ok(())
+        })
+        .register_asynchronous_uri_scheme_protocol(
+            covers::COVER_SCHEME,
+            |context, request, responder| {
+                let app = context.app_handle().clone();
+                std::thread::spawn(move || responder.respond(covers::handle(&app, &request)));
+            },
+        )
         .invoke_handler(tauri::generate_handler![
             parse_epub,
             save_library,
             load_library,
+            find_duplicate_books,
+            update_existing_book,
+            search_library,
+            get_shelves,
+            create_collection,
+            update_collection,
+            delete_collection,
+            create_smart_shelf,
+            update_smart_shelf,
+            delete_smart_shelf,
+            evaluate_smart_shelf,
+            query_library,
+            set_book_tags,
+            update_book_metadata,
+            replace_book_cover,
+            save_reading_position,
+            restore_reading_position,
+            get_position_history,
+            create_annotation,
+            update_annotation,
+            delete_annotation,
+            list_annotations,
+            export_annotations,
+            export_all_annotations,
+            export_backup,
+            import_backup,
+            get_watch_folders,
+            add_watch_folder,
+            update_watch_folder,
+            remove_watch_folder,
+            import_calibre_library,
+            opds_browse,
+            opds_search,
+            opds_download,
+            get_opds_server_status,
+            set_opds_server_settings,
+            report_narration,
+            get_stats_overview,
+            get_weekly_stats,
+            get_book_stats,
+            get_top_books,
+            get_sessions,
+            bookmark_current_narration,
+            get_audio_cache_stats,
+            set_audio_cache_limit,
+            purge_audio_cache,
+            split_sentences,
+            plan_chapter_narration,
+            get_normalization_settings,
+            set_normalization_settings,
+            preview_normalization,
+            get_lexicon,
+            save_lexicon_entry,
+            delete_lexicon_entry,
+            audition_pronunciation,
+            tts_providers,
             tts_generate,
-            minimax_upload_clone_audio,
-            minimax_create_clone,
-            elevenlabs_create_clone
+            tts_stream,
+            cancel_tts,
+            tts_list_voices,
+            tts_clone_voice
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
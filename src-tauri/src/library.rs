@@ -0,0 +1,390 @@
+use crate::epub;
+use crate::models::{BookEntry, LibraryRecovery, LoadedLibrary};
+use crate::storage;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use tauri::{AppHandle, Manager};
+
+/// Current on-disk layout of `library.json`. Bump this and append a
+/// migration to `MIGRATIONS` whenever the stored shape of `BookEntry` changes.
+pub const SCHEMA_VERSION: u64 = 3;
+
+const BACKUP_COUNT: usize = 5;
+
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// `MIGRATIONS[n]` upgrades a document from version `n + 1` to `n + 2`.
+const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2, migrate_v2_to_v3];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LibraryDocument<'a> {
+    schema_version: u64,
+    books: &'a [BookEntry],
+}
+
+/// Kept in Tauri state so that changes to `library.json` from commands,
+/// the folder watcher and batch imports do not overwrite each other. Hold
+/// the guard from `lock` from loading the library until it is saved.
+#[derive(Default)]
+pub struct LibraryState {
+    lock: Mutex<()>,
+    /// What `recover_at_startup` restored, until the frontend is told.
+    recovery: Mutex<Option<LibraryRecovery>>,
+}
+
+pub fn lock(app: &AppHandle) -> Result<MutexGuard<'_, ()>, String> {
+    app.state::<LibraryState>()
+        .inner()
+        .lock
+        .lock()
+        .map_err(|_| "Library lock poisoned".to_string())
+}
+
+/// Restores an unreadable `library.json` from its backups before anything
+/// else reads it. Runs once at startup, under the library lock.
+pub fn recover_at_startup(app: &AppHandle, path: &Path) -> Result<(), String> {
+    let _guard = lock(app)?;
+    let recovery = recover(path)?;
+    *app.state::<LibraryState>()
+        .inner()
+        .recovery
+        .lock()
+        .map_err(|_| "Library lock poisoned".to_string())? = recovery;
+    Ok(())
+}
+
+/// The recovery made at startup, if any, handed out once.
+pub fn take_recovery(app: &AppHandle) -> Result<Option<LibraryRecovery>, String> {
+    Ok(app
+        .state::<LibraryState>()
+        .inner()
+        .recovery
+        .lock()
+        .map_err(|_| "Library lock poisoned".to_string())?
+        .take())
+}
+
+/// Saves the frontend's copy of the library. The frontend only adds and
+/// removes books, so books already stored keep their saved version, which
+/// may carry tags, metadata or covers edited since the frontend loaded it.
+pub fn save_from_frontend(path: &Path, books: Vec<BookEntry>) -> Result<Vec<BookEntry>, String> {
+    let mut stored = load(path)?.books;
+    let merged: Vec<BookEntry> = books
+        .into_iter()
+        .map(|book| {
+            let index = stored.iter().position(|existing| existing.id == book.id);
+            index.map_or(book, |index| stored.swap_remove(index))
+        })
+        .collect();
+    save(path, &merged)?;
+    Ok(merged)
+}
+
+pub fn save(path: &Path, books: &[BookEntry]) -> Result<(), String> {
+    let json = to_json(books)?;
+    storage::rotate_backups(path, BACKUP_COUNT)?;
+    storage::write_atomic(path, &json)
+}
+
+/// Serializes books as a versioned library document.
+pub fn to_json(books: &[BookEntry]) -> Result<Vec<u8>, String> {
+    let document = LibraryDocument {
+        schema_version: SCHEMA_VERSION,
+        books,
+    };
+    serde_json::to_vec(&document).map_err(|e| format!("Failed to serialize library: {}", e))
+}
+
+/// Reads `library.json` without changing it. Unreadable entries are
+/// skipped and reported; an unreadable file is left to `recover`.
+pub fn load(path: &Path) -> Result<LoadedLibrary, String> {
+    if !path.exists() {
+        return Ok(LoadedLibrary {
+            books: Vec::new(),
+            recovery: None,
+        });
+    }
+    let (books, skipped) = read_document(path)?;
+    let recovery = (!skipped.is_empty()).then(|| LibraryRecovery {
+        source: file_label(path),
+        errors: skipped,
+        book_count: books.len(),
+    });
+    Ok(LoadedLibrary { books, recovery })
+}
+
+/// Replaces a missing or unreadable library file with the newest readable
+/// backup, moving the broken file aside. Callers must hold the library lock.
+pub fn recover(path: &Path) -> Result<Option<LibraryRecovery>, String> {
+    let backups = storage::existing_backups(path, BACKUP_COUNT);
+    if backups.is_empty() {
+        return Ok(None);
+    }
+    let primary_error = match path.exists().then(|| read_document(path)) {
+        Some(Ok(_)) => return Ok(None),
+        Some(Err(error)) => error,
+        None => format!("{} is missing", file_label(path)),
+    };
+    println!("DEBUG WARNING: Library file unreadable: {}", primary_error);
+
+    for backup in backups {
+        match read_document(&backup) {
+            Ok((books, skipped)) => {
+                quarantine(path);
+                save(path, &books)?;
+                let mut errors = vec![primary_error];
+                errors.extend(skipped);
+                return Ok(Some(LibraryRecovery {
+                    source: file_label(&backup),
+                    errors,
+                    book_count: books.len(),
+                }));
+            }
+            Err(error) => {
+                println!("DEBUG WARNING: Backup {} unreadable: {}", backup.display(), error);
+            }
+        }
+    }
+
+    Err(primary_error)
+}
+
+/// Reads and migrates a library file.
+fn read_document(path: &Path) -> Result<(Vec<BookEntry>, Vec<String>), String> {
+    let json = fs::read(path)
+        .map_err(|e| format!("Failed to read {}: {}", file_label(path), e))?;
+    from_json(&json, &file_label(path))
+}
+
+/// Parses and migrates a library document from any supported schema version.
+/// Entries that still fail to deserialize after migration are dropped and
+/// described in the returned list instead of failing the whole library.
+pub fn from_json(json: &[u8], label: &str) -> Result<(Vec<BookEntry>, Vec<String>), String> {
+    let value: Value = serde_json::from_slice(json)
+        .map_err(|e| format!("Failed to parse {}: {}", label, e))?;
+    let value = migrate(value)?;
+
+    let entries = match value.get("books") {
+        Some(Value::Array(entries)) => entries.clone(),
+        _ => return Err(format!("{} has no books list", label)),
+    };
+
+    let mut books = Vec::with_capacity(entries.len());
+    let mut skipped = Vec::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        let label = entry
+            .get("title")
+            .and_then(Value::as_str)
+            .map(|title| format!("\"{}\"", title))
+            .unwrap_or_else(|| format!("entry {}", index + 1));
+        match serde_json::from_value::<BookEntry>(entry) {
+            Ok(book) => books.push(book),
+            Err(e) => skipped.push(format!("Skipped {}: {}", label, e)),
+        }
+    }
+    Ok((books, skipped))
+}
+
+fn migrate(mut value: Value) -> Result<Value, String> {
+    let mut version = schema_version_of(&value)?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "Library schema version {} is newer than supported version {}",
+            version, SCHEMA_VERSION
+        ));
+    }
+    while version < SCHEMA_VERSION {
+        let migration = MIGRATIONS[(version - 1) as usize];
+        value = migration(value)?;
+        version += 1;
+    }
+    Ok(value)
+}
+
+fn schema_version_of(value: &Value) -> Result<u64, String> {
+    match value {
+        // Version 1 stored the bare `Vec<BookEntry>`.
+        Value::Array(_) => Ok(1),
+        Value::Object(map) => map
+            .get("schemaVersion")
+            .and_then(Value::as_u64)
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| "Library file missing schemaVersion".to_string()),
+        _ => Err("Library file has an unrecognized layout".to_string()),
+    }
+}
+
+/// Wraps the bare array in a versioned document and backfills fields that
+/// early builds did not always write.
+fn migrate_v1_to_v2(value: Value) -> Result<Value, String> {
+    let Value::Array(mut books) = value else {
+        return Err("Expected version 1 library to be a list".to_string());
+    };
+    for (index, book) in books.iter_mut().enumerate() {
+        let Some(book) = book.as_object_mut() else {
+            continue;
+        };
+        book.entry("id")
+            .or_insert_with(|| Value::String(format!("book-legacy-{}", index + 1)));
+        book.entry("importedAt")
+            .or_insert_with(|| Value::String(String::new()));
+        book.entry("chapters").or_insert_with(|| json!([]));
+        if let Some(Value::Array(chapters)) = book.get_mut("chapters") {
+            for chapter in chapters.iter_mut().filter_map(Value::as_object_mut) {
+                if !chapter.contains_key("wordCount") {
+                    let words = chapter
+                        .get("text")
+                        .and_then(Value::as_str)
+                        .map(|text| text.split_whitespace().count())
+                        .unwrap_or(0);
+                    chapter.insert("wordCount".to_string(), json!(words));
+                }
+            }
+        }
+    }
+    Ok(json!({ "schemaVersion": 2, "books": books }))
+}
+
+/// Backfills per-chapter content hashes used to diff re-imported editions.
+fn migrate_v2_to_v3(mut value: Value) -> Result<Value, String> {
+    if let Some(Value::Array(books)) = value.get_mut("books") {
+        for book in books.iter_mut() {
+            let Some(Value::Array(chapters)) = book.get_mut("chapters") else {
+                continue;
+            };
+            for chapter in chapters.iter_mut().filter_map(Value::as_object_mut) {
+                if chapter.get("contentHash").is_none_or(Value::is_null) {
+                    if let Some(text) = chapter.get("text").and_then(Value::as_str) {
+                        let hash = epub::hash_text(text);
+                        chapter.insert("contentHash".to_string(), Value::String(hash));
+                    }
+                }
+            }
+        }
+    }
+    value["schemaVersion"] = json!(3);
+    Ok(value)
+}
+
+/// Moves an unreadable library aside so the recovered copy can take its
+/// place without the broken file entering the backup rotation.
+fn quarantine(path: &Path) {
+    if path.exists() {
+        let target = storage::sibling_path(path, "corrupt");
+        if let Err(error) = fs::rename(path, &target) {
+            println!("DEBUG WARNING: Failed to move aside {}: {}", path.display(), error);
+        }
+    }
+}
+
+fn file_label(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh directory for one test's library files.
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rebook-library-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn book(id: &str) -> Value {
+        json!({
+            "id": id,
+            "title": id,
+            "author": null,
+            "coverBase64": null,
+            "coverMime": null,
+            "chapters": [],
+            "importedAt": "2024-01-01T00:00:00Z",
+        })
+    }
+
+    #[test]
+    fn version_1_libraries_migrate_to_the_current_schema() {
+        let v1 = json!([
+            {
+                "title": "Mort",
+                "author": "Terry Pratchett",
+                "coverBase64": null,
+                "coverMime": null,
+                "chapters": [{
+                    "id": "c1",
+                    "title": "One",
+                    "text": "Death rode a horse.",
+                    "html": null,
+                    "sourceHref": null,
+                }],
+            },
+            { "id": "book-2", "title": 7 },
+        ]);
+        let (books, skipped) = from_json(v1.to_string().as_bytes(), "library.json").unwrap();
+        assert_eq!(books.len(), 1);
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].starts_with("Skipped entry 2:"));
+
+        let book = &books[0];
+        assert_eq!(book.id, "book-legacy-1");
+        assert_eq!(book.imported_at, "");
+        assert_eq!(book.chapters[0].word_count, 4);
+        assert_eq!(
+            book.chapters[0].content_hash,
+            Some(epub::hash_text("Death rode a horse."))
+        );
+    }
+
+    #[test]
+    fn newer_schemas_are_refused() {
+        let future = json!({ "schemaVersion": SCHEMA_VERSION + 1, "books": [] });
+        assert!(from_json(future.to_string().as_bytes(), "library.json").is_err());
+    }
+
+    #[test]
+    fn a_corrupt_library_is_recovered_from_its_newest_backup() {
+        let dir = scratch("recover");
+        let path = dir.join("library.json");
+        let first: BookEntry = serde_json::from_value(book("book-1")).unwrap();
+        let second: BookEntry = serde_json::from_value(book("book-2")).unwrap();
+        save(&path, std::slice::from_ref(&first)).unwrap();
+        save(&path, &[first, second]).unwrap();
+        fs::write(&path, b"{\"schemaVersion\": 3, \"books\": [").unwrap();
+
+        // Loading leaves the broken file alone.
+        assert!(load(&path).is_err());
+        assert!(!storage::sibling_path(&path, "corrupt").exists());
+
+        let recovery = recover(&path).unwrap().unwrap();
+        assert_eq!(recovery.source, "library.json.1");
+        assert_eq!(recovery.book_count, 1);
+        assert!(storage::sibling_path(&path, "corrupt").exists());
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.books[0].id, "book-1");
+        assert!(loaded.recovery.is_none());
+        assert!(recover(&path).unwrap().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recovery_fails_without_a_readable_backup() {
+        let dir = scratch("unrecoverable");
+        let path = dir.join("library.json");
+        fs::write(storage::backup_path(&path, 1), b"not json").unwrap();
+        fs::write(&path, b"not json").unwrap();
+        assert!(recover(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+use crate::config;
+use crate::lexicon::Rendering;
+use crate::models::TtsCapabilities;
+use crate::prosody::{self, Markup};
+use crate::tts::{SpeechInput, SynthesizedAudio, TtsProvider};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+/// Speaking rate, in words per minute, that `{wpm}` scales by the speed.
+const BASE_WORDS_PER_MINUTE: f32 = 175.0;
+
+/// A speech engine installed on this machine, such as espeak-ng or Piper,
+/// set up with `REBOOK_LOCAL_TTS_*` variables so the app can read offline.
+///
+/// `REBOOK_LOCAL_TTS_ARGS` is split on whitespace, then each argument has
+/// `{text}`, `{output}`, `{voice}`, `{speed}`, `{wpm}`, `{length_scale}` and
+/// `{model}` filled in. Without `{text}` the text goes to stdin, and without
+/// `{output}` the audio is read from stdout. An argument whose placeholder
+/// has no value is left out, along with the flag before it.
+///
+/// With `REBOOK_LOCAL_TTS_PERSISTENT` the engine is kept running between
+/// requests instead: it is sent one JSON object per line with `text` and
+/// `output_file`, and answers with a line once the file is written, as
+/// Piper does with `--json-input`.
+#[derive(Default)]
+pub struct LocalProvider {
+    /// Idle persistent engines, ready for the next request.
+    engines: Mutex<Vec<Engine>>,
+    next_file: AtomicU64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalOptions {
+    pub voice_id: Option<String>,
+    /// Speaking rate relative to the engine's default.
+    pub speed: Option<f32>,
+    /// Overrides `REBOOK_LOCAL_TTS_MODEL` for engines with model files.
+    pub model: Option<String>,
+}
+
+struct LocalSettings {
+    command: String,
+    args: Vec<String>,
+    model: Option<String>,
+    voice: Option<String>,
+    /// Set when the engine writes headerless 16-bit mono PCM at this rate
+    /// rather than WAV.
+    raw_sample_rate: Option<u32>,
+    ssml: bool,
+    persistent: bool,
+    pool_size: usize,
+    timeout: Duration,
+}
+
+impl LocalSettings {
+    fn from_config() -> Result<Self, String> {
+        let command = config::local_tts_setting("COMMAND")
+            .ok_or_else(|| "Missing REBOOK_LOCAL_TTS_COMMAND in environment.".to_string())?;
+        let flag = |name: &str| {
+            config::local_tts_setting(name)
+                .is_some_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"))
+        };
+        let number = |name: &str| {
+            config::local_tts_setting(name).and_then(|value| match value.parse::<u32>() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    println!(
+                        "DEBUG WARNING: Ignoring invalid REBOOK_LOCAL_TTS_{}: {}",
+                        name, value
+                    );
+                    None
+                }
+            })
+        };
+        let raw = config::local_tts_setting("OUTPUT").is_some_and(|format| format == "raw");
+        Ok(Self {
+            command,
+            args: config::local_tts_setting("ARGS")
+                .map(|args| args.split_whitespace().map(String::from).collect())
+                .unwrap_or_default(),
+            model: config::local_tts_setting("MODEL"),
+            voice: config::local_tts_setting("VOICE"),
+            raw_sample_rate: raw.then(|| number("SAMPLE_RATE").unwrap_or(22_050)),
+            ssml: flag("SSML"),
+            persistent: flag("PERSISTENT"),
+            pool_size: number("POOL_SIZE").unwrap_or(1).max(1) as usize,
+            timeout: Duration::from_secs(number("TIMEOUT_SECS").unwrap_or(60).into()),
+        })
+    }
+}
+
+/// A persistent engine process and the arguments it was started with, so
+/// requests for another voice or model start their own.
+struct Engine {
+    args: Vec<String>,
+    // Dropping the child kills it, so an engine that timed out or was
+    // cancelled mid-request is not reused.
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+/// A file the engine writes audio to, removed once read or abandoned.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[async_trait]
+impl TtsProvider for LocalProvider {
+    type Options = LocalOptions;
+
+    fn id(&self) -> &'static str {
+        "local"
+    }
+
+    fn name(&self) -> &'static str {
+        "Local engine"
+    }
+
+    fn capabilities(&self) -> TtsCapabilities {
+        TtsCapabilities {
+            voice_listing: false,
+            voice_cloning: false,
+            streaming: false,
+            output_formats: vec!["wav".to_string()],
+            max_characters: None,
+            configured: config::local_tts_setting("COMMAND").is_some(),
+        }
+    }
+
+    async fn synthesize(
+        &self,
+        input: &SpeechInput,
+        options: LocalOptions,
+    ) -> Result<SynthesizedAudio, String> {
+        let settings = LocalSettings::from_config()?;
+        let markup = if settings.ssml {
+            Markup::Ssml
+        } else {
+            Markup::Plain
+        };
+        let text = prosody::render(input, markup, Some(Rendering::Alias));
+        let output = self.temp_file();
+
+        let bytes = if settings.persistent {
+            self.speak(&settings, &options, &text, &output.0).await?
+        } else {
+            self.run(&settings, &options, &text, &output.0).await?
+        };
+        if bytes.is_empty() {
+            return Err(format!("{} produced no audio", settings.command));
+        }
+        let bytes = match settings.raw_sample_rate {
+            Some(sample_rate) => wav(&bytes, sample_rate),
+            None => bytes,
+        };
+        Ok(SynthesizedAudio {
+            bytes,
+            mime: "audio/wav".to_string(),
+        })
+    }
+}
+
+impl LocalProvider {
+    /// Starts the engine for one request and waits for it to exit.
+    async fn run(
+        &self,
+        settings: &LocalSettings,
+        options: &LocalOptions,
+        text: &str,
+        output: &Path,
+    ) -> Result<Vec<u8>, String> {
+        let uses = |placeholder: &str| settings.args.iter().any(|arg| arg.contains(placeholder));
+        let text_in_args = uses("{text}");
+        let output_in_args = uses("{output}");
+        let args = fill_args(&settings.args, |name| match name {
+            "text" => Some(Some(text.to_string())),
+            "output" => Some(Some(output.to_string_lossy().to_string())),
+            name => value(settings, options, name),
+        });
+
+        let mut child = Command::new(&settings.command)
+            .args(&args)
+            .stdin(if text_in_args {
+                Stdio::null()
+            } else {
+                Stdio::piped()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|error| format!("Failed to start {}: {}", settings.command, error))?;
+
+        let stdin = child.stdin.take();
+        let write = async {
+            // An engine that exits early closes stdin; its exit status says why.
+            if let Some(mut stdin) = stdin {
+                let _ = stdin.write_all(text.as_bytes()).await;
+            }
+        };
+        let finished = async { tokio::join!(write, child.wait_with_output()).1 };
+        let result = tokio::time::timeout(settings.timeout, finished)
+            .await
+            .map_err(|_| timed_out(settings))?
+            .map_err(|error| format!("{} failed: {}", settings.command, error))?;
+
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            return Err(format!(
+                "{} exited with {}: {}",
+                settings.command,
+                result.status,
+                stderr.trim()
+            ));
+        }
+        if output_in_args {
+            std::fs::read(output).map_err(|error| {
+                format!("Failed reading audio from {}: {}", settings.command, error)
+            })
+        } else {
+            Ok(result.stdout)
+        }
+    }
+
+    /// Sends one request to a persistent engine, starting one if none is
+    /// idle. The engine goes back to the pool only if it answered in time.
+    async fn speak(
+        &self,
+        settings: &LocalSettings,
+        options: &LocalOptions,
+        text: &str,
+        output: &Path,
+    ) -> Result<Vec<u8>, String> {
+        let args = fill_args(&settings.args, |name| value(settings, options, name));
+        let mut engine = match self.checkout(&args) {
+            Some(engine) => engine,
+            None => start_engine(settings, args)?,
+        };
+
+        let request = serde_json::json!({
+            "text": text,
+            "output_file": output,
+        });
+        let exchange = async {
+            let line = format!("{}\n", request);
+            engine.stdin.write_all(line.as_bytes()).await?;
+            engine.stdin.flush().await?;
+            let mut reply = String::new();
+            engine.stdout.read_line(&mut reply).await
+        };
+        let read = tokio::time::timeout(settings.timeout, exchange)
+            .await
+            .map_err(|_| timed_out(settings))?
+            .map_err(|error| format!("{} failed: {}", settings.command, error))?;
+        if read == 0 {
+            return Err(format!("{} exited", settings.command));
+        }
+
+        let bytes = std::fs::read(output).map_err(|error| {
+            format!("Failed reading audio from {}: {}", settings.command, error)
+        })?;
+        self.checkin(engine, settings.pool_size);
+        Ok(bytes)
+    }
+
+    /// Takes an idle engine started with `args`, skipping any that exited.
+    fn checkout(&self, args: &[String]) -> Option<Engine> {
+        let mut engines = self.engines.lock().ok()?;
+        engines.retain_mut(|engine| matches!(engine.child.try_wait(), Ok(None)));
+        let index = engines.iter().position(|engine| engine.args == args)?;
+        Some(engines.swap_remove(index))
+    }
+
+    fn checkin(&self, engine: Engine, pool_size: usize) {
+        if let Ok(mut engines) = self.engines.lock() {
+            if engines.len() >= pool_size {
+                // Make room by stopping the engine idle the longest.
+                engines.remove(0);
+            }
+            engines.push(engine);
+        }
+    }
+
+    fn temp_file(&self) -> TempFile {
+        let index = self.next_file.fetch_add(1, Ordering::Relaxed);
+        TempFile(std::env::temp_dir().join(format!(
+            "rebook-tts-{}-{}.wav",
+            std::process::id(),
+            index
+        )))
+    }
+}
+
+fn start_engine(settings: &LocalSettings, args: Vec<String>) -> Result<Engine, String> {
+    let mut child = Command::new(&settings.command)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        // Nothing reads a long-running engine's log, so it must not fill a pipe.
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|error| format!("Failed to start {}: {}", settings.command, error))?;
+    let stdin = child.stdin.take();
+    let stdout = child.stdout.take();
+    match (stdin, stdout) {
+        (Some(stdin), Some(stdout)) => Ok(Engine {
+            args,
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        }),
+        _ => Err(format!("Failed to connect to {}", settings.command)),
+    }
+}
+
+/// The value of a placeholder other than `{text}` and `{output}`: `None`
+/// for an unknown name, `Some(None)` for a known one without a value.
+fn value(settings: &LocalSettings, options: &LocalOptions, name: &str) -> Option<Option<String>> {
+    let speed = options.speed.unwrap_or(1.0).clamp(0.25, 4.0);
+    let value = match name {
+        "voice" => options
+            .voice_id
+            .clone()
+            .filter(|voice| !voice.is_empty())
+            .or_else(|| settings.voice.clone()),
+        "model" => options
+            .model
+            .clone()
+            .filter(|model| !model.is_empty())
+            .or_else(|| settings.model.clone()),
+        "speed" => Some(speed.to_string()),
+        "wpm" => Some(((BASE_WORDS_PER_MINUTE * speed).round() as u32).to_string()),
+        "length_scale" => Some(format!("{:.2}", 1.0 / speed)),
+        "text" | "output" => None,
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Fills each argument's placeholders in one pass, so text containing
+/// `{voice}` is passed on as written.
+fn fill_args(templates: &[String], value: impl Fn(&str) -> Option<Option<String>>) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    for (index, template) in templates.iter().enumerate() {
+        let mut arg = String::new();
+        let mut rest = template.as_str();
+        let mut missing = false;
+        while let Some(open) = rest.find('{') {
+            let Some(close) = rest[open..].find('}').map(|close| open + close) else {
+                break;
+            };
+            arg.push_str(&rest[..open]);
+            match value(&rest[open + 1..close]) {
+                Some(Some(value)) => arg.push_str(&value),
+                Some(None) => missing = true,
+                None => arg.push_str(&rest[open..=close]),
+            }
+            rest = &rest[close + 1..];
+        }
+        arg.push_str(rest);
+
+        if missing {
+            let flag_before = index > 0
+                && templates[index - 1].starts_with('-')
+                && !templates[index - 1].contains('{');
+            if flag_before && !template.starts_with('-') {
+                args.pop();
+            }
+            continue;
+        }
+        args.push(arg);
+    }
+    args
+}
+
+fn timed_out(settings: &LocalSettings) -> String {
+    format!(
+        "{} did not finish within {}s",
+        settings.command,
+        settings.timeout.as_secs()
+    )
+}
+
+/// Wraps 16-bit mono PCM in a WAV header so the webview can play it.
+fn wav(pcm: &[u8], sample_rate: u32) -> Vec<u8> {
+    let data_len = pcm.len() as u32;
+    let mut out = Vec::with_capacity(pcm.len() + 44);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    out.extend_from_slice(pcm);
+    out
+}
@@ -0,0 +1,497 @@
+use crate::config;
+use crate::epub;
+use crate::library;
+use crate::models::{AuthorName, BookEntry, MetadataUpdate};
+use crate::search;
+use crate::shelves;
+use crate::sources;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::ImageFormat;
+use roxmltree::{Document, Node};
+use std::fs::{self, File};
+use std::io::{Cursor, Write};
+use std::path::Path;
+use tauri::AppHandle;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const OPF_NS: &str = "http://www.idpf.org/2007/opf";
+
+/// Name suffixes that stay after the given names in a sort name.
+const NAME_SUFFIXES: &[&str] = &["jr", "jr.", "sr", "sr.", "ii", "iii", "iv", "phd", "ph.d."];
+
+/// Metadata the editor manages. Everything else in the OPF is kept as is.
+const MANAGED_DC: &[&str] = &["title", "creator", "description", "subject"];
+const MANAGED_META_NAMES: &[&str] = &[
+    "calibre:series",
+    "calibre:series_index",
+    "calibre:author_sort",
+];
+
+/// Applies `edit` to a library book and saves it. With `write_to_file`, the
+/// changes also go into the book's stored EPUB, so that downloads and
+/// backups carry them; `write_cover` includes the cover.
+pub fn edit_book(
+    app: &AppHandle,
+    book_id: &str,
+    write_to_file: bool,
+    write_cover: bool,
+    edit: impl FnOnce(&mut BookEntry) -> Result<(), String>,
+) -> Result<BookEntry, String> {
+    let path = config::get_library_path(app)?;
+    let _guard = library::lock(app)?;
+    let mut books = library::load(&path)?.books;
+    let entry = books
+        .iter_mut()
+        .find(|entry| entry.id == book_id)
+        .ok_or_else(|| format!("Book {} not found in library", book_id))?;
+    edit(entry)?;
+
+    if write_to_file {
+        let source = entry
+            .content_hash
+            .as_deref()
+            .map(|hash| config::get_sources_dir(app).map(|dir| sources::path_for(&dir, hash)))
+            .transpose()?
+            .filter(|source| source.exists())
+            .ok_or_else(|| {
+                format!(
+                    "The original EPUB of \"{}\" is not stored, so changes can only be saved to the library.",
+                    entry.title
+                )
+            })?;
+        write_to_epub(&source, entry, write_cover)?;
+    }
+
+    let updated = entry.clone();
+    library::save(&path, &books)?;
+    search::update_index(app, &books);
+    Ok(updated)
+}
+
+pub fn apply(entry: &mut BookEntry, update: MetadataUpdate) -> Result<(), String> {
+    let title = update.title.trim();
+    if title.is_empty() {
+        return Err("A book needs a title.".to_string());
+    }
+    let authors: Vec<AuthorName> = update
+        .authors
+        .into_iter()
+        .filter(|author| !author.name.trim().is_empty())
+        .collect();
+
+    entry.title = title.to_string();
+    entry.author = join(authors.iter().map(|author| author.name.trim().to_string()));
+    entry.author_sort = join(authors.iter().map(author_sort_name));
+    entry.series = non_empty(update.series);
+    entry.series_index = entry.series.as_ref().and(update.series_index);
+    if let Some(language) = non_empty(update.language) {
+        entry.language = Some(language);
+    }
+    entry.description = non_empty(update.description);
+    entry.tags = shelves::normalize_tags(update.tags);
+    Ok(())
+}
+
+/// Turns "Terry Pratchett" into "Pratchett, Terry", keeping suffixes such
+/// as "Jr." at the end. Names that already contain a comma are taken to be
+/// in sort form.
+pub fn sort_name(name: &str) -> String {
+    let name = name.trim();
+    if name.contains(',') {
+        return name.to_string();
+    }
+    let mut words: Vec<&str> = name.split_whitespace().collect();
+    let suffix = words
+        .last()
+        .filter(|word| NAME_SUFFIXES.contains(&word.to_lowercase().as_str()))
+        .copied();
+    if suffix.is_some() {
+        words.pop();
+    }
+    let Some(surname) = words.pop() else {
+        return name.to_string();
+    };
+    if words.is_empty() {
+        return surname.to_string();
+    }
+    let mut sorted = format!("{}, {}", surname, words.join(" "));
+    if let Some(suffix) = suffix {
+        sorted.push_str(", ");
+        sorted.push_str(suffix);
+    }
+    sorted
+}
+
+/// Reads a replacement cover, returning it base64-encoded with its MIME
+/// type.
+pub fn read_cover(path: &Path) -> Result<(String, String), String> {
+    let bytes =
+        fs::read(path).map_err(|error| format!("Failed to read {}: {error}", path.display()))?;
+    let format = image::guess_format(&bytes)
+        .ok()
+        .filter(|format| {
+            matches!(
+                format,
+                ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP
+            )
+        })
+        .ok_or_else(|| "Covers must be JPEG, PNG, GIF or WebP images.".to_string())?;
+    image::load_from_memory_with_format(&bytes, format)
+        .map_err(|error| format!("Unreadable cover image: {error}"))?;
+    Ok((STANDARD.encode(&bytes), format.to_mime_type().to_string()))
+}
+
+/// Writes a book's metadata, and its cover when `write_cover` is set, into
+/// the stored EPUB at `path`.
+pub fn write_to_epub(path: &Path, entry: &BookEntry, write_cover: bool) -> Result<(), String> {
+    let bytes =
+        fs::read(path).map_err(|error| format!("Failed to read {}: {error}", path.display()))?;
+    let mut zip = ZipArchive::new(Cursor::new(bytes))
+        .map_err(|error| format!("Invalid EPUB archive: {error}"))?;
+    let container_xml = epub::read_zip_file(&mut zip, "META-INF/container.xml")?;
+    let opf_path = epub::find_rootfile(&container_xml)?;
+    let opf_xml = epub::read_zip_file(&mut zip, &opf_path)?;
+    let new_opf = rewrite_opf(&opf_xml, entry)?;
+
+    let cover = if write_cover {
+        match (cover_item(&opf_xml)?, entry.cover_base64.as_deref()) {
+            (Some((href, media_type)), Some(cover)) => {
+                let bytes = STANDARD
+                    .decode(cover)
+                    .map_err(|error| format!("Invalid cover data: {error}"))?;
+                let bytes = convert_cover(bytes, entry.cover_mime.as_deref(), &media_type)?;
+                Some((epub::resolve_relative_path(&opf_path, &href), bytes))
+            }
+            (None, Some(_)) => {
+                return Err(format!(
+                    "The EPUB of \"{}\" has no cover image to replace, so the cover can only be saved to the library.",
+                    entry.title
+                ));
+            }
+            (_, None) => return Err(format!("\"{}\" has no cover to write.", entry.title)),
+        }
+    } else {
+        None
+    };
+
+    let temp = path.with_extension("epub.tmp");
+    let file = File::create(&temp)
+        .map_err(|error| format!("Failed to create {}: {error}", temp.display()))?;
+    let mut writer = ZipWriter::new(file);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for index in 0..zip.len() {
+        let entry_file = zip
+            .by_index_raw(index)
+            .map_err(|error| format!("Failed to read EPUB entry: {error}"))?;
+        let name = entry_file.name().to_string();
+        let replacement = if name == opf_path {
+            Some(new_opf.as_bytes())
+        } else {
+            cover
+                .as_ref()
+                .filter(|(cover_path, _)| *cover_path == name)
+                .map(|(_, bytes)| bytes.as_slice())
+        };
+        match replacement {
+            Some(bytes) => {
+                drop(entry_file);
+                writer
+                    .start_file(name.as_str(), deflated)
+                    .and_then(|_| writer.write_all(bytes).map_err(Into::into))
+                    .map_err(|error| format!("Failed to write {}: {error}", name))?;
+            }
+            // Copying entries as they are keeps the uncompressed `mimetype`
+            // entry first, as EPUB readers require.
+            None => writer
+                .raw_copy_file(entry_file)
+                .map_err(|error| format!("Failed to copy {}: {error}", name))?,
+        }
+    }
+    writer
+        .finish()
+        .map_err(|error| format!("Failed to write {}: {error}", temp.display()))?;
+    fs::rename(&temp, path)
+        .map_err(|error| format!("Failed to replace {}: {error}", path.display()))
+}
+
+/// Replaces the managed metadata elements of an OPF document, copying every
+/// other element through unchanged.
+fn rewrite_opf(opf_xml: &str, entry: &BookEntry) -> Result<String, String> {
+    let document =
+        Document::parse(opf_xml).map_err(|error| format!("Invalid OPF file: {error}"))?;
+    let epub3 = document
+        .root_element()
+        .attribute("version")
+        .is_some_and(|version| version.starts_with('3'));
+    let metadata = document
+        .descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == "metadata")
+        .ok_or_else(|| "OPF file has no metadata".to_string())?;
+    let (Some(first), Some(last)) = (metadata.first_child(), metadata.last_child()) else {
+        return Err("OPF file has no metadata".to_string());
+    };
+    let manage_language = entry.language.is_some();
+
+    let removed_ids: Vec<&str> = metadata
+        .children()
+        .filter(|node| is_managed(*node, manage_language))
+        .filter_map(|node| node.attribute("id"))
+        .collect();
+    let mut content = String::new();
+    for node in metadata.children() {
+        let refines_removed = node
+            .attribute("refines")
+            .is_some_and(|target| removed_ids.contains(&target.trim_start_matches('#')));
+        let blank = node.is_text() && node.text().is_some_and(|text| text.trim().is_empty());
+        if blank || refines_removed || is_managed(node, manage_language) {
+            continue;
+        }
+        content.push_str("\n    ");
+        content.push_str(&opf_xml[node.range()]);
+    }
+
+    let writer = ElementWriter::new(metadata);
+    content.push_str(&writer.dc("title", "", &entry.title));
+    let names = split_authors(entry.author.as_deref());
+    let sorts = split_authors(entry.author_sort.as_deref());
+    for (index, name) in names.iter().enumerate() {
+        let sort = sorts
+            .get(index)
+            .filter(|_| sorts.len() == names.len())
+            .cloned()
+            .unwrap_or_else(|| sort_name(name));
+        if epub3 {
+            let id = format!("rebook-creator-{}", index + 1);
+            content.push_str(&writer.dc("creator", &format!(" id=\"{}\"", id), name));
+            content.push_str(&writer.refinement(&id, "file-as", &sort));
+            content.push_str(&writer.refinement(&id, "role", "aut"));
+        } else {
+            let attributes = writer.opf_attributes(&[("file-as", &sort), ("role", "aut")]);
+            content.push_str(&writer.dc("creator", &attributes, name));
+        }
+    }
+    if let Some(language) = &entry.language {
+        content.push_str(&writer.dc("language", "", language));
+    }
+    if let Some(description) = &entry.description {
+        content.push_str(&writer.dc("description", "", description));
+    }
+    for tag in &entry.tags {
+        content.push_str(&writer.dc("subject", "", tag));
+    }
+    if let Some(series) = &entry.series {
+        let index = entry.series_index.map(|index| index.to_string());
+        content.push_str(&writer.named_meta("calibre:series", series));
+        if let Some(index) = &index {
+            content.push_str(&writer.named_meta("calibre:series_index", index));
+        }
+        if epub3 {
+            content.push_str(&format!(
+                "\n    <{0}meta property=\"belongs-to-collection\" id=\"rebook-series\">{1}</{0}meta>",
+                writer.opf_prefix,
+                epub::escape_xml(series)
+            ));
+            content.push_str(&writer.refinement("rebook-series", "collection-type", "series"));
+            if let Some(index) = &index {
+                content.push_str(&writer.refinement("rebook-series", "group-position", index));
+            }
+        }
+    }
+    content.push_str("\n  ");
+
+    let start = first.range().start;
+    let end = last.range().end;
+    Ok(format!(
+        "{}{}{}",
+        &opf_xml[..start],
+        content.trim_start_matches(' '),
+        &opf_xml[end..]
+    ))
+}
+
+fn is_managed(node: Node, manage_language: bool) -> bool {
+    if !node.is_element() {
+        return false;
+    }
+    let name = node.tag_name().name();
+    if node.tag_name().namespace() == Some(DC_NS) {
+        return MANAGED_DC.contains(&name) || (manage_language && name == "language");
+    }
+    name == "meta"
+        && (node
+            .attribute("name")
+            .is_some_and(|meta| MANAGED_META_NAMES.contains(&meta))
+            || node.attribute("property") == Some("belongs-to-collection"))
+}
+
+/// Writes new metadata elements with the prefixes the document already
+/// uses, declaring the namespace inline where it has none.
+struct ElementWriter {
+    dc_prefix: String,
+    dc_declaration: &'static str,
+    opf_prefix: String,
+    /// Attributes need a prefix even when OPF is the default namespace.
+    opf_attribute_prefix: String,
+    opf_attribute_declaration: &'static str,
+}
+
+impl ElementWriter {
+    fn new(metadata: Node) -> ElementWriter {
+        let prefixed = |prefix: Option<&str>| prefix.map(|prefix| format!("{}:", prefix));
+        let dc_prefix = prefixed(metadata.lookup_prefix(DC_NS));
+        let opf_prefix = if metadata.default_namespace() == Some(OPF_NS) {
+            Some(String::new())
+        } else {
+            prefixed(metadata.lookup_prefix(OPF_NS))
+        };
+        let opf_attribute_prefix = prefixed(metadata.lookup_prefix(OPF_NS));
+        ElementWriter {
+            dc_declaration: if dc_prefix.is_some() {
+                ""
+            } else {
+                " xmlns:dc=\"http://purl.org/dc/elements/1.1/\""
+            },
+            dc_prefix: dc_prefix.unwrap_or_else(|| "dc:".to_string()),
+            opf_prefix: opf_prefix.unwrap_or_default(),
+            opf_attribute_declaration: if opf_attribute_prefix.is_some() {
+                ""
+            } else {
+                " xmlns:opf=\"http://www.idpf.org/2007/opf\""
+            },
+            opf_attribute_prefix: opf_attribute_prefix.unwrap_or_else(|| "opf:".to_string()),
+        }
+    }
+
+    fn dc(&self, name: &str, attributes: &str, text: &str) -> String {
+        format!(
+            "\n    <{0}{1}{2}{3}>{4}</{0}{1}>",
+            self.dc_prefix,
+            name,
+            self.dc_declaration,
+            attributes,
+            epub::escape_xml(text)
+        )
+    }
+
+    fn opf_attributes(&self, attributes: &[(&str, &str)]) -> String {
+        let mut written = self.opf_attribute_declaration.to_string();
+        for (name, value) in attributes {
+            written.push_str(&format!(
+                " {}{}=\"{}\"",
+                self.opf_attribute_prefix,
+                name,
+                epub::escape_xml(value)
+            ));
+        }
+        written
+    }
+
+    fn refinement(&self, id: &str, property: &str, value: &str) -> String {
+        format!(
+            "\n    <{0}meta refines=\"#{1}\" property=\"{2}\">{3}</{0}meta>",
+            self.opf_prefix,
+            id,
+            property,
+            epub::escape_xml(value)
+        )
+    }
+
+    fn named_meta(&self, name: &str, content: &str) -> String {
+        format!(
+            "\n    <{}meta name=\"{}\" content=\"{}\"/>",
+            self.opf_prefix,
+            name,
+            epub::escape_xml(content)
+        )
+    }
+}
+
+/// Finds the manifest item holding the cover image: its href and media type.
+fn cover_item(opf_xml: &str) -> Result<Option<(String, String)>, String> {
+    let document =
+        Document::parse(opf_xml).map_err(|error| format!("Invalid OPF file: {error}"))?;
+    let items: Vec<Node> = document
+        .descendants()
+        .filter(|node| node.is_element() && node.tag_name().name() == "item")
+        .collect();
+    let cover_id = document
+        .descendants()
+        .find(|node| {
+            node.is_element()
+                && node.tag_name().name() == "meta"
+                && node.attribute("name") == Some("cover")
+        })
+        .and_then(|node| node.attribute("content"));
+    let item = items
+        .iter()
+        .find(|item| {
+            item.attribute("properties")
+                .is_some_and(|properties| properties.split_whitespace().any(|p| p == "cover-image"))
+        })
+        .or_else(|| {
+            items
+                .iter()
+                .find(|item| cover_id.is_some() && item.attribute("id") == cover_id)
+        });
+    Ok(item.and_then(|item| {
+        Some((
+            item.attribute("href")?.to_string(),
+            item.attribute("media-type")?.to_string(),
+        ))
+    }))
+}
+
+/// Re-encodes a cover into the media type the EPUB declares for it, so the
+/// manifest stays correct.
+fn convert_cover(bytes: Vec<u8>, mime: Option<&str>, target: &str) -> Result<Vec<u8>, String> {
+    if mime == Some(target) {
+        return Ok(bytes);
+    }
+    let format = ImageFormat::from_mime_type(target)
+        .ok_or_else(|| format!("Cannot write a cover as {}", target))?;
+    let image = image::load_from_memory(&bytes)
+        .map_err(|error| format!("Unreadable cover image: {error}"))?;
+    let image = if format == ImageFormat::Jpeg {
+        image::DynamicImage::ImageRgb8(image.to_rgb8())
+    } else {
+        image
+    };
+    let mut converted = Cursor::new(Vec::new());
+    image
+        .write_to(&mut converted, format)
+        .map_err(|error| format!("Failed to convert cover: {error}"))?;
+    Ok(converted.into_inner())
+}
+
+fn author_sort_name(author: &AuthorName) -> String {
+    author
+        .sort_name
+        .as_deref()
+        .map(str::trim)
+        .filter(|sort| !sort.is_empty())
+        .map_or_else(|| sort_name(&author.name), str::to_string)
+}
+
+fn split_authors(joined: Option<&str>) -> Vec<String> {
+    joined
+        .into_iter()
+        .flat_map(|joined| joined.split(" & "))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn join(values: impl Iterator<Item = String>) -> Option<String> {
+    let values: Vec<String> = values.collect();
+    (!values.is_empty()).then(|| values.join(" & "))
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
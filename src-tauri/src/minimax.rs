@@ -1,11 +1,91 @@
 use crate::config;
-use crate::models::{
-    MinimaxCloneRequest, MinimaxCloneResponse, MinimaxUploadRequest, MinimaxUploadResponse,
-};
+use crate::http::Http;
+use crate::lexicon;
+use crate::models::{ClonedVoice, ProviderVoice, TtsCapabilities, VoiceCloneRequest};
+use crate::prosody::{self, Markup};
+use crate::tts::{AudioSink, SpeechInput, SynthesizedAudio, TtsProvider};
+use async_trait::async_trait;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
+use hex::FromHex;
 use reqwest::multipart::{Form, Part};
-use serde::Deserialize;
+use reqwest::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct MinimaxProvider {
+    http: Http,
+}
+
+impl MinimaxProvider {
+    pub fn new(http: Http) -> Self {
+        Self { http }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MinimaxOptions {
+    pub voice_id: Option<String>,
+    pub model: Option<String>,
+    pub output_format: Option<String>,
+}
+
+#[derive(Serialize)]
+struct MinimaxVoiceSetting<'a> {
+    voice_id: &'a str,
+}
+
+#[derive(Serialize)]
+struct MinimaxAudioSetting<'a> {
+    format: &'a str,
+}
+
+#[derive(Serialize)]
+struct MinimaxTtsPayload<'a> {
+    model: &'a str,
+    text: &'a str,
+    stream: bool,
+    voice_setting: MinimaxVoiceSetting<'a>,
+    audio_setting: MinimaxAudioSetting<'a>,
+    output_format: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pronunciation_dict: Option<MinimaxPronunciationDict>,
+}
+
+/// Replacements as `"word/alias"`; MiniMax has no IPA input, so entries
+/// with only IPA are left to the voice.
+#[derive(Serialize)]
+struct MinimaxPronunciationDict {
+    tone: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct MinimaxTtsResponse {
+    data: Option<MinimaxTtsData>,
+    base_resp: Option<BaseResp>,
+}
+
+#[derive(Deserialize)]
+struct MinimaxTtsData {
+    audio: String,
+    status: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct GetVoiceResp {
+    #[serde(default)]
+    system_voice: Vec<VoiceObject>,
+    #[serde(default)]
+    voice_cloning: Vec<VoiceObject>,
+    base_resp: BaseResp,
+}
+
+#[derive(Deserialize)]
+struct VoiceObject {
+    voice_id: String,
+    voice_name: Option<String>,
+}
 
 #[derive(Deserialize)]
 struct UploadFileResp {
@@ -16,7 +96,6 @@ struct UploadFileResp {
 #[derive(Deserialize)]
 struct UploadFileObject {
     file_id: i64,
-    filename: String,
 }
 
 #[derive(Deserialize)]
@@ -31,11 +110,327 @@ struct VoiceCloneResp {
     base_resp: BaseResp,
 }
 
-pub async fn upload_clone_audio(
-    request: MinimaxUploadRequest,
-) -> Result<MinimaxUploadResponse, String> {
-    let api_key = config::minimax_api_key()
-        .ok_or_else(|| "Missing REBOOK_MINIMAX_API_KEY in environment.".to_string())?;
+#[async_trait]
+impl TtsProvider for MinimaxProvider {
+    type Options = MinimaxOptions;
+
+    fn id(&self) -> &'static str {
+        "minimax"
+    }
+
+    fn name(&self) -> &'static str {
+        "MiniMax"
+    }
+
+    fn capabilities(&self) -> TtsCapabilities {
+        TtsCapabilities {
+            voice_listing: true,
+            voice_cloning: true,
+            streaming: true,
+            output_formats: ["mp3", "wav", "flac", "pcm"].map(String::from).to_vec(),
+            // Requests must stay under 10,000 characters.
+            max_characters: Some(9_999),
+            configured: config::minimax_api_key().is_some_and(|key| !key.is_empty()),
+        }
+    }
+
+    async fn synthesize(
+        &self,
+        input: &SpeechInput,
+        options: MinimaxOptions,
+    ) -> Result<SynthesizedAudio, String> {
+        let _permit = self.http.permit(self.id()).await?;
+        let (request, mime) = tts_request(&self.http, input, &options, false)?;
+        let response = send(&self.http, request).await?;
+        let body = self
+            .http
+            .bytes(response)
+            .await
+            .map_err(|error| format!("Minimax audio read failed: {error}"))?;
+        let body: MinimaxTtsResponse = serde_json::from_slice(&body)
+            .map_err(|error| format!("Minimax TTS response parse failed: {error}"))?;
+        if let Some(base_resp) = body.base_resp {
+            check(base_resp, "Minimax TTS failed")?;
+        }
+
+        let audio_hex = body
+            .data
+            .ok_or_else(|| "Minimax TTS response missing data.".to_string())?
+            .audio;
+        let bytes =
+            Vec::from_hex(audio_hex).map_err(|error| format!("Invalid audio hex: {error}"))?;
+
+        Ok(SynthesizedAudio { bytes, mime })
+    }
+
+    async fn synthesize_stream(
+        &self,
+        input: &SpeechInput,
+        options: MinimaxOptions,
+        sink: &AudioSink<'_>,
+    ) -> Result<(), String> {
+        let _permit = self.http.permit(self.id()).await?;
+        let (request, mime) = tts_request(&self.http, input, &options, true)?;
+        let mut response = send(&self.http, request).await?;
+        let mut buffer = Vec::new();
+        let mut produced = false;
+        loop {
+            let chunk = self
+                .http
+                .chunk(&mut response)
+                .await
+                .map_err(|error| format!("Minimax TTS stream failed: {error}"))?;
+            let done = chunk.is_none();
+            if let Some(chunk) = chunk {
+                buffer.extend(chunk.iter().filter(|&&byte| byte != b'\r'));
+            }
+            for event in sse_events(&mut buffer, done) {
+                let body: MinimaxTtsResponse = serde_json::from_str(&event)
+                    .map_err(|error| format!("Minimax TTS event parse failed: {error}"))?;
+                if let Some(base_resp) = body.base_resp {
+                    check(base_resp, "Minimax TTS failed")?;
+                }
+                // The last event repeats the whole clip, which is only
+                // needed if no pieces of it came before.
+                let Some(data) = body.data.filter(|data| data.status != Some(2) || !produced)
+                else {
+                    continue;
+                };
+                if data.audio.is_empty() {
+                    continue;
+                }
+                let bytes = Vec::from_hex(data.audio)
+                    .map_err(|error| format!("Invalid audio hex: {error}"))?;
+                sink(SynthesizedAudio {
+                    bytes,
+                    mime: mime.clone(),
+                })?;
+                produced = true;
+            }
+            if done {
+                if !produced {
+                    return Err("Minimax TTS stream ended without audio".to_string());
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    async fn list_voices(&self, _options: MinimaxOptions) -> Result<Vec<ProviderVoice>, String> {
+        let api_key = api_key()?;
+        let request = self
+            .http
+            .client()
+            .post(endpoint("get_voice"))
+            .bearer_auth(api_key)
+            .json(&serde_json::json!({ "voice_type": "all" }));
+        let response = self
+            .http
+            .send(request)
+            .await
+            .map_err(|error| format!("Minimax voice request failed: {error}"))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Minimax voices returned {}: {}", status, body));
+        }
+
+        let body: GetVoiceResp = response
+            .json()
+            .await
+            .map_err(|error| format!("Minimax voice response parse failed: {error}"))?;
+        check(body.base_resp, "Minimax voice listing failed")?;
+
+        let voices = body
+            .voice_cloning
+            .into_iter()
+            .map(|voice| (voice, true))
+            .chain(body.system_voice.into_iter().map(|voice| (voice, false)))
+            .map(|(voice, is_cloned)| ProviderVoice {
+                name: voice.voice_name.unwrap_or_else(|| voice.voice_id.clone()),
+                voice_id: voice.voice_id,
+                language: None,
+                is_cloned,
+            })
+            .collect();
+        Ok(voices)
+    }
+
+    async fn clone_voice(&self, request: VoiceCloneRequest) -> Result<ClonedVoice, String> {
+        let file_id = upload_clone_audio(&self.http, &request).await?;
+        let voice_id = request
+            .voice_id
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| generate_voice_id(&request.name));
+        let demo_audio = create_clone(&self.http, file_id, &voice_id).await?;
+        Ok(ClonedVoice {
+            voice_id,
+            name: request.name,
+            demo_audio,
+        })
+    }
+}
+
+fn api_key() -> Result<String, String> {
+    config::minimax_api_key()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| "Missing REBOOK_MINIMAX_API_KEY in environment.".to_string())
+}
+
+/// Builds a `t2a_v2` request for `input`, returning it with the mime type
+/// of the audio it asks for.
+fn tts_request(
+    http: &Http,
+    input: &SpeechInput,
+    options: &MinimaxOptions,
+    stream: bool,
+) -> Result<(RequestBuilder, String), String> {
+    let api_key = api_key()?;
+    let voice_id = options
+        .voice_id
+        .as_ref()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| "Minimax voice_id required.".to_string())?;
+    let model = options
+        .model
+        .as_deref()
+        .filter(|value| !value.is_empty())
+        .unwrap_or("speech-2.6-hd");
+    let format = options
+        .output_format
+        .as_deref()
+        .filter(|value| !value.is_empty())
+        .unwrap_or("mp3");
+
+    let text = prosody::render(input, Markup::PauseMarkers, None);
+    let tone: Vec<String> = lexicon::aliases(&text, &input.pronunciations)
+        .into_iter()
+        .map(|(word, alias)| format!("{}/{}", word, alias))
+        .collect();
+    let payload = MinimaxTtsPayload {
+        model,
+        text: &text,
+        stream,
+        voice_setting: MinimaxVoiceSetting { voice_id },
+        audio_setting: MinimaxAudioSetting { format },
+        output_format: "hex",
+        pronunciation_dict: (!tone.is_empty()).then_some(MinimaxPronunciationDict { tone }),
+    };
+
+    let request = http
+        .client()
+        .post(endpoint("t2a_v2"))
+        .bearer_auth(api_key)
+        .json(&payload);
+    Ok((request, mime(format)))
+}
+
+async fn send(http: &Http, request: RequestBuilder) -> Result<Response, String> {
+    let response = http
+        .send(request)
+        .await
+        .map_err(|error| format!("Minimax TTS request failed: {error}"))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Minimax TTS returned {}: {}", status, body));
+    }
+    Ok(response)
+}
+
+/// Takes the complete server-sent events out of `buffer`, returning their
+/// `data`. At the end of the stream whatever is left counts as an event;
+/// a leftover without `data:` lines is returned whole, as errors can come
+/// back as a plain JSON body.
+fn sse_events(buffer: &mut Vec<u8>, done: bool) -> Vec<String> {
+    let mut events = Vec::new();
+    loop {
+        let end = buffer
+            .windows(2)
+            .position(|window| window == b"\n\n")
+            .map(|position| (position, position + 2));
+        let (event_end, next) = match end {
+            Some(end) => end,
+            None if done && !buffer.is_empty() => (buffer.len(), buffer.len()),
+            None => return events,
+        };
+        let event = String::from_utf8_lossy(&buffer[..event_end]).to_string();
+        buffer.drain(..next);
+        let data: Vec<&str> = event
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(str::trim_start)
+            .collect();
+        if !data.is_empty() {
+            events.push(data.join("\n"));
+        } else if done && event.trim_start().starts_with('{') {
+            events.push(event.trim().to_string());
+        }
+    }
+}
+
+fn endpoint(path: &str) -> String {
+    format!(
+        "{}/v1/{}",
+        config::minimax_api_base().trim_end_matches('/'),
+        path
+    )
+}
+
+fn check(base_resp: BaseResp, fallback: &str) -> Result<(), String> {
+    if base_resp.status_code != 0 {
+        return Err(base_resp.status_msg.unwrap_or_else(|| fallback.to_string()));
+    }
+    Ok(())
+}
+
+fn mime(format: &str) -> String {
+    match format {
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "pcm" => "audio/pcm",
+        _ => "audio/mpeg",
+    }
+    .to_string()
+}
+
+/// Minimax voice ids are chosen by the caller and must be unique, so cloned
+/// voices are named `rebook_<name>_<timestamp>`.
+fn generate_voice_id(name: &str) -> String {
+    let slug: String = name
+        .trim()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("_")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default();
+    format!("rebook_{}_{}", slug, to_base36(millis))
+}
+
+fn to_base36(mut value: u128) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut digits = Vec::new();
+    loop {
+        digits.push(DIGITS[(value % 36) as usize]);
+        value /= 36;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    String::from_utf8(digits).unwrap_or_default()
+}
+
+async fn upload_clone_audio(http: &Http, request: &VoiceCloneRequest) -> Result<i64, String> {
+    let api_key = api_key()?;
     let bytes = STANDARD
         .decode(request.audio_base64.as_bytes())
         .map_err(|error| format!("Invalid audio base64: {error}"))?;
@@ -44,86 +439,122 @@ pub async fn upload_clone_audio(
         .text("purpose", "voice_clone")
         .part("file", file_part);
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post("https://api.minimaxi.com/v1/files/upload")
+    let request = http
+        .client()
+        .post(endpoint("files/upload"))
         .bearer_auth(api_key)
-        .multipart(form)
-        .send()
+        .multipart(form);
+    let response = http
+        .send(request)
         .await
         .map_err(|error| format!("Upload request failed: {error}"))?;
 
     let status = response.status();
     if !status.is_success() {
         let body = response.text().await.unwrap_or_default();
-        return Err(format!(
-            "Upload failed with {}: {}",
-            status,
-            body
-        ));
+        return Err(format!("Upload failed with {}: {}", status, body));
     }
 
     let body: UploadFileResp = response
         .json()
         .await
         .map_err(|error| format!("Upload response parse failed: {error}"))?;
-    if body.base_resp.status_code != 0 {
-        let msg = body
-            .base_resp
-            .status_msg
-            .unwrap_or_else(|| "Upload failed".to_string());
-        return Err(msg);
-    }
-
-    Ok(MinimaxUploadResponse {
-        file_id: body.file.file_id,
-        filename: body.file.filename,
-    })
+    check(body.base_resp, "Upload failed")?;
+    Ok(body.file.file_id)
 }
 
-pub async fn create_clone(
-    request: MinimaxCloneRequest,
-) -> Result<MinimaxCloneResponse, String> {
-    let api_key = config::minimax_api_key()
-        .ok_or_else(|| "Missing REBOOK_MINIMAX_API_KEY in environment.".to_string())?;
+async fn create_clone(http: &Http, file_id: i64, voice_id: &str) -> Result<Option<String>, String> {
+    let api_key = api_key()?;
     let payload = serde_json::json!({
-        "file_id": request.file_id,
-        "voice_id": request.voice_id,
+        "file_id": file_id,
+        "voice_id": voice_id,
     });
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post("https://api.minimaxi.com/v1/voice_clone")
+    let request = http
+        .client()
+        .post(endpoint("voice_clone"))
         .bearer_auth(api_key)
-        .json(&payload)
-        .send()
+        .json(&payload);
+    let response = http
+        .send(request)
         .await
         .map_err(|error| format!("Clone request failed: {error}"))?;
 
     let status = response.status();
     if !status.is_success() {
         let body = response.text().await.unwrap_or_default();
-        return Err(format!(
-            "Clone failed with {}: {}",
-            status,
-            body
-        ));
+        return Err(format!("Clone failed with {}: {}", status, body));
     }
 
     let body: VoiceCloneResp = response
         .json()
         .await
         .map_err(|error| format!("Clone response parse failed: {error}"))?;
-    if body.base_resp.status_code != 0 {
-        let msg = body
-            .base_resp
-            .status_msg
-            .unwrap_or_else(|| "Clone failed".to_string());
-        return Err(msg);
-    }
-
-    Ok(MinimaxCloneResponse {
-        voice_id: request.voice_id,
-        demo_audio: body.demo_audio,
-    })
+    check(body.base_resp, "Clone failed")?;
+    Ok(body.demo_audio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::HttpSettings;
+    use crate::tts::SpeechPart;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tiny_http::{Header, Response as ServerResponse, Server};
+
+    #[tokio::test]
+    async fn synthesize_waits_for_retry_after() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", server.server_addr().to_ip().unwrap());
+        std::env::set_var("REBOOK_MINIMAX_API_BASE", &base);
+        std::env::set_var("REBOOK_MINIMAX_API_KEY", "test-key");
+
+        let served = thread::spawn(move || {
+            let mut paths = Vec::new();
+            let request = server.recv().unwrap();
+            paths.push(request.url().to_string());
+            let retry_after = Header::from_bytes("Retry-After", "1").unwrap();
+            let busy = ServerResponse::from_string("busy")
+                .with_status_code(429)
+                .with_header(retry_after);
+            request.respond(busy).unwrap();
+
+            let request = server.recv().unwrap();
+            paths.push(request.url().to_string());
+            let body = r#"{"data":{"audio":"494433","status":2},"base_resp":{"status_code":0}}"#;
+            request.respond(ServerResponse::from_string(body)).unwrap();
+            paths
+        });
+
+        // Backoff alone would wait far longer than the server asks for.
+        let http = Http::new(HttpSettings {
+            base_delay: Duration::from_secs(20),
+            max_delay: Duration::from_secs(20),
+            ..HttpSettings::default()
+        })
+        .unwrap();
+        let provider = MinimaxProvider::new(http);
+        let input = SpeechInput {
+            parts: vec![SpeechPart::Text {
+                text: "Hello".to_string(),
+                emphasis: false,
+            }],
+            pronunciations: Vec::new(),
+        };
+        let options = MinimaxOptions {
+            voice_id: Some("voice".to_string()),
+            ..MinimaxOptions::default()
+        };
+
+        let started = Instant::now();
+        let audio = provider.synthesize(&input, options).await.unwrap();
+        let waited = started.elapsed();
+
+        assert_eq!(audio.bytes, b"ID3");
+        assert_eq!(audio.mime, "audio/mpeg");
+        assert!(waited >= Duration::from_secs(1), "retried after {waited:?}");
+        assert!(waited < Duration::from_secs(10), "retried after {waited:?}");
+        assert_eq!(served.join().unwrap(), ["/v1/t2a_v2", "/v1/t2a_v2"]);
+    }
 }
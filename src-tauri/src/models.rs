@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Chapter {
     pub id: String,
@@ -9,6 +9,22 @@ pub struct Chapter {
     pub html: Option<String>,
     pub source_href: Option<String>,
     pub word_count: usize,
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// Pronunciations the publisher marked inline with `ssml:ph`.
+    #[serde(default)]
+    pub pronunciations: Vec<PronunciationHint>,
+}
+
+/// A publisher's pronunciation for one occurrence of a word. Offsets count
+/// characters into the chapter text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PronunciationHint {
+    pub start: usize,
+    pub end: usize,
+    pub grapheme: String,
+    pub ipa: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,104 +35,895 @@ pub struct Book {
     pub chapters: Vec<Chapter>,
     pub cover_base64: Option<String>,
     pub cover_mime: Option<String>,
+    pub language: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub content_hash: Option<String>,
+    pub identifier: Option<String>,
+    pub description: Option<String>,
+    /// Entries from the PLS lexicons shipped in the EPUB.
+    pub lexicon: Vec<LexiconEntry>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BookEntry {
     pub id: String,
     pub title: String,
     pub author: Option<String>,
+    /// Sort form of `author`, e.g. "Tolkien, J. R. R.", with several
+    /// authors joined by " & " like `author`.
+    #[serde(default)]
+    pub author_sort: Option<String>,
     pub cover_base64: Option<String>,
     pub cover_mime: Option<String>,
     pub chapters: Vec<Chapter>,
     pub imported_at: String,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub series: Option<String>,
+    #[serde(default)]
+    pub series_index: Option<f64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    #[serde(default)]
+    pub identifier: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Publisher pronunciations from the EPUB's PLS lexicons. Readers'
+    /// own entries live in the pronunciation lexicon store instead.
+    #[serde(default)]
+    pub lexicon: Vec<LexiconEntry>,
+    /// The metadata the EPUB gave at its last import. Books imported before
+    /// this was recorded have none.
+    #[serde(default)]
+    pub imported: Option<ImportedMetadata>,
+}
+
+/// Metadata as an EPUB gave it, so a re-import can tell the reader's edits
+/// from changes in the new edition.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedMetadata {
+    pub title: String,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub description: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum VoiceMode {
-    Neutral,
-    External,
-    Minimax,
-    Elevenlabs,
+#[serde(rename_all = "camelCase")]
+pub struct LoadedLibrary {
+    pub books: Vec<BookEntry>,
+    pub recovery: Option<LibraryRecovery>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ExternalTtsConfig {
-    pub api_base_url: String,
-    pub voice_id: Option<String>,
-    pub output_format: Option<String>,
+pub struct LibraryRecovery {
+    pub source: String,
+    pub errors: Vec<String>,
+    pub book_count: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct TtsRequest {
+pub enum DuplicateReason {
+    SameFile,
+    SameIdentifier,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateMatch {
+    pub book_id: String,
+    pub title: String,
+    pub reason: DuplicateReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChapterChangeStatus {
+    Unchanged,
+    Modified,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterChange {
+    pub old_id: Option<String>,
+    pub new_id: Option<String>,
+    pub status: ChapterChangeStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookUpdate {
+    pub book: BookEntry,
+    pub chapters: Vec<ChapterChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub book_id: String,
+    pub book_title: String,
     pub chapter_id: String,
-    pub text: String,
-    pub voice_mode: VoiceMode,
-    pub external: Option<ExternalTtsConfig>,
-    pub minimax: Option<MinimaxTtsConfig>,
-    pub elevenlabs: Option<ElevenLabsTtsConfig>,
+    pub chapter_title: String,
+    pub offset: usize,
+    pub length: usize,
+    pub snippet: String,
+    pub snippet_match_start: usize,
+    pub snippet_match_end: usize,
+    pub match_count: usize,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+    pub id: String,
+    pub name: String,
+    pub book_ids: Vec<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadingStatus {
+    Unread,
+    InProgress,
+    Finished,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShelfQuery {
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub series: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub collection_id: Option<String>,
+    pub status: Option<ReadingStatus>,
+    pub has_cached_audio: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortKey {
+    #[default]
+    Title,
+    Author,
+    LastRead,
+    ImportedAt,
+    Progress,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShelfSort {
+    pub key: SortKey,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartShelf {
+    pub id: String,
+    pub name: String,
+    pub query: ShelfQuery,
+    #[serde(default)]
+    pub sort: ShelfSort,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Shelves {
+    #[serde(default)]
+    pub collections: Vec<Collection>,
+    #[serde(default)]
+    pub smart_shelves: Vec<SmartShelf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookActivity {
+    pub progress: f64,
+    pub last_read_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextQuote {
+    pub prefix: String,
+    pub exact: String,
+    pub suffix: String,
+}
+
+/// A reading position that survives re-pagination and re-import. The chapter
+/// id and character offset into `Chapter::text` are the primary anchor; the
+/// quote re-anchors it when the text shifts, and `cfi` is an EPUB CFI for the
+/// enclosing element when the chapter HTML could be parsed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Locator {
+    pub chapter_id: String,
+    pub element_path: Option<String>,
+    pub char_offset: usize,
+    pub cfi: Option<String>,
+    pub quote: Option<TextQuote>,
+    #[serde(default)]
+    pub progress: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionInput {
+    pub chapter_id: Option<String>,
+    pub char_offset: Option<usize>,
+    pub element_path: Option<String>,
+    pub sentence_text: Option<String>,
+    pub progress: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionRecord {
+    pub locator: Locator,
+    pub saved_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct MinimaxTtsConfig {
-    pub voice_id: Option<String>,
-    pub model: Option<String>,
-    pub output_format: Option<String>,
+pub struct ResolvedPosition {
+    pub locator: Locator,
+    pub exact: bool,
+    pub chapter_title: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnnotationKind {
+    Bookmark,
+    Highlight,
+    Note,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HighlightColor {
+    Yellow,
+    Green,
+    Blue,
+    Pink,
+    Purple,
+}
+
+/// A bookmark, highlight or note. The highlighted range starts at the
+/// locator's offset and spans the quoted `exact` text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub id: String,
+    pub book_id: String,
+    pub kind: AnnotationKind,
+    pub locator: Locator,
+    pub color: Option<HighlightColor>,
+    pub note: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct MinimaxUploadRequest {
-    pub filename: String,
-    pub audio_base64: String,
+pub struct AnnotationInput {
+    pub kind: AnnotationKind,
+    pub position: PositionInput,
+    pub color: Option<HighlightColor>,
+    pub note: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct MinimaxUploadResponse {
-    pub file_id: i64,
-    pub filename: String,
+pub struct AnnotationUpdate {
+    pub color: Option<HighlightColor>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    Markdown,
+    Csv,
+    Json,
+    Readwise,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NowPlaying {
+    pub book_id: String,
+    pub chapter_id: Option<String>,
+    pub sentence_text: String,
+    pub progress: Option<f64>,
+    /// Size of the chunk that started playing, for listening statistics.
+    #[serde(default)]
+    pub sentence_count: Option<usize>,
+    #[serde(default)]
+    pub word_count: Option<usize>,
+    #[serde(default)]
+    pub character_count: Option<usize>,
+    /// TTS provider narrating the chunk, e.g. "minimax".
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchedFolder {
+    pub path: String,
+    #[serde(default)]
+    pub remove_on_delete: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LibraryChangeKind {
+    Added,
+    Updated,
+    Removed,
+}
+
+/// Payload of the `library-changed` event emitted when the backend changes
+/// the library on its own, e.g. from a watched folder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryChange {
+    pub kind: LibraryChangeKind,
+    pub book_id: String,
+    pub title: String,
+    pub source_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportFailure {
+    pub source_path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportProgress {
+    pub done: usize,
+    pub total: usize,
+    pub title: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryImportSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub skipped: Vec<String>,
+    pub library: Vec<BookEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// An OPDS 1.2 (Atom) or 2.0 (JSON) feed reduced to what the catalog browser
+/// shows. All links are absolute.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsFeed {
+    pub url: String,
+    pub title: String,
+    pub navigation: Vec<OpdsLink>,
+    pub publications: Vec<OpdsPublication>,
+    pub groups: Vec<OpdsGroup>,
+    pub facets: Vec<OpdsFacetGroup>,
+    /// A URL template or an OpenSearch description; pass it to `opds_search`.
+    pub search_url: Option<String>,
+    pub pagination: OpdsPagination,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsLink {
+    pub href: String,
+    pub title: Option<String>,
+    pub rel: Option<String>,
+    pub media_type: Option<String>,
+    pub count: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsPublication {
+    pub id: Option<String>,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub summary: Option<String>,
+    pub language: Option<String>,
+    pub updated: Option<String>,
+    pub cover_url: Option<String>,
+    pub thumbnail_url: Option<String>,
+    /// Links whose rel is an `http://opds-spec.org/acquisition` variant.
+    pub acquisitions: Vec<OpdsLink>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsGroup {
+    pub title: String,
+    pub navigation: Vec<OpdsLink>,
+    pub publications: Vec<OpdsPublication>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsFacetGroup {
+    pub title: String,
+    pub facets: Vec<OpdsFacet>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct MinimaxCloneRequest {
-    pub file_id: i64,
-    pub voice_id: String,
+pub struct OpdsFacet {
+    pub link: OpdsLink,
+    pub active: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsPagination {
+    pub first: Option<String>,
+    pub previous: Option<String>,
+    pub next: Option<String>,
+    pub last: Option<String>,
+    pub total_results: Option<u64>,
+    pub items_per_page: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportResultKind {
+    Added,
+    Updated,
+    Unchanged,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct MinimaxCloneResponse {
+pub struct ImportResult {
+    pub kind: ImportResultKind,
+    pub book: BookEntry,
+}
+
+/// Settings of the built-in OPDS server, which publishes the library to
+/// e-readers on the local network. It listens on this machine only until
+/// another address is chosen, which requires credentials.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OpdsServerSettings {
+    pub enabled: bool,
+    pub bind_address: String,
+    pub port: u16,
+    /// Requires HTTP basic auth when set.
+    pub credentials: Option<OpdsCredentials>,
+}
+
+impl Default for OpdsServerSettings {
+    fn default() -> Self {
+        OpdsServerSettings {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            port: 8090,
+            credentials: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsServerStatus {
+    pub settings: OpdsServerSettings,
+    pub running: bool,
+    /// Address the server is listening on, e.g. `0.0.0.0:8090`. The catalog
+    /// is served under `/opds`.
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionKind {
+    Listening,
+    Reading,
+}
+
+/// A stretch of listening to or reading one book. A pause longer than the
+/// idle timeout ends the session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub id: String,
+    pub book_id: String,
+    pub kind: SessionKind,
+    pub started_at: String,
+    pub ended_at: String,
+    /// Active time, excluding pauses.
+    pub seconds: f64,
+    pub words: usize,
+    #[serde(default)]
+    pub sentences: usize,
+    #[serde(default)]
+    pub characters: usize,
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityTotals {
+    pub listening_seconds: f64,
+    pub reading_seconds: f64,
+    pub words_listened: usize,
+    pub words_read: usize,
+    pub sentences_narrated: usize,
+    pub characters_narrated: usize,
+    pub sessions: usize,
+}
+
+/// Totals for one local day, or for the week starting on `date`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatedTotals {
+    pub date: String,
+    #[serde(flatten)]
+    pub totals: ActivityTotals,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderUsage {
+    pub provider: String,
+    pub sessions: usize,
+    pub seconds: f64,
+    pub characters: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsOverview {
+    pub totals: ActivityTotals,
+    /// One entry per day, oldest first, including days without activity.
+    pub days: Vec<DatedTotals>,
+    /// Consecutive days with activity, ending today or yesterday.
+    pub current_streak: usize,
+    pub longest_streak: usize,
+    pub listening_wpm: Option<f64>,
+    pub reading_wpm: Option<f64>,
+    pub providers: Vec<ProviderUsage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookStats {
+    pub book_id: String,
+    pub title: String,
+    #[serde(flatten)]
+    pub totals: ActivityTotals,
+    pub progress: f64,
+    pub remaining_words: usize,
+    /// Time left at the book's average speed, or the overall average when
+    /// the book has too little history.
+    pub estimated_listening_seconds: Option<f64>,
+    pub estimated_reading_seconds: Option<f64>,
+    pub last_session_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorName {
+    pub name: String,
+    /// Derived from `name` when missing.
+    #[serde(default)]
+    pub sort_name: Option<String>,
+}
+
+/// Edited book metadata. Every field replaces the current value, except that
+/// an empty `language` keeps the book's language.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataUpdate {
+    pub title: String,
+    #[serde(default)]
+    pub authors: Vec<AuthorName>,
+    #[serde(default)]
+    pub series: Option<String>,
+    #[serde(default)]
+    pub series_index: Option<f64>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// A voice the user added in the frontend, as kept in its settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedVoice {
+    pub provider: String,
     pub voice_id: String,
-    pub demo_audio: Option<String>,
+    pub label: String,
+    #[serde(default)]
+    pub is_cloned: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ElevenLabsTtsConfig {
-    pub voice_id: Option<String>,
-    pub model: Option<String>,
+pub struct BackupManifest {
+    pub format: String,
+    pub version: u64,
+    pub created_at: String,
+    pub book_count: usize,
+    pub includes_audio: bool,
+}
+
+/// What a restore does with a backed-up book that is already in the library.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictResolution {
+    /// Keep the library's copy and merge the backup's progress into it.
+    #[default]
+    KeepExisting,
+    /// Replace the library's copy with the backup's, keeping the library's id.
+    UseBackup,
+    /// Import the backup's copy as a separate book.
+    KeepBoth,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupImportOptions {
+    #[serde(default)]
+    pub conflict: ConflictResolution,
+    #[serde(default)]
+    pub restore_settings: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupImportSummary {
+    pub books_added: usize,
+    pub books_replaced: usize,
+    pub books_merged: usize,
+    pub annotations_added: usize,
+    pub audio_files_restored: usize,
+    pub skipped: Vec<String>,
+    pub library: Vec<BookEntry>,
+    pub voices: Vec<SavedVoice>,
+    pub settings: Option<serde_json::Value>,
+}
+
+/// A synthesis request for one registered TTS provider. `options` holds that
+/// provider's own settings, such as the voice and model to use.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TtsRequest {
+    pub chapter_id: String,
+    pub text: String,
+    pub provider: String,
+    #[serde(default)]
+    pub options: serde_json::Value,
+    /// The book being narrated, for its normalization settings and audio
+    /// cache accounting.
+    #[serde(default)]
+    pub book_id: Option<String>,
+    /// The book's language, which decides how text is normalized.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Where `text` lies in chapter `chapter_id`, as a narration plan's chunk
+    /// `start` and `end`, so the publisher's inline pronunciations for that
+    /// part of the chapter apply.
+    #[serde(default)]
+    pub start: Option<usize>,
+    #[serde(default)]
+    pub end: Option<usize>,
+}
+
+/// What a TTS provider supports, so the frontend can offer only what works.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TtsCapabilities {
+    pub voice_listing: bool,
+    pub voice_cloning: bool,
+    /// Whether audio arrives in pieces as it is synthesized, rather than
+    /// all at once when the clip is done.
+    pub streaming: bool,
+    pub output_formats: Vec<String>,
+    /// The longest text one request may carry, when the provider has a limit.
+    pub max_characters: Option<usize>,
+    /// Whether the provider has what it needs to run, e.g. its API key.
+    pub configured: bool,
+}
+
+/// Sent over a synthesis stream's channel: audio as it arrives, then how the
+/// stream ended.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum AudioStreamEvent {
+    #[serde(rename_all = "camelCase")]
+    Chunk { audio_base64: String, mime: String },
+    Finished { cached: bool },
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TtsProviderInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub capabilities: TtsCapabilities,
+}
+
+/// A voice offered by a provider's account or catalog.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderVoice {
+    pub voice_id: String,
+    pub name: String,
+    pub language: Option<String>,
+    pub is_cloned: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ElevenLabsCloneRequest {
+pub struct VoiceCloneRequest {
     pub name: String,
     pub filename: String,
     pub audio_base64: String,
+    /// The id to give the new voice, for providers that let the caller choose.
+    #[serde(default)]
+    pub voice_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ElevenLabsCloneResponse {
+pub struct ClonedVoice {
     pub voice_id: String,
     pub name: String,
+    pub demo_audio: Option<String>,
+}
+
+/// Which parts of the text are spelled out before synthesis. Stored per book;
+/// everything is on by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NormalizationSettings {
+    pub enabled: bool,
+    pub numbers: bool,
+    pub ordinals: bool,
+    pub currency: bool,
+    pub dates: bool,
+    pub roman_numerals: bool,
+    pub abbreviations: bool,
+    pub units: bool,
+    pub urls: bool,
+    pub dashes: bool,
+}
+
+impl Default for NormalizationSettings {
+    fn default() -> Self {
+        NormalizationSettings {
+            enabled: true,
+            numbers: true,
+            ordinals: true,
+            currency: true,
+            dates: true,
+            roman_numerals: true,
+            abbreviations: true,
+            units: true,
+            urls: true,
+            dashes: true,
+        }
+    }
+}
+
+/// How to say a word: an alias spelling, IPA, or both. Entries belong to a
+/// book or to the global lexicon; a book's entry overrides a global one for
+/// the same word.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LexiconEntry {
+    pub id: String,
+    pub grapheme: String,
+    pub alias: Option<String>,
+    pub ipa: Option<String>,
+    #[serde(default)]
+    pub case_sensitive: bool,
+}
+
+/// A lexicon entry from the frontend; without an id it is added as new.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LexiconEntryInput {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub grapheme: String,
+    #[serde(default)]
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub ipa: Option<String>,
+    #[serde(default)]
+    pub case_sensitive: bool,
+}
+
+/// A sentence of chapter text. Offsets count characters, like `charOffset`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SentenceSpan {
+    pub index: usize,
+    pub paragraph: usize,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Text sent to a TTS provider in one request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NarrationChunk {
+    pub id: String,
+    pub index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub first_sentence: usize,
+    pub sentence_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NarrationPlan {
+    pub chapter_id: String,
+    pub sentences: Vec<SentenceSpan>,
+    pub chunks: Vec<NarrationChunk>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioCacheStats {
+    pub total_bytes: u64,
+    pub max_bytes: u64,
+    pub clips: usize,
+    /// Largest first. Clips shared by several books count toward each.
+    pub books: Vec<BookAudioUsage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookAudioUsage {
+    pub book_id: String,
+    pub bytes: u64,
+    pub clips: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
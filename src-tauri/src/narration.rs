@@ -0,0 +1,25 @@
+use crate::models::NowPlaying;
+use std::sync::Mutex;
+
+/// What the reader is narrating right now, reported by the frontend as each
+/// chunk starts playing.
+#[derive(Default)]
+pub struct NarrationState(pub Mutex<Option<NowPlaying>>);
+
+impl NarrationState {
+    pub fn set(&self, now_playing: Option<NowPlaying>) -> Result<(), String> {
+        let mut guard = self
+            .0
+            .lock()
+            .map_err(|_| "Narration state lock poisoned".to_string())?;
+        *guard = now_playing;
+        Ok(())
+    }
+
+    pub fn current(&self) -> Result<Option<NowPlaying>, String> {
+        self.0
+            .lock()
+            .map(|guard| guard.clone())
+            .map_err(|_| "Narration state lock poisoned".to_string())
+    }
+}
@@ -0,0 +1,788 @@
+use crate::config;
+use crate::models::NormalizationSettings;
+use crate::storage;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use tauri::AppHandle;
+
+/// Per-book normalization toggles, keyed by book id.
+pub type NormalizationStore = HashMap<String, NormalizationSettings>;
+
+static URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(?:https?://|www\.)[^\s<>()\[\]]+").unwrap());
+static EMAIL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b[\w.+-]+@([\w-]+(?:\.[\w-]+)+)\b").unwrap());
+static DASH_RUN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[ \t]*(?:-{2,}|[—―](?:[ \t]*[—―])*)[ \t]*").unwrap());
+static RANGE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d)[ \t]?–[ \t]?(\d)").unwrap());
+/// A numbered heading at the start of a line, like "Chapter XIV" or
+/// "Part II: The Return". The numeral must end the line or be followed by
+/// punctuation, so "Part I remember" in running text is left alone.
+static HEADING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?m)^([ \t]*(?i:chapter|part|book|volume|act|scene|section|canto|appendix)[ \t]+)([IVXLCDM]+)([ \t]*(?:[.:—–-]|$))",
+    )
+    .unwrap()
+});
+static ISO_DATE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{4})-(\d{2})-(\d{2})\b").unwrap());
+static MONTH_DAY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\b(January|February|March|April|May|June|July|August|September|October|November|December|Jan|Feb|Mar|Apr|Jun|Jul|Aug|Sept|Sep|Oct|Nov|Dec)\.?\s+(\d{1,2})(?:st|nd|rd|th)?\b(?:,?\s+(\d{4})\b)?",
+    )
+    .unwrap()
+});
+static TIME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{1,2}):(\d{2})\b(?:\s?((?i:a\.?m\.?|p\.?m\.?)))?").unwrap());
+static CURRENCY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"([$£€¥])\s?(\d[\d,]*(?:\.\d+)?)(?:\s?(thousand|million|billion|trillion)\b)?")
+        .unwrap()
+});
+static PERCENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d[\d,]*(?:\.\d+)?)\s?%").unwrap());
+static DEGREES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d[\d,]*(?:\.\d+)?)\s?°\s?([CF]\b)?").unwrap());
+static UNIT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\b(\d[\d,]*(?:\.\d+)?)\s?(km/h|mph|kWh|kW|km|kg|mg|ml|cm|mm|mi|ft|lbs|lb|oz|GB|MB|TB|kHz|MHz|GHz|Hz|m|g)\b",
+    )
+    .unwrap()
+});
+static ORDINAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d+)(?i:st|nd|rd|th)\b").unwrap());
+static DECADE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(\d{3}0)s\b").unwrap());
+static NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b\d{1,3}(?:,\d{3})+(?:\.\d+)?\b|\b\d+(?:\.\d+)?\b").unwrap());
+static ABBREVIATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\b(Dr|Mr|Mrs|Ms|Prof|Jr|Sr|St|Mt|Capt|Lt|Sgt|Col|Rev|Hon|Vol|Ave|Blvd|Rd|vs|etc|approx|e\.g|i\.e)\.",
+    )
+    .unwrap()
+});
+static SPACES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[ \t]{2,}").unwrap());
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+const SCALES: [(u64, &str); 4] = [
+    (1_000_000_000_000, "trillion"),
+    (1_000_000_000, "billion"),
+    (1_000_000, "million"),
+    (1_000, "thousand"),
+];
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+pub fn load_settings(
+    app: &AppHandle,
+    book_id: Option<&str>,
+) -> Result<NormalizationSettings, String> {
+    let Some(book_id) = book_id else {
+        return Ok(NormalizationSettings::default());
+    };
+    let store: NormalizationStore = storage::read_json(&config::get_normalization_path(app)?)?;
+    Ok(store.get(book_id).cloned().unwrap_or_default())
+}
+
+pub fn save_settings(
+    app: &AppHandle,
+    book_id: &str,
+    settings: NormalizationSettings,
+) -> Result<(), String> {
+    let path = config::get_normalization_path(app)?;
+    let mut store: NormalizationStore = storage::read_json(&path)?;
+    if settings == NormalizationSettings::default() {
+        store.remove(book_id);
+    } else {
+        store.insert(book_id.to_string(), settings);
+    }
+    storage::write_json(&path, &store)
+}
+
+/// Rewrites `text` the way a narrator would read it aloud. Spelling out
+/// numbers, dates and abbreviations is only done for English; other
+/// languages get the language-neutral cleanups.
+pub fn normalize(text: &str, language: Option<&str>, settings: &NormalizationSettings) -> String {
+    if !settings.enabled {
+        return text.to_string();
+    }
+    let english = language
+        .and_then(|language| language.split(['-', '_']).next())
+        .is_none_or(|primary| primary.trim().eq_ignore_ascii_case("en"));
+    let mut text = text.to_string();
+
+    if settings.urls {
+        text = replace_urls(&text, english);
+    }
+    if settings.dashes {
+        text = DASH_RUN.replace_all(&text, " — ").into_owned();
+        if english {
+            text = RANGE.replace_all(&text, "$1 to $2").into_owned();
+        }
+    }
+    if english {
+        if settings.roman_numerals {
+            text = replace_headings(&text);
+        }
+        if settings.dates {
+            text = replace_dates(&text);
+        }
+        if settings.currency {
+            text = CURRENCY.replace_all(&text, currency).into_owned();
+        }
+        if settings.units {
+            text = replace_units(&text);
+        }
+        if settings.ordinals {
+            text = ORDINAL
+                .replace_all(&text, |caps: &Captures| match caps[1].parse::<u64>() {
+                    Ok(value) => ordinal(value),
+                    Err(_) => caps[0].to_string(),
+                })
+                .into_owned();
+        }
+        if settings.numbers {
+            text = replace_numbers(&text);
+        }
+        if settings.abbreviations {
+            text = replace_abbreviations(&text);
+        }
+    }
+    SPACES.replace_all(&text, " ").trim().to_string()
+}
+
+/// Reads URLs as their domain and email addresses as "name at domain".
+fn replace_urls(text: &str, english: bool) -> String {
+    let text = URL.replace_all(text, |caps: &Captures| {
+        let url = &caps[0];
+        // Sentence punctuation after a URL isn't part of it.
+        let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"']);
+        let host = trimmed
+            .split("://")
+            .last()
+            .unwrap_or(trimmed)
+            .split(['/', '?', '#'])
+            .next()
+            .unwrap_or("");
+        let host = host.strip_prefix("www.").unwrap_or(host);
+        let host = if english {
+            host.replace('.', " dot ")
+        } else {
+            host.to_string()
+        };
+        format!("{}{}", host, &url[trimmed.len()..])
+    });
+    if !english {
+        return text.into_owned();
+    }
+    EMAIL
+        .replace_all(&text, |caps: &Captures| {
+            let (user, _) = caps[0].split_once('@').unwrap_or((&caps[0], ""));
+            format!(
+                "{} at {}",
+                user.replace('.', " dot "),
+                caps[1].replace('.', " dot ")
+            )
+        })
+        .into_owned()
+}
+
+fn replace_headings(text: &str) -> String {
+    let text = HEADING.replace_all(text, |caps: &Captures| match roman(&caps[2]) {
+        Some(value) => format!("{}{}{}", &caps[1], cardinal(value), &caps[3]),
+        None => caps[0].to_string(),
+    });
+    // A chapter heading that is only a numeral, like "XIV". A lone "I" is
+    // far more likely the pronoun.
+    let trimmed = text.trim().trim_end_matches('.');
+    if trimmed.len() >= 2 {
+        if let Some(value) = roman(trimmed) {
+            return capitalize(&cardinal(value));
+        }
+    }
+    text.into_owned()
+}
+
+fn replace_dates(text: &str) -> String {
+    let text = ISO_DATE.replace_all(text, |caps: &Captures| {
+        let year: u64 = caps[1].parse().unwrap_or(0);
+        let month: usize = caps[2].parse().unwrap_or(0);
+        let day: u64 = caps[3].parse().unwrap_or(0);
+        match MONTHS.get(month.wrapping_sub(1)) {
+            Some(name) if (1..=31).contains(&day) => {
+                format!("{} {}, {}", name, ordinal(day), year_words(year))
+            }
+            _ => caps[0].to_string(),
+        }
+    });
+    let text = MONTH_DAY.replace_all(&text, |caps: &Captures| {
+        let day: u64 = caps[2].parse().unwrap_or(0);
+        if !(1..=31).contains(&day) {
+            return caps[0].to_string();
+        }
+        let month = MONTHS
+            .iter()
+            .find(|month| month.starts_with(&caps[1]))
+            .copied()
+            .unwrap_or("");
+        match caps
+            .get(3)
+            .and_then(|year| year.as_str().parse::<u64>().ok())
+        {
+            Some(year) => format!("{} {}, {}", month, ordinal(day), year_words(year)),
+            None => format!("{} {}", month, ordinal(day)),
+        }
+    });
+    TIME.replace_all(&text, |caps: &Captures| {
+        let hour: u64 = caps[1].parse().unwrap_or(99);
+        let minute: u64 = caps[2].parse().unwrap_or(99);
+        if hour > 24 || minute > 59 {
+            return caps[0].to_string();
+        }
+        let suffix = caps
+            .get(3)
+            .map(|meridiem| {
+                let letter = meridiem.as_str().chars().next().unwrap_or('a');
+                format!(" {}.m.", letter.to_ascii_lowercase())
+            })
+            .unwrap_or_default();
+        let minutes = match minute {
+            0 if suffix.is_empty() => " o'clock".to_string(),
+            0 => String::new(),
+            1..=9 => format!(" oh {}", ONES[minute as usize]),
+            _ => format!(" {}", cardinal(minute)),
+        };
+        format!("{}{}{}", cardinal(hour), minutes, suffix)
+    })
+    .into_owned()
+}
+
+fn currency(caps: &Captures) -> String {
+    let (unit, units, cent, cents) = match &caps[1] {
+        "$" => ("dollar", "dollars", "cent", "cents"),
+        "£" => ("pound", "pounds", "penny", "pence"),
+        "€" => ("euro", "euros", "cent", "cents"),
+        _ => ("yen", "yen", "", ""),
+    };
+    let amount = caps[2].replace(',', "");
+    if let Some(scale) = caps.get(3) {
+        return format!("{} {} {}", spell_number(&amount), scale.as_str(), units);
+    }
+    let (whole, fraction) = amount.split_once('.').unwrap_or((&amount, ""));
+    let Ok(whole) = whole.parse::<u64>() else {
+        return caps[0].to_string();
+    };
+    let mut words = format!(
+        "{} {}",
+        cardinal(whole),
+        if whole == 1 { unit } else { units }
+    );
+    if fraction.len() == 2 && !cent.is_empty() {
+        let fraction: u64 = fraction.parse().unwrap_or(0);
+        if fraction > 0 {
+            words.push_str(&format!(
+                " and {} {}",
+                cardinal(fraction),
+                if fraction == 1 { cent } else { cents }
+            ));
+        }
+    } else if !fraction.is_empty() {
+        return format!("{} {}", spell_number(&amount), units);
+    }
+    words
+}
+
+fn replace_units(text: &str) -> String {
+    let text = PERCENT.replace_all(text, |caps: &Captures| {
+        format!("{} percent", spell_number(&caps[1].replace(',', "")))
+    });
+    let text = DEGREES.replace_all(&text, |caps: &Captures| {
+        let number = caps[1].replace(',', "");
+        let scale = match caps.get(2).map(|scale| scale.as_str()) {
+            Some("C") => " Celsius",
+            Some("F") => " Fahrenheit",
+            _ => "",
+        };
+        let unit = if number == "1" { "degree" } else { "degrees" };
+        format!("{} {}{}", spell_number(&number), unit, scale)
+    });
+    UNIT.replace_all(&text, |caps: &Captures| {
+        let number = caps[1].replace(',', "");
+        let (singular, plural) = match &caps[2] {
+            "km/h" => ("kilometer per hour", "kilometers per hour"),
+            "mph" => ("mile per hour", "miles per hour"),
+            "kWh" => ("kilowatt hour", "kilowatt hours"),
+            "kW" => ("kilowatt", "kilowatts"),
+            "km" => ("kilometer", "kilometers"),
+            "kg" => ("kilogram", "kilograms"),
+            "mg" => ("milligram", "milligrams"),
+            "ml" => ("milliliter", "milliliters"),
+            "cm" => ("centimeter", "centimeters"),
+            "mm" => ("millimeter", "millimeters"),
+            "mi" => ("mile", "miles"),
+            "ft" => ("foot", "feet"),
+            "lb" | "lbs" => ("pound", "pounds"),
+            "oz" => ("ounce", "ounces"),
+            "GB" => ("gigabyte", "gigabytes"),
+            "MB" => ("megabyte", "megabytes"),
+            "TB" => ("terabyte", "terabytes"),
+            "kHz" => ("kilohertz", "kilohertz"),
+            "MHz" => ("megahertz", "megahertz"),
+            "GHz" => ("gigahertz", "gigahertz"),
+            "Hz" => ("hertz", "hertz"),
+            "m" => ("meter", "meters"),
+            _ => ("gram", "grams"),
+        };
+        let unit = if number == "1" { singular } else { plural };
+        format!("{} {}", spell_number(&number), unit)
+    })
+    .into_owned()
+}
+
+fn replace_numbers(text: &str) -> String {
+    let text = DECADE.replace_all(text, |caps: &Captures| {
+        let year: u64 = caps[1].parse().unwrap_or(0);
+        if (1000..2100).contains(&year) {
+            plural(&year_words(year))
+        } else {
+            caps[0].to_string()
+        }
+    });
+    NUMBER
+        .replace_all(&text, |caps: &Captures| {
+            let number = &caps[0];
+            match number.parse::<u64>() {
+                // Four-digit numbers read as years: "nineteen eighty-four",
+                // which is also how "1500 soldiers" is usually said.
+                Ok(year) if number.len() == 4 && (1100..2100).contains(&year) => year_words(year),
+                _ => spell_number(&number.replace(',', "")),
+            }
+        })
+        .into_owned()
+}
+
+/// Expands abbreviations, reading "St." and "Dr." as "Saint" and "Doctor"
+/// before a name but "Street" and "Drive" after one.
+fn replace_abbreviations(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut last = 0;
+    for caps in ABBREVIATION.captures_iter(text) {
+        let whole = caps.get(0).unwrap();
+        let before = &text[..whole.start()];
+        let after = &text[whole.end()..];
+        let next_capitalized = after
+            .strip_prefix(' ')
+            .is_some_and(|rest| rest.starts_with(char::is_uppercase));
+        let ends_sentence = after.trim().is_empty() || next_capitalized;
+
+        let (expansion, is_title) = match &caps[1] {
+            "Dr" | "St" => {
+                let address = !next_capitalized || follows_name(before);
+                match (&caps[1], address) {
+                    ("Dr", true) => ("Drive", false),
+                    ("Dr", false) => ("Doctor", true),
+                    (_, true) => ("Street", false),
+                    _ => ("Saint", true),
+                }
+            }
+            "Mr" => ("Mister", true),
+            "Mrs" => ("Missus", true),
+            "Ms" => ("Miz", true),
+            "Prof" => ("Professor", true),
+            "Mt" => ("Mount", true),
+            "Capt" => ("Captain", true),
+            "Lt" => ("Lieutenant", true),
+            "Sgt" => ("Sergeant", true),
+            "Col" => ("Colonel", true),
+            "Rev" => ("Reverend", true),
+            "Hon" => ("Honorable", true),
+            "Vol" => ("Volume", true),
+            "Jr" => ("Junior", false),
+            "Sr" => ("Senior", false),
+            "Ave" => ("Avenue", false),
+            "Blvd" => ("Boulevard", false),
+            "Rd" => ("Road", false),
+            "vs" => ("versus", true),
+            "etc" => ("et cetera", false),
+            "approx" => ("approximately", true),
+            "e.g" => ("for example", true),
+            _ => ("that is", true),
+        };
+        output.push_str(&text[last..whole.start()]);
+        output.push_str(expansion);
+        // Keep the period when the abbreviation also ended the sentence.
+        if !is_title && ends_sentence {
+            output.push('.');
+        }
+        last = whole.end();
+    }
+    output.push_str(&text[last..]);
+    output
+}
+
+/// Whether the word before an abbreviation is part of a street name, like
+/// "Baker" in "Baker St.", rather than the start of a sentence.
+fn follows_name(before: &str) -> bool {
+    let mut words = before.split_whitespace().rev();
+    let Some(previous) = words.next() else {
+        return false;
+    };
+    if previous.starts_with(|c: char| c.is_ascii_digit()) {
+        return true;
+    }
+    let sentence_start = words
+        .next()
+        .is_none_or(|word| word.ends_with(['.', '!', '?', ':']));
+    previous.starts_with(char::is_uppercase) && !sentence_start
+}
+
+/// Reads digits as a number, or one by one for leading zeros and numbers
+/// too long to be quantities.
+fn spell_number(number: &str) -> String {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let mut words = if (whole.len() > 1 && whole.starts_with('0')) || whole.len() > 15 {
+        digits(whole)
+    } else {
+        match whole.parse::<u64>() {
+            Ok(value) => cardinal(value),
+            Err(_) => digits(whole),
+        }
+    };
+    if !fraction.is_empty() {
+        words.push_str(" point ");
+        words.push_str(&digits(fraction));
+    }
+    words
+}
+
+fn digits(number: &str) -> String {
+    number
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|digit| ONES[digit as usize])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn cardinal(value: u64) -> String {
+    if value < 20 {
+        return ONES[value as usize].to_string();
+    }
+    if value < 100 {
+        let tens = TENS[(value / 10) as usize];
+        return match value % 10 {
+            0 => tens.to_string(),
+            ones => format!("{}-{}", tens, ONES[ones as usize]),
+        };
+    }
+    if value < 1000 {
+        let hundreds = format!("{} hundred", ONES[(value / 100) as usize]);
+        return match value % 100 {
+            0 => hundreds,
+            rest => format!("{} {}", hundreds, cardinal(rest)),
+        };
+    }
+    let (scale, name) = SCALES
+        .iter()
+        .find(|(scale, _)| value >= *scale)
+        .copied()
+        .unwrap_or((1_000, "thousand"));
+    let words = format!("{} {}", cardinal(value / scale), name);
+    match value % scale {
+        0 => words,
+        rest => format!("{} {}", words, cardinal(rest)),
+    }
+}
+
+fn ordinal(value: u64) -> String {
+    let words = cardinal(value);
+    let split = words.rfind([' ', '-']).map(|index| index + 1).unwrap_or(0);
+    let (head, last) = words.split_at(split);
+    let last = match last {
+        "one" => "first".to_string(),
+        "two" => "second".to_string(),
+        "three" => "third".to_string(),
+        "five" => "fifth".to_string(),
+        "eight" => "eighth".to_string(),
+        "nine" => "ninth".to_string(),
+        "twelve" => "twelfth".to_string(),
+        word if word.ends_with('y') => format!("{}ieth", &word[..word.len() - 1]),
+        word => format!("{}th", word),
+    };
+    format!("{}{}", head, last)
+}
+
+fn year_words(year: u64) -> String {
+    if !(1000..2100).contains(&year) || (2000..2010).contains(&year) || year.is_multiple_of(1000) {
+        return cardinal(year);
+    }
+    let century = cardinal(year / 100);
+    match year % 100 {
+        0 => format!("{} hundred", century),
+        rest @ 1..=9 => format!("{} oh {}", century, ONES[rest as usize]),
+        rest => format!("{} {}", century, cardinal(rest)),
+    }
+}
+
+fn plural(words: &str) -> String {
+    match words.strip_suffix('y') {
+        Some(stem) => format!("{}ies", stem),
+        None => format!("{}s", words),
+    }
+}
+
+/// The value of a well-formed Roman numeral such as "XIV".
+fn roman(numeral: &str) -> Option<u64> {
+    let value_of = |c: char| match c {
+        'I' => Some(1),
+        'V' => Some(5),
+        'X' => Some(10),
+        'L' => Some(50),
+        'C' => Some(100),
+        'D' => Some(500),
+        'M' => Some(1000),
+        _ => None,
+    };
+    let values: Vec<u64> = numeral.chars().map(value_of).collect::<Option<_>>()?;
+    let mut total = 0;
+    for (index, value) in values.iter().enumerate() {
+        match values.get(index + 1) {
+            Some(next) if next > value => total -= *value as i64,
+            _ => total += *value as i64,
+        }
+    }
+    let total = u64::try_from(total).ok().filter(|total| *total > 0)?;
+    // Reject malformed numerals like "IIII" or "VX".
+    (to_roman(total) == numeral).then_some(total)
+}
+
+fn to_roman(mut value: u64) -> String {
+    const NUMERALS: [(u64, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut numeral = String::new();
+    for (amount, symbol) in NUMERALS {
+        while value >= amount {
+            numeral.push_str(symbol);
+            value -= amount;
+        }
+    }
+    numeral
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn english(text: &str) -> String {
+        normalize(text, Some("en"), &NormalizationSettings::default())
+    }
+
+    fn assert_cases(cases: &[(&str, &str)]) {
+        for (input, expected) in cases {
+            assert_eq!(english(input), *expected, "normalizing {:?}", input);
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        assert_cases(&[
+            ("I have 3 cats.", "I have three cats."),
+            (
+                "It cost 1,250,000 in total.",
+                "It cost one million two hundred fifty thousand in total.",
+            ),
+            ("Pi is about 3.14.", "Pi is about three point one four."),
+            ("Call 007 now.", "Call zero zero seven now."),
+            ("Born in 1984.", "Born in nineteen eighty-four."),
+            ("The 1960s were loud.", "The nineteen sixties were loud."),
+            ("In 2005 it ended.", "In two thousand five it ended."),
+        ]);
+    }
+
+    #[test]
+    fn ordinals() {
+        assert_cases(&[
+            ("She came 1st.", "She came first."),
+            ("The 22nd time.", "The twenty-second time."),
+            ("On the 103rd day.", "On the one hundred third day."),
+            ("His 40th birthday.", "His fortieth birthday."),
+        ]);
+    }
+
+    #[test]
+    fn currency() {
+        assert_cases(&[
+            ("It costs $5.", "It costs five dollars."),
+            ("Only $1.01 left.", "Only one dollar and one cent left."),
+            ("Pay £20.50 now.", "Pay twenty pounds and fifty pence now."),
+            ("A €3 million deal.", "A three million euros deal."),
+            ("That is ¥500.", "That is five hundred yen."),
+        ]);
+    }
+
+    #[test]
+    fn dates_and_times() {
+        assert_cases(&[
+            (
+                "On 2024-03-05 we left.",
+                "On March fifth, twenty twenty-four we left.",
+            ),
+            (
+                "Due Jan. 3, 1999.",
+                "Due January third, nineteen ninety-nine.",
+            ),
+            ("By July 4th.", "By July fourth."),
+            ("Meet at 9:05 pm.", "Meet at nine oh five p.m."),
+            ("Meet at 10:00.", "Meet at ten o'clock."),
+            ("Leave at 7:30 a.m.", "Leave at seven thirty a.m."),
+        ]);
+    }
+
+    #[test]
+    fn units() {
+        assert_cases(&[
+            ("Up 15% today.", "Up fifteen percent today."),
+            (
+                "It was 30°C outside.",
+                "It was thirty degrees Celsius outside.",
+            ),
+            ("Drive 5 km north.", "Drive five kilometers north."),
+            ("Weighs 1 kg.", "Weighs one kilogram."),
+        ]);
+    }
+
+    #[test]
+    fn abbreviations() {
+        assert_cases(&[
+            ("Dr. Watson arrived.", "Doctor Watson arrived."),
+            (
+                "He lives on Baker St. in London.",
+                "He lives on Baker Street in London.",
+            ),
+            ("We visited St. Paul today.", "We visited Saint Paul today."),
+            ("Mr. and Mrs. Smith", "Mister and Missus Smith"),
+            ("Apples, pears, etc.", "Apples, pears, et cetera."),
+        ]);
+    }
+
+    #[test]
+    fn headings() {
+        assert_cases(&[
+            ("Chapter XIV", "Chapter fourteen"),
+            ("CHAPTER IV. The Return", "CHAPTER four. The Return"),
+            ("Part II: Home", "Part two: Home"),
+            ("Book I", "Book one"),
+            ("XIV", "Fourteen"),
+        ]);
+    }
+
+    #[test]
+    fn pronoun_i_is_not_a_numeral() {
+        assert_cases(&[
+            (
+                "The best part I remember is this.",
+                "The best part I remember is this.",
+            ),
+            (
+                "In this section I will explain.",
+                "In this section I will explain.",
+            ),
+            ("Part I will explain later.", "Part I will explain later."),
+            ("I", "I"),
+        ]);
+    }
+
+    #[test]
+    fn dashes_are_spaced_alike() {
+        assert_cases(&[
+            ("Wait—no——stop", "Wait — no — stop"),
+            ("Wait -- no", "Wait — no"),
+            ("Pages 10–12.", "Pages ten to twelve."),
+        ]);
+    }
+
+    #[test]
+    fn urls_and_email() {
+        assert_cases(&[
+            ("See https://www.example.com/page.", "See example dot com."),
+            (
+                "Mail jane.doe@example.org today.",
+                "Mail jane dot doe at example dot org today.",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn other_languages_keep_numbers() {
+        let settings = NormalizationSettings::default();
+        assert_eq!(
+            normalize("Er hat 3 Katzen — und Chapter IV", Some("de-DE"), &settings),
+            "Er hat 3 Katzen — und Chapter IV"
+        );
+    }
+
+    #[test]
+    fn disabled_leaves_text_alone() {
+        let settings = NormalizationSettings {
+            enabled: false,
+            ..NormalizationSettings::default()
+        };
+        assert_eq!(normalize("  Dr. 3  ", Some("en"), &settings), "  Dr. 3  ");
+    }
+}
@@ -0,0 +1,522 @@
+use crate::epub;
+use crate::http::Http;
+use crate::importer;
+use crate::models::{
+    ImportResult, OpdsCredentials, OpdsFacet, OpdsFacetGroup, OpdsFeed, OpdsGroup, OpdsLink,
+    OpdsPagination, OpdsPublication,
+};
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::{StatusCode, Url};
+use roxmltree::{Document, Node};
+use serde_json::Value;
+use tauri::AppHandle;
+
+const FEED_ACCEPT: &str =
+    "application/opds+json, application/atom+xml;q=0.9, application/xml;q=0.8, */*;q=0.5";
+const OPENSEARCH_ACCEPT: &str = "application/opensearchdescription+xml, */*;q=0.5";
+const DOWNLOAD_ACCEPT: &str = "application/epub+zip, */*;q=0.5";
+
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+const IMAGE_REL: &str = "http://opds-spec.org/image";
+const THUMBNAIL_REL: &str = "http://opds-spec.org/image/thumbnail";
+const FACET_REL: &str = "http://opds-spec.org/facet";
+
+struct Fetched {
+    url: Url,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+pub async fn browse(
+    http: &Http,
+    url: &str,
+    credentials: Option<&OpdsCredentials>,
+) -> Result<OpdsFeed, String> {
+    let fetched = fetch(http, url, credentials, FEED_ACCEPT).await?;
+    let text = String::from_utf8_lossy(&fetched.body);
+    let is_json = fetched.content_type.contains("json") || text.trim_start().starts_with('{');
+    if is_json {
+        parse_json_feed(&fetched.url, &text)
+    } else {
+        parse_atom_feed(&fetched.url, &text)
+    }
+}
+
+/// Runs a search through a feed's `search_url`, which is either a URL
+/// template or an OpenSearch description that points to one.
+pub async fn search(
+    http: &Http,
+    search_url: &str,
+    query: &str,
+    credentials: Option<&OpdsCredentials>,
+) -> Result<OpdsFeed, String> {
+    let template = if search_url.contains('{') {
+        search_url.to_string()
+    } else {
+        let description = fetch(http, search_url, credentials, OPENSEARCH_ACCEPT).await?;
+        opensearch_template(&description)?
+    };
+    browse(http, &expand_template(&template, query), credentials).await
+}
+
+/// Downloads an acquisition link and runs it through the regular importer.
+pub async fn download(
+    app: &AppHandle,
+    http: &Http,
+    url: &str,
+    credentials: Option<&OpdsCredentials>,
+) -> Result<ImportResult, String> {
+    let fetched = fetch(http, url, credentials, DOWNLOAD_ACCEPT).await?;
+    // EPUBs are zip files; anything else (PDF, MOBI, an HTML login page)
+    // cannot be imported.
+    if !fetched.body.starts_with(b"PK") {
+        let kind = if fetched.content_type.is_empty() {
+            "an unknown file type"
+        } else {
+            fetched.content_type.as_str()
+        };
+        return Err(format!("Only EPUB downloads can be imported, got {}.", kind));
+    }
+    importer::import_epub(app, fetched.body, None).map(|outcome| outcome.into_result())
+}
+
+async fn fetch(
+    http: &Http,
+    url: &str,
+    credentials: Option<&OpdsCredentials>,
+    accept: &str,
+) -> Result<Fetched, String> {
+    let mut request = http.client().get(url).header(ACCEPT, accept);
+    if let Some(credentials) = credentials {
+        request = request.basic_auth(&credentials.username, Some(&credentials.password));
+    }
+    let response = http
+        .send(request)
+        .await
+        .map_err(|error| format!("OPDS request failed: {error}"))?;
+
+    let status = response.status();
+    if status == StatusCode::UNAUTHORIZED {
+        return Err("The catalog requires a username and password.".to_string());
+    }
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("OPDS server returned {}: {}", status, body));
+    }
+
+    let url = response.url().clone();
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_lowercase();
+    let body = http
+        .bytes(response)
+        .await
+        .map_err(|error| format!("Failed reading OPDS response: {error}"))?;
+    Ok(Fetched {
+        url,
+        content_type,
+        body,
+    })
+}
+
+fn parse_atom_feed(base: &Url, xml: &str) -> Result<OpdsFeed, String> {
+    let document = Document::parse(xml).map_err(|error| format!("Invalid OPDS feed: {error}"))?;
+    let root = document.root_element();
+    if root.tag_name().name() != "feed" {
+        return Err("The URL did not return an OPDS catalog.".to_string());
+    }
+
+    let mut feed = OpdsFeed {
+        url: base.to_string(),
+        title: child_text(root, "title").unwrap_or_default(),
+        ..OpdsFeed::default()
+    };
+    feed.pagination.total_results = child_text(root, "totalResults").and_then(|n| n.parse().ok());
+    feed.pagination.items_per_page = child_text(root, "itemsPerPage").and_then(|n| n.parse().ok());
+
+    for node in children(root, "link") {
+        let rel = attribute(node, "rel").unwrap_or("");
+        if rel == "search" {
+            feed.search_url = attribute(node, "href").map(|href| resolve_template(base, href));
+            continue;
+        }
+        let Some(link) = atom_link(base, node) else {
+            continue;
+        };
+        if rel == FACET_REL {
+            let group = attribute(node, "facetGroup").unwrap_or("").to_string();
+            let facet = OpdsFacet {
+                link,
+                active: attribute(node, "activeFacet") == Some("true"),
+            };
+            add_facet(&mut feed.facets, group, facet);
+        } else {
+            set_page_link(&mut feed.pagination, rel, link.href);
+        }
+    }
+
+    for entry in children(root, "entry") {
+        let links: Vec<(Node, OpdsLink)> = children(entry, "link")
+            .filter_map(|node| atom_link(base, node).map(|link| (node, link)))
+            .collect();
+        let title = child_text(entry, "title").unwrap_or_default();
+        let acquisitions: Vec<OpdsLink> = links
+            .iter()
+            .filter(|(_, link)| is_acquisition(link))
+            .map(|(_, link)| link.clone())
+            .collect();
+
+        if acquisitions.is_empty() {
+            if let Some((_, link)) = links.iter().find(|(_, link)| is_catalog_link(link)) {
+                feed.navigation.push(OpdsLink {
+                    title: Some(title),
+                    ..link.clone()
+                });
+                continue;
+            }
+        }
+
+        let image = |rel: &str| {
+            links
+                .iter()
+                .find(|(_, link)| link.rel.as_deref() == Some(rel))
+                .map(|(_, link)| link.href.clone())
+        };
+        feed.publications.push(OpdsPublication {
+            id: child_text(entry, "id"),
+            title,
+            authors: children(entry, "author")
+                .filter_map(|author| child_text(author, "name"))
+                .collect(),
+            summary: child_text(entry, "summary")
+                .or_else(|| child_text(entry, "content"))
+                .and_then(|text| epub::description_text(&text)),
+            language: child_text(entry, "language"),
+            updated: child_text(entry, "updated").or_else(|| child_text(entry, "issued")),
+            cover_url: image(IMAGE_REL).or_else(|| image(THUMBNAIL_REL)),
+            thumbnail_url: image(THUMBNAIL_REL).or_else(|| image(IMAGE_REL)),
+            acquisitions,
+        });
+    }
+    Ok(feed)
+}
+
+fn atom_link(base: &Url, node: Node) -> Option<OpdsLink> {
+    Some(OpdsLink {
+        href: resolve(base, attribute(node, "href")?)?,
+        title: attribute(node, "title").map(str::to_string),
+        rel: attribute(node, "rel").map(str::to_string),
+        media_type: attribute(node, "type").map(str::to_string),
+        count: attribute(node, "count").and_then(|count| count.parse().ok()),
+    })
+}
+
+fn parse_json_feed(base: &Url, json: &str) -> Result<OpdsFeed, String> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|error| format!("Invalid OPDS feed: {error}"))?;
+    let metadata = &value["metadata"];
+    let mut feed = OpdsFeed {
+        url: base.to_string(),
+        title: metadata["title"].as_str().unwrap_or_default().to_string(),
+        navigation: json_links(base, &value["navigation"]),
+        publications: json_publications(base, &value["publications"]),
+        ..OpdsFeed::default()
+    };
+    feed.pagination.total_results = metadata["numberOfItems"].as_u64();
+    feed.pagination.items_per_page = metadata["itemsPerPage"].as_u64();
+
+    for link in value["links"].as_array().into_iter().flatten() {
+        let rels = json_rels(link);
+        if rels.contains(&"search") {
+            feed.search_url = link["href"].as_str().map(|href| resolve_template(base, href));
+        } else if let Some(parsed) = json_link(base, link) {
+            for rel in rels {
+                set_page_link(&mut feed.pagination, rel, parsed.href.clone());
+            }
+        }
+    }
+
+    for group in value["groups"].as_array().into_iter().flatten() {
+        feed.groups.push(OpdsGroup {
+            title: group["metadata"]["title"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            navigation: json_links(base, &group["navigation"]),
+            publications: json_publications(base, &group["publications"]),
+        });
+    }
+
+    for facet_group in value["facets"].as_array().into_iter().flatten() {
+        let title = facet_group["metadata"]["title"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        for link in facet_group["links"].as_array().into_iter().flatten() {
+            if let Some(parsed) = json_link(base, link) {
+                let facet = OpdsFacet {
+                    link: parsed,
+                    active: json_rels(link).contains(&"self"),
+                };
+                add_facet(&mut feed.facets, title.clone(), facet);
+            }
+        }
+    }
+    Ok(feed)
+}
+
+fn json_links(base: &Url, value: &Value) -> Vec<OpdsLink> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|link| json_link(base, link))
+        .collect()
+}
+
+fn json_link(base: &Url, value: &Value) -> Option<OpdsLink> {
+    Some(OpdsLink {
+        href: resolve(base, value["href"].as_str()?)?,
+        title: value["title"].as_str().map(str::to_string),
+        rel: json_rels(value).first().map(|rel| rel.to_string()),
+        media_type: value["type"].as_str().map(str::to_string),
+        count: value["properties"]["numberOfItems"].as_u64(),
+    })
+}
+
+/// `rel` may be a single string or a list of them.
+fn json_rels(link: &Value) -> Vec<&str> {
+    match &link["rel"] {
+        Value::String(rel) => vec![rel.as_str()],
+        Value::Array(rels) => rels.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn json_publications(base: &Url, value: &Value) -> Vec<OpdsPublication> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|publication| json_publication(base, publication))
+        .collect()
+}
+
+fn json_publication(base: &Url, value: &Value) -> OpdsPublication {
+    let metadata = &value["metadata"];
+    let links = json_links(base, &value["links"]);
+    let images = json_links(base, &value["images"]);
+    // Images are listed best first; the smallest by width makes the thumbnail.
+    let thumbnail = value["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .zip(&images)
+        .filter_map(|(image, link)| image["width"].as_u64().map(|width| (width, link)))
+        .min_by_key(|(width, _)| *width)
+        .map(|(_, link)| link.href.clone());
+
+    OpdsPublication {
+        id: metadata["identifier"].as_str().map(str::to_string),
+        title: json_text(&metadata["title"]).unwrap_or_default(),
+        authors: json_names(&metadata["author"]),
+        summary: metadata["description"]
+            .as_str()
+            .and_then(epub::description_text),
+        language: match &metadata["language"] {
+            Value::Array(languages) => languages.first().and_then(Value::as_str),
+            other => other.as_str(),
+        }
+        .map(str::to_string),
+        updated: metadata["modified"]
+            .as_str()
+            .or_else(|| metadata["published"].as_str())
+            .map(str::to_string),
+        cover_url: images.first().map(|link| link.href.clone()),
+        thumbnail_url: thumbnail.or_else(|| images.first().map(|link| link.href.clone())),
+        acquisitions: links.into_iter().filter(is_acquisition).collect(),
+    }
+}
+
+/// Titles may be a plain string or a map of language to string.
+fn json_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Object(map) => map.values().find_map(Value::as_str).map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Contributors may be a name, an object with a `name`, or a list of either.
+fn json_names(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(items) => items.iter().flat_map(json_names).collect(),
+        Value::Object(_) => json_text(&value["name"]).into_iter().collect(),
+        other => json_text(other).into_iter().collect(),
+    }
+}
+
+fn opensearch_template(fetched: &Fetched) -> Result<String, String> {
+    let xml = String::from_utf8_lossy(&fetched.body);
+    let document = Document::parse(&xml)
+        .map_err(|error| format!("Invalid OpenSearch description: {error}"))?;
+    let urls: Vec<(&str, &str)> = document
+        .descendants()
+        .filter(|node| node.is_element() && node.tag_name().name() == "Url")
+        .filter_map(|node| Some((attribute(node, "type")?, attribute(node, "template")?)))
+        .collect();
+    let template = urls
+        .iter()
+        .find(|(media_type, _)| media_type.contains("opds") || media_type.contains("atom"))
+        .or(urls.first())
+        .map(|(_, template)| *template)
+        .ok_or_else(|| "The catalog's search description has no search URL.".to_string())?;
+    Ok(resolve_template(&fetched.url, template))
+}
+
+/// Fills an OpenSearch (`{searchTerms}`) or RFC 6570 (`{?query}`) template
+/// with `query`. Other variables, such as optional paging parameters, are
+/// left out.
+fn expand_template(template: &str, query: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let expression = &rest[start + 1..start + length];
+        match expression.chars().next() {
+            Some(operator @ ('?' | '&')) => {
+                if expression[1..].split(',').any(|name| name == "query") {
+                    expanded.push(operator);
+                    expanded.push_str("query=");
+                    expanded.push_str(&encode_component(query));
+                }
+            }
+            _ => {
+                let name = expression.trim_end_matches('?');
+                let name = name.rsplit(':').next().unwrap_or(name);
+                if name == "searchTerms" || name == "query" {
+                    expanded.push_str(&encode_component(query));
+                }
+            }
+        }
+        rest = &rest[start + length + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+pub fn encode_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub fn decode_component(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| value.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn resolve(base: &Url, href: &str) -> Option<String> {
+    base.join(href).ok().map(|url| url.to_string())
+}
+
+/// Like `resolve`, but leaves template braces unescaped.
+fn resolve_template(base: &Url, template: &str) -> String {
+    if template.contains("://") {
+        return template.to_string();
+    }
+    let origin = base.origin().ascii_serialization();
+    if template.starts_with('/') {
+        return format!("{}{}", origin, template);
+    }
+    let path = base.path();
+    let directory = &path[..path.rfind('/').map_or(0, |index| index + 1)];
+    format!("{}{}{}", origin, directory, template)
+}
+
+fn set_page_link(pagination: &mut OpdsPagination, rel: &str, href: String) {
+    match rel {
+        "first" | "start" if pagination.first.is_none() => pagination.first = Some(href),
+        "previous" | "prev" => pagination.previous = Some(href),
+        "next" => pagination.next = Some(href),
+        "last" => pagination.last = Some(href),
+        _ => {}
+    }
+}
+
+fn add_facet(groups: &mut Vec<OpdsFacetGroup>, title: String, facet: OpdsFacet) {
+    match groups.iter_mut().find(|group| group.title == title) {
+        Some(group) => group.facets.push(facet),
+        None => groups.push(OpdsFacetGroup {
+            title,
+            facets: vec![facet],
+        }),
+    }
+}
+
+fn is_acquisition(link: &OpdsLink) -> bool {
+    link.rel
+        .as_deref()
+        .is_some_and(|rel| rel.starts_with(ACQUISITION_REL))
+}
+
+/// Links to another feed rather than to a book or an entry's details page.
+fn is_catalog_link(link: &OpdsLink) -> bool {
+    let media_type = link.media_type.as_deref().unwrap_or("");
+    (media_type.starts_with("application/atom+xml") && !media_type.contains("type=entry"))
+        || media_type.starts_with("application/opds+json")
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    children(node, name)
+        .next()
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Looks an attribute up by local name, so `opds:facetGroup` and
+/// `thr:count` match whatever prefix the feed declared.
+fn attribute<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|attr| attr.name() == name)
+        .map(|attr| attr.value())
+}
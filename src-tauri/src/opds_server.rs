@@ -0,0 +1,780 @@
+use crate::config;
+use crate::epub;
+use crate::covers::{self, CoverSize};
+use crate::library;
+use crate::models::{BookEntry, OpdsServerSettings, OpdsServerStatus, Shelves};
+use crate::opds;
+use crate::search;
+use crate::sources;
+use crate::storage;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
+use tauri::{AppHandle, Manager};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const PAGE_SIZE: usize = 50;
+
+const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+const OPENSEARCH_TYPE: &str = "application/opensearchdescription+xml";
+const EPUB_TYPE: &str = "application/epub+zip";
+
+/// The running catalog server, if enabled.
+#[derive(Default)]
+pub struct OpdsServerState {
+    running: Mutex<Option<RunningServer>>,
+    /// Held for a whole restart so the old server has let go of its port
+    /// before another takes it, without blocking `status` meanwhile.
+    restarting: Mutex<()>,
+}
+
+/// The server's accept thread, which hands each request to a thread of its
+/// own so a slow download doesn't hold up the rest of the catalog.
+struct RunningServer {
+    server: Arc<Server>,
+    stopping: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+    address: String,
+}
+
+/// The library as the server last read it, reloaded only when
+/// `library.json` changes. Shared by the request threads.
+#[derive(Default)]
+struct LibraryCache {
+    modified: Option<SystemTime>,
+    books: Arc<Vec<BookEntry>>,
+}
+
+/// A book list with the links needed to page through it.
+struct AcquisitionFeed<'a> {
+    id: String,
+    title: String,
+    path: String,
+    books: Vec<&'a BookEntry>,
+    page: usize,
+}
+
+pub fn load_settings(app: &AppHandle) -> Result<OpdsServerSettings, String> {
+    storage::read_json(&config::get_opds_server_path(app)?)
+}
+
+pub fn save_settings(app: &AppHandle, settings: &OpdsServerSettings) -> Result<(), String> {
+    storage::write_json(&config::get_opds_server_path(app)?, settings)
+}
+
+/// Refuses to publish the library beyond this machine without a password.
+pub fn check_exposure(settings: &OpdsServerSettings) -> Result<(), String> {
+    let loopback = settings.bind_address == "localhost"
+        || settings
+            .bind_address
+            .parse::<IpAddr>()
+            .is_ok_and(|address| address.is_loopback());
+    if settings.enabled && !loopback && settings.credentials.is_none() {
+        return Err(format!(
+            "Set a username and password before sharing the catalog on {}.",
+            settings.bind_address
+        ));
+    }
+    Ok(())
+}
+
+pub fn status(app: &AppHandle) -> Result<OpdsServerStatus, String> {
+    let settings = load_settings(app)?;
+    let state = app.state::<OpdsServerState>();
+    let guard = state
+        .running
+        .lock()
+        .map_err(|_| "OPDS server lock poisoned".to_string())?;
+    Ok(OpdsServerStatus {
+        settings,
+        running: guard.is_some(),
+        address: guard.as_ref().map(|running| running.address.clone()),
+    })
+}
+
+/// Stops the server if it is running and starts it again when the saved
+/// settings have it enabled.
+pub fn restart(app: &AppHandle) -> Result<OpdsServerStatus, String> {
+    let settings = load_settings(app)?;
+    {
+        let state = app.state::<OpdsServerState>();
+        let _restarting = state
+            .restarting
+            .lock()
+            .map_err(|_| "OPDS server lock poisoned".to_string())?;
+        let previous = state
+            .running
+            .lock()
+            .map_err(|_| "OPDS server lock poisoned".to_string())?
+            .take();
+        if let Some(running) = previous {
+            // Only the accept loop is waited for; requests still being
+            // answered finish on their own threads.
+            running.stopping.store(true, Ordering::SeqCst);
+            running.server.unblock();
+            let _ = running.thread.join();
+        }
+        if settings.enabled {
+            let started = start(app, &settings)?;
+            *state
+                .running
+                .lock()
+                .map_err(|_| "OPDS server lock poisoned".to_string())? = Some(started);
+        }
+    }
+    status(app)
+}
+
+fn start(app: &AppHandle, settings: &OpdsServerSettings) -> Result<RunningServer, String> {
+    check_exposure(settings)?;
+    let address = format!("{}:{}", settings.bind_address, settings.port);
+    let server = Server::http(&address)
+        .map(Arc::new)
+        .map_err(|error| format!("Failed to start OPDS server on {}: {error}", address))?;
+    let stopping = Arc::new(AtomicBool::new(false));
+
+    let thread = {
+        let server = server.clone();
+        let stopping = stopping.clone();
+        let app = app.clone();
+        let credentials = settings.credentials.clone();
+        let expected_auth: Option<Arc<str>> = credentials.map(|credentials| {
+            let pair = format!("{}:{}", credentials.username, credentials.password);
+            format!("Basic {}", STANDARD.encode(pair)).into()
+        });
+        thread::spawn(move || {
+            let library = Arc::new(Mutex::new(LibraryCache::default()));
+            loop {
+                match server.recv() {
+                    Ok(request) => {
+                        let app = app.clone();
+                        let library = library.clone();
+                        let expected_auth = expected_auth.clone();
+                        thread::spawn(move || {
+                            handle(&app, &library, request, expected_auth.as_deref())
+                        });
+                    }
+                    Err(_) if stopping.load(Ordering::SeqCst) => break,
+                    Err(error) => println!("DEBUG WARNING: OPDS server error: {}", error),
+                }
+            }
+        })
+    };
+    Ok(RunningServer {
+        server,
+        stopping,
+        thread,
+        address,
+    })
+}
+
+fn handle(
+    app: &AppHandle,
+    library: &Mutex<LibraryCache>,
+    request: Request,
+    expected_auth: Option<&str>,
+) {
+    let response = if !matches!(request.method(), Method::Get | Method::Head) {
+        Err(text_response(405, "Method not allowed"))
+    } else if !is_authorized(&request, expected_auth) {
+        Err(
+            text_response(401, "Authentication required").with_header(header(
+                "WWW-Authenticate",
+                "Basic realm=\"rebook\", charset=\"UTF-8\"",
+            )),
+        )
+    } else {
+        route(app, library, request.url())
+    };
+    let url = request.url().to_string();
+    let result = match response {
+        Ok(Reply::Data(response)) | Err(response) => request.respond(response),
+        Ok(Reply::File(response)) => request.respond(response),
+    };
+    if let Err(error) = result {
+        println!(
+            "DEBUG WARNING: Failed to answer OPDS request {}: {}",
+            url, error
+        );
+    }
+}
+
+enum Reply {
+    Data(Response<Cursor<Vec<u8>>>),
+    File(Response<File>),
+}
+
+fn is_authorized(request: &Request, expected_auth: Option<&str>) -> bool {
+    let Some(expected) = expected_auth else {
+        return true;
+    };
+    request
+        .headers()
+        .iter()
+        .any(|header| header.field.equiv("Authorization") && header.value.as_str() == expected)
+}
+
+type RouteResult = Result<Reply, Response<Cursor<Vec<u8>>>>;
+
+fn route(app: &AppHandle, library: &Mutex<LibraryCache>, url: &str) -> RouteResult {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(opds::decode_component)
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let page = query_param(query, "page")
+        .and_then(|page| page.parse().ok())
+        .unwrap_or(1usize)
+        .max(1);
+
+    let books = load_books(app, library)?;
+    let books: &[BookEntry] = &books;
+    match segments.as_slice() {
+        [] | ["opds"] => Ok(xml_reply(root_feed(), NAVIGATION_TYPE)),
+        ["opds", "opensearch.xml"] => Ok(xml_reply(opensearch_description(), OPENSEARCH_TYPE)),
+        ["opds", "books"] => acquisition(
+            app,
+            AcquisitionFeed {
+                id: "all".to_string(),
+                title: "All books".to_string(),
+                path: "/opds/books".to_string(),
+                books: books.iter().collect(),
+                page,
+            },
+        ),
+        ["opds", "authors"] => {
+            let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+            for book in books {
+                for author in authors(book) {
+                    *counts.entry(author.to_string()).or_default() += 1;
+                }
+            }
+            Ok(navigation("authors", "Authors", "/opds/authors", counts))
+        }
+        ["opds", "authors", name] => acquisition(
+            app,
+            AcquisitionFeed {
+                id: format!("authors:{}", name),
+                title: name.to_string(),
+                path: format!("/opds/authors/{}", opds::encode_component(name)),
+                books: books
+                    .iter()
+                    .filter(|book| authors(book).any(|author| author == *name))
+                    .collect(),
+                page,
+            },
+        ),
+        ["opds", "series"] => {
+            let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+            for series in books.iter().filter_map(|book| book.series.as_ref()) {
+                *counts.entry(series.clone()).or_default() += 1;
+            }
+            Ok(navigation("series", "Series", "/opds/series", counts))
+        }
+        ["opds", "series", name] => {
+            let mut in_series: Vec<&BookEntry> = books
+                .iter()
+                .filter(|book| book.series.as_deref() == Some(*name))
+                .collect();
+            in_series.sort_by(|a, b| {
+                let a = a.series_index.unwrap_or(f64::MAX);
+                a.total_cmp(&b.series_index.unwrap_or(f64::MAX))
+            });
+            acquisition(
+                app,
+                AcquisitionFeed {
+                    id: format!("series:{}", name),
+                    title: name.to_string(),
+                    path: format!("/opds/series/{}", opds::encode_component(name)),
+                    books: in_series,
+                    page,
+                },
+            )
+        }
+        ["opds", "collections"] => {
+            let shelves = load_shelves(app)?;
+            let entries = shelves
+                .collections
+                .iter()
+                .map(|collection| {
+                    nav_entry(
+                        &format!("collections:{}", collection.id),
+                        &collection.name,
+                        &format!(
+                            "/opds/collections/{}",
+                            opds::encode_component(&collection.id)
+                        ),
+                        collection.book_ids.len(),
+                    )
+                })
+                .collect();
+            Ok(xml_reply(
+                feed_xml(
+                    "collections",
+                    "Collections",
+                    "/opds/collections",
+                    "",
+                    entries,
+                ),
+                NAVIGATION_TYPE,
+            ))
+        }
+        ["opds", "collections", id] => {
+            let shelves = load_shelves(app)?;
+            let collection = shelves
+                .collections
+                .iter()
+                .find(|collection| collection.id == *id)
+                .ok_or_else(|| text_response(404, "Collection not found"))?;
+            acquisition(
+                app,
+                AcquisitionFeed {
+                    id: format!("collections:{}", id),
+                    title: collection.name.clone(),
+                    path: format!("/opds/collections/{}", opds::encode_component(id)),
+                    books: collection
+                        .book_ids
+                        .iter()
+                        .filter_map(|book_id| books.iter().find(|book| &book.id == book_id))
+                        .collect(),
+                    page,
+                },
+            )
+        }
+        ["opds", "search"] => {
+            let query = query_param(query, "q").unwrap_or_default();
+            acquisition(
+                app,
+                AcquisitionFeed {
+                    id: format!("search:{}", query),
+                    title: format!("Search: {}", query),
+                    path: format!("/opds/search?q={}", opds::encode_component(&query)),
+                    books: search_books(books, &query),
+                    page,
+                },
+            )
+        }
+        ["opds", "covers", id] => {
+            let book = find_book(books, id)?;
+            let cover = book
+                .cover_base64
+                .as_deref()
+                .and_then(|cover| STANDARD.decode(cover).ok())
+                .ok_or_else(|| text_response(404, "No cover"))?;
+            let mime = book.cover_mime.as_deref().unwrap_or("image/jpeg");
+            Ok(Reply::Data(
+                Response::from_data(cover).with_header(header("Content-Type", mime)),
+            ))
+        }
+        ["opds", "thumbnails", id] => {
+            let (bytes, mime) = covers::thumbnail(app, id, CoverSize::Detail)
+                .map_err(|error| text_response(404, &error))?;
+            Ok(Reply::Data(
+                Response::from_data(bytes).with_header(header("Content-Type", mime)),
+            ))
+        }
+        ["opds", "download", id] => {
+            let book = find_book(books, id)?;
+            let file = source_path(app, book)
+                .and_then(|path| File::open(path).ok())
+                .ok_or_else(|| text_response(404, "The original file is not available"))?;
+            let disposition = format!(
+                "attachment; filename=\"{}.epub\"; filename*=UTF-8''{}.epub",
+                file_name(&book.title),
+                opds::encode_component(&book.title)
+            );
+            Ok(Reply::File(
+                Response::from_file(file)
+                    .with_header(header("Content-Type", EPUB_TYPE))
+                    .with_header(header("Content-Disposition", &disposition)),
+            ))
+        }
+        _ => Err(text_response(404, "Not found")),
+    }
+}
+
+fn root_feed() -> String {
+    let entries = [
+        ("all", "All books", "/opds/books", ACQUISITION_TYPE),
+        ("authors", "Authors", "/opds/authors", NAVIGATION_TYPE),
+        ("series", "Series", "/opds/series", NAVIGATION_TYPE),
+        (
+            "collections",
+            "Collections",
+            "/opds/collections",
+            NAVIGATION_TYPE,
+        ),
+    ]
+    .iter()
+    .map(|(id, title, href, kind)| {
+        format!(
+            "<entry><title>{}</title><id>urn:rebook:{}</id><updated>{}</updated>\
+             <link rel=\"subsection\" href=\"{}\" type=\"{}\"/></entry>",
+            title,
+            id,
+            storage::now_iso(),
+            href,
+            kind
+        )
+    })
+    .collect();
+    feed_xml("root", "rebook", "/opds", "", entries)
+}
+
+fn opensearch_description() -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <OpenSearchDescription xmlns=\"http://a9.com/-/spec/opensearch/1.1/\">\
+         <ShortName>rebook</ShortName><Description>Search the rebook library</Description>\
+         <InputEncoding>UTF-8</InputEncoding><OutputEncoding>UTF-8</OutputEncoding>\
+         <Url type=\"{}\" template=\"/opds/search?q={{searchTerms}}\"/>\
+         </OpenSearchDescription>",
+        ACQUISITION_TYPE
+    )
+}
+
+/// A navigation feed with one entry per name, each listing its books.
+fn navigation(id: &str, title: &str, path: &str, counts: BTreeMap<String, usize>) -> Reply {
+    let entries = counts
+        .iter()
+        .map(|(name, count)| {
+            nav_entry(
+                &format!("{}:{}", id, name),
+                name,
+                &format!("{}/{}", path, opds::encode_component(name)),
+                *count,
+            )
+        })
+        .collect();
+    xml_reply(feed_xml(id, title, path, "", entries), NAVIGATION_TYPE)
+}
+
+fn nav_entry(id: &str, title: &str, href: &str, count: usize) -> String {
+    format!(
+        "<entry><title>{}</title><id>urn:rebook:{}</id><updated>{}</updated>\
+         <content type=\"text\">{} books</content>\
+         <link rel=\"subsection\" href=\"{}\" type=\"{}\" thr:count=\"{}\"/></entry>",
+        epub::escape_xml(title),
+        epub::escape_xml(id),
+        storage::now_iso(),
+        count,
+        epub::escape_xml(href),
+        ACQUISITION_TYPE,
+        count
+    )
+}
+
+fn acquisition(app: &AppHandle, feed: AcquisitionFeed) -> RouteResult {
+    let total = feed.books.len();
+    let last_page = total.div_ceil(PAGE_SIZE).max(1);
+    if feed.page > last_page {
+        return Err(text_response(404, "Page not found"));
+    }
+    let separator = if feed.path.contains('?') { '&' } else { '?' };
+    let page_link = |rel: &str, page: usize| {
+        format!(
+            "<link rel=\"{}\" href=\"{}\" type=\"{}\"/>",
+            rel,
+            epub::escape_xml(&format!("{}{}page={}", feed.path, separator, page)),
+            ACQUISITION_TYPE
+        )
+    };
+    let mut links = format!(
+        "<opensearch:totalResults>{}</opensearch:totalResults>\
+         <opensearch:itemsPerPage>{}</opensearch:itemsPerPage>",
+        total, PAGE_SIZE
+    );
+    if last_page > 1 {
+        links.push_str(&page_link("first", 1));
+        links.push_str(&page_link("last", last_page));
+    }
+    if feed.page > 1 {
+        links.push_str(&page_link("previous", feed.page - 1));
+    }
+    if feed.page < last_page {
+        links.push_str(&page_link("next", feed.page + 1));
+    }
+
+    let entries = feed
+        .books
+        .iter()
+        .skip((feed.page - 1) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|book| book_entry(app, book))
+        .collect();
+    Ok(xml_reply(
+        feed_xml(&feed.id, &feed.title, &feed.path, &links, entries),
+        ACQUISITION_TYPE,
+    ))
+}
+
+fn book_entry(app: &AppHandle, book: &BookEntry) -> String {
+    let id = opds::encode_component(&book.id);
+    let mut entry = format!(
+        "<entry><title>{}</title><id>urn:rebook:book:{}</id><updated>{}</updated>",
+        epub::escape_xml(&book.title),
+        epub::escape_xml(&book.id),
+        epub::escape_xml(&book.imported_at)
+    );
+    for author in authors(book) {
+        entry.push_str(&format!(
+            "<author><name>{}</name></author>",
+            epub::escape_xml(author)
+        ));
+    }
+    if let Some(language) = &book.language {
+        entry.push_str(&format!(
+            "<dc:language>{}</dc:language>",
+            epub::escape_xml(language)
+        ));
+    }
+    if let Some(identifier) = &book.identifier {
+        entry.push_str(&format!(
+            "<dc:identifier>{}</dc:identifier>",
+            epub::escape_xml(identifier)
+        ));
+    }
+    for tag in &book.tags {
+        entry.push_str(&format!(
+            "<category term=\"{0}\" label=\"{0}\"/>",
+            epub::escape_xml(tag)
+        ));
+    }
+    if let Some(description) = &book.description {
+        entry.push_str(&format!(
+            "<summary type=\"text\">{}</summary>",
+            epub::escape_xml(description)
+        ));
+    }
+    if let Some(mime) = &book.cover_mime {
+        // Thumbnails fall back to an SVG placeholder when they can't be made.
+        let thumbnail_type = covers::thumbnail_type(app, &book.id, CoverSize::Detail);
+        entry.push_str(&format!(
+            "<link rel=\"http://opds-spec.org/image\" href=\"/opds/covers/{0}\" type=\"{1}\"/>\
+             <link rel=\"http://opds-spec.org/image/thumbnail\" href=\"/opds/thumbnails/{0}\" type=\"{2}\"/>",
+            id,
+            epub::escape_xml(mime),
+            thumbnail_type
+        ));
+    }
+    if book.content_hash.is_some() {
+        entry.push_str(&format!(
+            "<link rel=\"http://opds-spec.org/acquisition\" href=\"/opds/download/{}\" type=\"{}\"/>",
+            id, EPUB_TYPE
+        ));
+    }
+    entry.push_str("</entry>");
+    entry
+}
+
+fn feed_xml(id: &str, title: &str, path: &str, links: &str, entries: Vec<String>) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/terms/\" \
+         xmlns:opds=\"http://opds-spec.org/2010/catalog\" \
+         xmlns:opensearch=\"http://a9.com/-/spec/opensearch/1.1/\" \
+         xmlns:thr=\"http://purl.org/syndication/thread/1.0\">\
+         <id>urn:rebook:{}</id><title>{}</title><updated>{}</updated>\
+         <author><name>rebook</name></author>\
+         <link rel=\"self\" href=\"{}\"/>\
+         <link rel=\"start\" href=\"/opds\" type=\"{}\"/>\
+         <link rel=\"search\" href=\"/opds/opensearch.xml\" type=\"{}\"/>\
+         {}{}</feed>",
+        epub::escape_xml(id),
+        epub::escape_xml(title),
+        storage::now_iso(),
+        epub::escape_xml(path),
+        NAVIGATION_TYPE,
+        OPENSEARCH_TYPE,
+        links,
+        entries.concat()
+    )
+}
+
+/// Matches books whose title, authors, series or tags contain every word of
+/// `query`, each as a word prefix.
+fn search_books<'a>(books: &'a [BookEntry], query: &str) -> Vec<&'a BookEntry> {
+    let terms: Vec<String> = search::tokenize(query)
+        .into_iter()
+        .map(|token| token.term)
+        .collect();
+    if terms.is_empty() {
+        return Vec::new();
+    }
+    books
+        .iter()
+        .filter(|book| {
+            let text = [
+                Some(book.title.as_str()),
+                book.author.as_deref(),
+                book.series.as_deref(),
+            ]
+            .into_iter()
+            .flatten()
+            .chain(book.tags.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+            let words = search::tokenize(&text);
+            terms.iter().all(|term| {
+                words
+                    .iter()
+                    .any(|word| word.term.starts_with(term.as_str()))
+            })
+        })
+        .collect()
+}
+
+/// Calibre imports join several authors with " & ".
+fn authors(book: &BookEntry) -> impl Iterator<Item = &str> {
+    book.author
+        .as_deref()
+        .into_iter()
+        .flat_map(|author| author.split(" & "))
+        .map(str::trim)
+        .filter(|author| !author.is_empty())
+}
+
+fn load_books(
+    app: &AppHandle,
+    cache: &Mutex<LibraryCache>,
+) -> Result<Arc<Vec<BookEntry>>, Response<Cursor<Vec<u8>>>> {
+    let path = config::get_library_path(app).map_err(server_error)?;
+    let modified = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let mut cache = cache
+        .lock()
+        .map_err(|_| server_error("Library cache lock poisoned".to_string()))?;
+    if modified.is_none() || modified != cache.modified {
+        cache.books = Arc::new(library::load(&path).map_err(server_error)?.books);
+        cache.modified = modified;
+    }
+    Ok(cache.books.clone())
+}
+
+fn load_shelves(app: &AppHandle) -> Result<Shelves, Response<Cursor<Vec<u8>>>> {
+    config::get_shelves_path(app)
+        .and_then(|path| storage::read_json(&path))
+        .map_err(server_error)
+}
+
+fn find_book<'a>(
+    books: &'a [BookEntry],
+    id: &str,
+) -> Result<&'a BookEntry, Response<Cursor<Vec<u8>>>> {
+    books
+        .iter()
+        .find(|book| book.id == id)
+        .ok_or_else(|| text_response(404, "Book not found"))
+}
+
+fn source_path(app: &AppHandle, book: &BookEntry) -> Option<std::path::PathBuf> {
+    let hash = book.content_hash.as_deref()?;
+    let path = sources::path_for(&config::get_sources_dir(app).ok()?, hash);
+    path.exists().then_some(path)
+}
+
+fn server_error(error: String) -> Response<Cursor<Vec<u8>>> {
+    println!(
+        "DEBUG WARNING: OPDS server failed to read library: {}",
+        error
+    );
+    text_response(500, "Failed to read the library")
+}
+
+fn xml_reply(xml: String, content_type: &str) -> Reply {
+    Reply::Data(Response::from_data(xml.into_bytes()).with_header(header(
+        "Content-Type",
+        &format!("{};charset=utf-8", content_type),
+    )))
+}
+
+fn text_response(status: u16, message: &str) -> Response<Cursor<Vec<u8>>> {
+    Response::from_data(message.as_bytes().to_vec())
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
+
+/// Header values must be printable ASCII; anything else is dropped.
+fn header(name: &str, value: &str) -> Header {
+    let value: String = value
+        .chars()
+        .filter(|c| c.is_ascii() && !c.is_ascii_control())
+        .collect();
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("ASCII header")
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| opds::decode_component(&value.replace('+', " ")))
+}
+
+/// An ASCII download name that is valid on every file system, for readers
+/// that ignore the UTF-8 `filename*`.
+fn file_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if !c.is_ascii() || c.is_ascii_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim();
+    if name.is_empty() {
+        "book".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::OpdsCredentials;
+
+    fn settings(bind_address: &str, credentials: bool) -> OpdsServerSettings {
+        OpdsServerSettings {
+            enabled: true,
+            bind_address: bind_address.to_string(),
+            credentials: credentials.then(|| OpdsCredentials {
+                username: "reader".to_string(),
+                password: "secret".to_string(),
+            }),
+            ..OpdsServerSettings::default()
+        }
+    }
+
+    #[test]
+    fn the_default_listens_on_this_machine_only() {
+        let settings = OpdsServerSettings {
+            enabled: true,
+            ..OpdsServerSettings::default()
+        };
+        assert!(check_exposure(&settings).is_ok());
+    }
+
+    #[test]
+    fn other_addresses_need_credentials() {
+        for address in ["127.0.0.1", "::1", "localhost"] {
+            assert!(check_exposure(&settings(address, false)).is_ok());
+        }
+        for address in ["0.0.0.0", "192.168.1.20", "::"] {
+            assert!(check_exposure(&settings(address, false)).is_err());
+            assert!(check_exposure(&settings(address, true)).is_ok());
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use crate::epub;
+use crate::models::{
+    BookEntry, Chapter, ChapterChange, ChapterChangeStatus, Locator, PositionInput,
+    PositionRecord, ResolvedPosition, TextQuote,
+};
+use crate::storage;
+use roxmltree::Node;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use tauri::{AppHandle, Manager};
+
+const HISTORY_LIMIT: usize = 20;
+const QUOTE_CONTEXT: usize = 32;
+/// Saves closer than this many characters to the newest history entry update
+/// it instead of adding a new one, so continuous playback does not flood the
+/// history with every sentence.
+const HISTORY_MERGE_DISTANCE: usize = 600;
+/// Shorter needle tried when a full sentence no longer matches verbatim.
+const FALLBACK_NEEDLE_CHARS: usize = 40;
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookPositions {
+    pub current: Option<PositionRecord>,
+    #[serde(default)]
+    pub history: Vec<PositionRecord>,
+}
+
+pub type PositionStore = HashMap<String, BookPositions>;
+
+/// Kept in Tauri state so that saves, re-imports and backup restores do not
+/// overwrite each other's changes to `positions.json`. Hold the guard from
+/// `lock` from reading the store until it is written.
+#[derive(Default)]
+pub struct PositionState(Mutex<()>);
+
+pub fn lock(app: &AppHandle) -> Result<MutexGuard<'_, ()>, String> {
+    app.state::<PositionState>()
+        .inner()
+        .0
+        .lock()
+        .map_err(|_| "Reading position lock poisoned".to_string())
+}
+
+/// Builds a locator from what the reader knows about its position: a chapter
+/// and offset when available, otherwise the sentence text plus an overall
+/// progress hint used to pick between repeated passages.
+pub fn locate(book: &BookEntry, input: &PositionInput) -> Result<Locator, String> {
+    let chapter_hint = input
+        .chapter_id
+        .as_ref()
+        .and_then(|id| book.chapters.iter().position(|chapter| &chapter.id == id));
+
+    let found = match (&input.sentence_text, chapter_hint) {
+        (Some(sentence), Some(index)) => {
+            let hint = input.char_offset.unwrap_or(0);
+            find_in_chapter(&book.chapters[index], sentence, hint)
+                .map(|offset| (index, offset, sentence.clone()))
+        }
+        (Some(sentence), None) => find_in_book(book, sentence, input.progress)
+            .map(|(index, offset)| (index, offset, sentence.clone())),
+        (None, _) => None,
+    };
+
+    let (chapter_index, offset, exact) = match (found, chapter_hint) {
+        (Some(found), _) => found,
+        (None, Some(index)) => {
+            let chapter = &book.chapters[index];
+            let offset = input
+                .char_offset
+                .unwrap_or(0)
+                .min(chapter.text.chars().count());
+            (index, offset, String::new())
+        }
+        (None, None) => {
+            let (index, offset) = offset_for_progress(book, input.progress.unwrap_or(0.0))
+                .ok_or_else(|| "Book has no chapters".to_string())?;
+            (index, offset, String::new())
+        }
+    };
+
+    Ok(build_locator_in(
+        book,
+        chapter_index,
+        offset,
+        &exact,
+        input.element_path.clone(),
+    ))
+}
+
+/// Re-anchors `locator` against the current text of `book`. The stored offset
+/// is trusted when the quoted text is still there; otherwise the quote is
+/// searched for near the old offset, then across the book.
+pub fn resolve(book: &BookEntry, locator: &Locator) -> Option<ResolvedPosition> {
+    let chapter_index = book
+        .chapters
+        .iter()
+        .position(|chapter| chapter.id == locator.chapter_id);
+    let exact = locator.quote.as_ref().map(|quote| quote.exact.as_str()).unwrap_or("");
+
+    if let Some(index) = chapter_index {
+        let chapter = &book.chapters[index];
+        if exact.is_empty() || quote_matches_at(chapter, locator.char_offset, exact) {
+            let offset = locator.char_offset.min(chapter.text.chars().count());
+            return Some(resolved(book, index, offset, exact, locator, true));
+        }
+        if let Some(offset) = find_in_chapter(chapter, exact, locator.char_offset) {
+            return Some(resolved(book, index, offset, exact, locator, false));
+        }
+    }
+
+    if !exact.is_empty() {
+        let hint = Some(locator.progress);
+        if let Some((index, offset)) = find_in_book(book, exact, hint) {
+            return Some(resolved(book, index, offset, exact, locator, false));
+        }
+    }
+
+    let (index, offset) = match chapter_index {
+        Some(index) => (
+            index,
+            locator
+                .char_offset
+                .min(book.chapters[index].text.chars().count()),
+        ),
+        None => offset_for_progress(book, locator.progress)?,
+    };
+    Some(resolved(book, index, offset, "", locator, false))
+}
+
+pub fn record(store: &mut PositionStore, book_id: &str, locator: Locator) -> PositionRecord {
+    let entry = store.entry(book_id.to_string()).or_default();
+    let record = PositionRecord {
+        locator,
+        saved_at: storage::now_iso(),
+    };
+    let merge = entry.history.first().is_some_and(|latest| {
+        latest.locator.chapter_id == record.locator.chapter_id
+            && latest.locator.char_offset.abs_diff(record.locator.char_offset)
+                < HISTORY_MERGE_DISTANCE
+    });
+    if merge {
+        entry.history[0] = record.clone();
+    } else {
+        entry.history.insert(0, record.clone());
+        entry.history.truncate(HISTORY_LIMIT);
+    }
+    entry.current = Some(record.clone());
+    record
+}
+
+/// Folds positions saved elsewhere into `store`: the most recently saved
+/// current position wins and both histories are interleaved by save time.
+pub fn merge(store: &mut PositionStore, book_id: &str, incoming: BookPositions) {
+    let entry = store.entry(book_id.to_string()).or_default();
+    let incoming_is_newer = match (&entry.current, &incoming.current) {
+        (Some(existing), Some(other)) => other.saved_at > existing.saved_at,
+        (None, Some(_)) => true,
+        _ => false,
+    };
+    if incoming_is_newer {
+        entry.current = incoming.current;
+    }
+    entry.history.extend(incoming.history);
+    entry.history.sort_by(|a, b| b.saved_at.cmp(&a.saved_at));
+    entry.history.dedup_by(|a, b| {
+        a.saved_at == b.saved_at
+            && a.locator.chapter_id == b.locator.chapter_id
+            && a.locator.char_offset == b.locator.char_offset
+    });
+    entry.history.truncate(HISTORY_LIMIT);
+}
+
+/// Moves stored positions onto a re-imported edition's chapter ids.
+pub fn remap(store: &mut PositionStore, book: &BookEntry, changes: &[ChapterChange]) {
+    let Some(entry) = store.get_mut(&book.id) else {
+        return;
+    };
+    for record in entry.current.iter_mut().chain(entry.history.iter_mut()) {
+        remap_locator(&mut record.locator, book, changes);
+    }
+}
+
+/// Points `locator` at the matching chapter of a re-imported edition.
+/// Locators in unchanged chapters only get the new id; locators in modified
+/// or removed chapters are re-anchored by their text quote.
+pub fn remap_locator(locator: &mut Locator, book: &BookEntry, changes: &[ChapterChange]) {
+    let Some(change) = changes
+        .iter()
+        .find(|change| change.old_id.as_ref() == Some(&locator.chapter_id))
+    else {
+        return;
+    };
+    match (change.status, &change.new_id) {
+        (ChapterChangeStatus::Unchanged, Some(new_id)) => {
+            locator.chapter_id = new_id.clone();
+            if let Some(path) = &locator.element_path {
+                locator.cfi = spine_step(new_id).map(|spine| cfi(spine, path));
+            }
+        }
+        (_, new_id) => {
+            let mut stale = locator.clone();
+            stale.chapter_id = new_id.clone().unwrap_or_default();
+            if let Some(position) = resolve(book, &stale) {
+                *locator = position.locator;
+            }
+        }
+    }
+}
+
+fn resolved(
+    book: &BookEntry,
+    chapter_index: usize,
+    offset: usize,
+    exact: &str,
+    previous: &Locator,
+    exact_match: bool,
+) -> ResolvedPosition {
+    let chapter = &book.chapters[chapter_index];
+    let element_path = if exact_match {
+        previous.element_path.clone()
+    } else {
+        None
+    };
+    ResolvedPosition {
+        locator: build_locator_in(book, chapter_index, offset, exact, element_path),
+        exact: exact_match,
+        chapter_title: chapter.title.clone(),
+    }
+}
+
+fn build_locator(
+    chapter: &Chapter,
+    offset: usize,
+    exact: &str,
+    element_path: Option<String>,
+) -> Locator {
+    let chars: Vec<char> = chapter.text.chars().collect();
+    let offset = offset.min(chars.len());
+    let exact = if exact.is_empty() {
+        // Without a sentence, quote the rest of the line so the position can
+        // still be re-anchored after the text shifts.
+        chars[offset..]
+            .iter()
+            .take_while(|c| **c != '\n')
+            .take(FALLBACK_NEEDLE_CHARS * 2)
+            .collect::<String>()
+    } else {
+        exact.to_string()
+    };
+    let prefix: String = chars[offset.saturating_sub(QUOTE_CONTEXT)..offset]
+        .iter()
+        .collect();
+    let exact_len = exact.chars().count();
+    let suffix_start = (offset + exact_len).min(chars.len());
+    let suffix: String = chars[suffix_start..]
+        .iter()
+        .take(QUOTE_CONTEXT)
+        .collect();
+
+    let element_path = element_path.or_else(|| {
+        chapter
+            .html
+            .as_deref()
+            .and_then(|html| element_path_for(html, &exact))
+    });
+    let cfi = element_path
+        .as_ref()
+        .and_then(|path| spine_step(&chapter.id).map(|spine| cfi(spine, path)));
+
+    Locator {
+        chapter_id: chapter.id.clone(),
+        element_path,
+        char_offset: offset,
+        cfi,
+        quote: Some(TextQuote {
+            prefix,
+            exact,
+            suffix,
+        }),
+        progress: 0.0,
+    }
+}
+
+fn build_locator_in(
+    book: &BookEntry,
+    chapter_index: usize,
+    offset: usize,
+    exact: &str,
+    element_path: Option<String>,
+) -> Locator {
+    let mut locator = build_locator(&book.chapters[chapter_index], offset, exact, element_path);
+    locator.progress = progress_of(book, chapter_index, offset);
+    locator
+}
+
+/// Overall progress through the book as a fraction of its characters.
+pub fn progress_of(book: &BookEntry, chapter_index: usize, offset: usize) -> f64 {
+    let lengths: Vec<usize> = book
+        .chapters
+        .iter()
+        .map(|chapter| chapter.text.chars().count())
+        .collect();
+    let total: usize = lengths.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let before: usize = lengths[..chapter_index].iter().sum();
+    ((before + offset) as f64 / total as f64).clamp(0.0, 1.0)
+}
+
+fn offset_for_progress(book: &BookEntry, progress: f64) -> Option<(usize, usize)> {
+    let lengths: Vec<usize> = book
+        .chapters
+        .iter()
+        .map(|chapter| chapter.text.chars().count())
+        .collect();
+    let total: usize = lengths.iter().sum();
+    let mut target = (progress.clamp(0.0, 1.0) * total as f64) as usize;
+    for (index, length) in lengths.iter().enumerate() {
+        if target <= *length {
+            return Some((index, target));
+        }
+        target -= length;
+    }
+    lengths.len().checked_sub(1).map(|last| (last, lengths[last]))
+}
+
+fn quote_matches_at(chapter: &Chapter, offset: usize, exact: &str) -> bool {
+    let window: String = chapter
+        .text
+        .chars()
+        .skip(offset)
+        .take(exact.chars().count() * 2 + 16)
+        .collect();
+    let window = NormalizedText::new(&window);
+    let needle = NormalizedText::new(exact);
+    !needle.chars.is_empty() && window.chars.starts_with(&needle.chars)
+}
+
+/// Finds `sentence` in the chapter, preferring the occurrence closest to
+/// `near` (a character offset). Falls back to the sentence's opening words
+/// when markup rendering changed the rest of it.
+fn find_in_chapter(chapter: &Chapter, sentence: &str, near: usize) -> Option<usize> {
+    let haystack = NormalizedText::new(&chapter.text);
+    let candidates = [
+        NormalizedText::new(sentence),
+        NormalizedText::new(&sentence.chars().take(FALLBACK_NEEDLE_CHARS).collect::<String>()),
+    ];
+    candidates
+        .iter()
+        .filter(|needle| !needle.chars.is_empty())
+        .find_map(|needle| {
+            haystack
+                .find_all(&needle.chars)
+                .into_iter()
+                .min_by_key(|offset| offset.abs_diff(near))
+        })
+}
+
+fn find_in_book(book: &BookEntry, sentence: &str, progress: Option<f64>) -> Option<(usize, usize)> {
+    let target = progress.unwrap_or(0.0);
+    book.chapters
+        .iter()
+        .enumerate()
+        .filter_map(|(index, chapter)| {
+            let near = offset_for_progress(book, target)
+                .filter(|(hint_index, _)| *hint_index == index)
+                .map_or(0, |(_, offset)| offset);
+            find_in_chapter(chapter, sentence, near).map(|offset| (index, offset))
+        })
+        .min_by(|a, b| {
+            let distance = |(index, offset): &(usize, usize)| {
+                (progress_of(book, *index, *offset) - target).abs()
+            };
+            distance(a).total_cmp(&distance(b))
+        })
+}
+
+/// Character offset of the first occurrence of `needle` in `text` at or after
+/// `from`, ignoring case and differences in whitespace.
+pub fn find_text_offset(text: &str, needle: &str, from: usize) -> Option<usize> {
+    let haystack = NormalizedText::new(text);
+    let needle = NormalizedText::new(needle);
+    haystack
+        .find_all(&needle.chars)
+        .into_iter()
+        .find(|offset| *offset >= from)
+}
+
+/// Lowercased text with whitespace runs collapsed, keeping a map back to
+/// character offsets in the original string.
+struct NormalizedText {
+    chars: Vec<char>,
+    offsets: Vec<usize>,
+}
+
+impl NormalizedText {
+    fn new(text: &str) -> NormalizedText {
+        let mut chars = Vec::new();
+        let mut offsets = Vec::new();
+        let mut pending_space = false;
+        for (offset, c) in text.chars().enumerate() {
+            if c.is_whitespace() {
+                pending_space = !chars.is_empty();
+                continue;
+            }
+            if pending_space {
+                chars.push(' ');
+                offsets.push(offset);
+                pending_space = false;
+            }
+            for lower in c.to_lowercase() {
+                chars.push(lower);
+                offsets.push(offset);
+            }
+        }
+        NormalizedText { chars, offsets }
+    }
+
+    fn find_all(&self, needle: &[char]) -> Vec<usize> {
+        if needle.is_empty() || needle.len() > self.chars.len() {
+            return Vec::new();
+        }
+        self.chars
+            .windows(needle.len())
+            .enumerate()
+            .filter(|(_, window)| *window == needle)
+            .map(|(index, _)| self.offsets[index])
+            .collect()
+    }
+}
+
+/// CFI step path (even indices count element children) to the innermost
+/// element of the chapter document whose text contains `exact`.
+fn element_path_for(html: &str, exact: &str) -> Option<String> {
+    let needle = NormalizedText::new(exact);
+    if needle.chars.is_empty() {
+        return None;
+    }
+    let document = epub::parse_xhtml(html).ok()?;
+    let mut path = Vec::new();
+    let mut node = document.root_element();
+    if !contains_text(node, &needle.chars) {
+        return None;
+    }
+    loop {
+        let next = node
+            .children()
+            .filter(|child| child.is_element())
+            .enumerate()
+            .find(|(_, child)| contains_text(*child, &needle.chars));
+        match next {
+            Some((index, child)) => {
+                let step = (index + 1) * 2;
+                match child.attribute("id") {
+                    Some(id) => path.push(format!("/{step}[{id}]")),
+                    None => path.push(format!("/{step}")),
+                }
+                node = child;
+            }
+            None => break,
+        }
+    }
+    (!path.is_empty()).then(|| path.concat())
+}
+
+fn contains_text(node: Node, needle: &[char]) -> bool {
+    let text: String = node
+        .descendants()
+        .filter(|child| child.is_text())
+        .filter_map(|child| child.text())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let haystack = NormalizedText::new(&text);
+    haystack.chars.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Chapter ids are `chapter-<spine position>`; the spine is child /6 of the
+/// package document and each itemref is an even step within it.
+fn spine_step(chapter_id: &str) -> Option<usize> {
+    chapter_id
+        .strip_prefix("chapter-")
+        .and_then(|number| number.parse::<usize>().ok())
+        .map(|number| number * 2)
+}
+
+fn cfi(spine_step: usize, element_path: &str) -> String {
+    format!("epubcfi(/6/{spine_step}!{element_path})")
+}
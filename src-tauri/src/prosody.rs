@@ -0,0 +1,505 @@
+use crate::epub;
+use crate::lexicon::{self, Rendering};
+use crate::models::Chapter;
+use crate::tts::{SpeechInput, SpeechPart};
+use regex::Regex;
+use roxmltree::Node;
+use std::sync::LazyLock;
+
+/// Pause after a heading, before the text under it.
+const HEADING_PAUSE_MS: u32 = 1200;
+/// Pause for `<hr>` and typographic breaks like `* * *`.
+const SCENE_BREAK_PAUSE_MS: u32 = 2000;
+const PARAGRAPH_PAUSE_MS: u32 = 600;
+const LIST_ITEM_PAUSE_MS: u32 = 350;
+/// ElevenLabs ignores breaks longer than this.
+const MAX_BREAK_TAG_MS: u32 = 3000;
+
+/// Elements whose contents are read as a block of their own.
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "blockquote",
+    "pre",
+    "hr",
+    "section",
+    "article",
+    "aside",
+    "header",
+    "footer",
+    "figure",
+    "figcaption",
+    "table",
+    "tr",
+];
+
+/// An ordered-list number html2text left at the end of the preceding block.
+static LIST_NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s\d+\.$").unwrap());
+/// A link as html2text writes it, `[text][1]`, or its footnote marker.
+static LINK_REFERENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]*)\]\[\d+\]").unwrap());
+
+/// How a provider marks pauses and emphasis in the text it is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    /// No markup; blocks are separated by line breaks.
+    Plain,
+    /// MiniMax's `<#seconds#>` pause markers.
+    PauseMarkers,
+    /// Inline `<break time="…"/>` tags in otherwise plain text.
+    BreakTags,
+    /// A complete `<speak>` document with breaks and emphasis.
+    Ssml,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    Heading,
+    Paragraph,
+    ListItem,
+    Quote,
+    SceneBreak,
+}
+
+impl BlockKind {
+    fn pause_after(self) -> u32 {
+        match self {
+            BlockKind::Heading => HEADING_PAUSE_MS,
+            BlockKind::SceneBreak => SCENE_BREAK_PAUSE_MS,
+            BlockKind::ListItem => LIST_ITEM_PAUSE_MS,
+            BlockKind::Paragraph | BlockKind::Quote => PARAGRAPH_PAUSE_MS,
+        }
+    }
+}
+
+/// A block of the chapter's HTML: its kind, its text as runs that are or
+/// aren't emphasized, and once located, where its words are in the chapter
+/// text.
+struct Block {
+    kind: BlockKind,
+    runs: Vec<(String, bool)>,
+    words: Option<(usize, usize)>,
+}
+
+/// The speech for characters `start..end` of a chapter's text, split at the
+/// blocks of its HTML with pauses between them. Chapters without usable HTML
+/// are read as one run of text.
+pub fn parts(chapter: &Chapter, start: usize, end: usize) -> Vec<SpeechPart> {
+    let chars: Vec<char> = chapter.text.chars().collect();
+    let end = end.min(chars.len());
+    let start = start.min(end);
+    let mut blocks = chapter.html.as_deref().map(blocks).unwrap_or_default();
+    let words = words(&chars);
+    locate(&mut blocks, &chars, &words);
+
+    let located: Vec<usize> = (0..blocks.len())
+        .filter(|&index| blocks[index].words.is_some())
+        .collect();
+    if located.is_empty() {
+        return text_part(&chars[start..end]).into_iter().collect();
+    }
+
+    let mut parts = Vec::new();
+    // Text before the first block, such as a title html2text added.
+    let first_start = block_start(&blocks[located[0]], &chars, &words);
+    if start < first_start {
+        parts.extend(text_part(&chars[start..first_start.min(end)]));
+    }
+    for (position, &index) in located.iter().enumerate() {
+        let block = &blocks[index];
+        let block_start = block_start(block, &chars, &words);
+        let next = located.get(position + 1).copied();
+        // A block runs to the next one so text that couldn't be located
+        // is still read; the last ends after its last word.
+        let block_end = match next {
+            Some(next) => block_start_trimmed(&blocks[next], &chars, &words),
+            None => word_run_end(&chars, words[block.words.unwrap().1 - 1].1),
+        };
+        if block_end <= start || block_start >= end {
+            continue;
+        }
+        let from = block_start.max(start);
+        let to = block_end.min(end);
+        parts.extend(block_parts(block, from, to, &chars, &words));
+        if block_end <= end && to > from {
+            let following = next.unwrap_or(blocks.len());
+            let pause = blocks[index + 1..following]
+                .iter()
+                .map(|block| block.kind.pause_after())
+                .chain([block.kind.pause_after()])
+                .max()
+                .unwrap_or(PARAGRAPH_PAUSE_MS);
+            if block.kind == BlockKind::Heading || block.kind == BlockKind::ListItem {
+                end_sentence(&mut parts);
+            }
+            parts.push(SpeechPart::Pause(pause));
+        }
+    }
+    parts
+}
+
+/// Writes `input` the way a provider expects. `pronunciations` says how to
+/// apply lexicon entries to the text, if the provider takes them inline.
+pub fn render(input: &SpeechInput, markup: Markup, pronunciations: Option<Rendering>) -> String {
+    let pronunciations = match (markup, pronunciations) {
+        (Markup::Ssml, Some(_)) => Some(Rendering::Ssml),
+        (_, rendering) => rendering,
+    };
+    let mut out = String::new();
+    let mut previous_text: Option<&str> = None;
+    for part in &input.parts {
+        match part {
+            SpeechPart::Text { text, emphasis } => {
+                if let Some(previous) = previous_text {
+                    if needs_space(previous, text) {
+                        out.push(' ');
+                    }
+                }
+                let spoken = match pronunciations {
+                    Some(rendering) => lexicon::apply(text, &input.pronunciations, rendering),
+                    None if markup == Markup::Ssml => epub::escape_xml(text),
+                    None => text.clone(),
+                };
+                if *emphasis && markup == Markup::Ssml {
+                    out.push_str(&format!(
+                        "<emphasis level=\"moderate\">{}</emphasis>",
+                        spoken
+                    ));
+                } else {
+                    out.push_str(&spoken);
+                }
+                previous_text = Some(text);
+            }
+            SpeechPart::Pause(millis) => {
+                match markup {
+                    Markup::Plain => out.push('\n'),
+                    Markup::PauseMarkers => {
+                        out.push_str(&format!("<#{:.2}#>", *millis as f64 / 1000.0))
+                    }
+                    Markup::BreakTags => out.push_str(&format!(
+                        " <break time=\"{}s\" /> ",
+                        (*millis).min(MAX_BREAK_TAG_MS) as f64 / 1000.0
+                    )),
+                    Markup::Ssml => out.push_str(&format!("<break time=\"{}ms\"/>", millis)),
+                }
+                previous_text = None;
+            }
+        }
+    }
+    let out = out.trim();
+    match markup {
+        Markup::Ssml => format!("<speak>{}</speak>", out),
+        _ => out.to_string(),
+    }
+}
+
+/// Whether a space goes between two runs of text, which lose the spaces
+/// around them when they are trimmed and normalized.
+fn needs_space(previous: &str, next: &str) -> bool {
+    !next.starts_with([',', '.', ';', ':', '!', '?', ')', ']', '}', '”', '’', '…'])
+        && !previous.ends_with(['(', '[', '{', '“', '‘'])
+}
+
+/// Ends a heading or list item with a period so plain-text voices drop
+/// their pitch at the end of it.
+fn end_sentence(parts: &mut [SpeechPart]) {
+    if let Some(SpeechPart::Text { text, .. }) = parts.last_mut() {
+        if text.ends_with(char::is_alphanumeric) {
+            text.push('.');
+        }
+    }
+}
+
+fn blocks(html: &str) -> Vec<Block> {
+    let Ok(document) = epub::parse_xhtml(html) else {
+        return Vec::new();
+    };
+    let root = document
+        .descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == "body")
+        .unwrap_or_else(|| document.root_element());
+    let mut blocks = Vec::new();
+    collect_blocks(root, false, &mut blocks);
+    blocks
+}
+
+fn collect_blocks(node: Node, in_quote: bool, blocks: &mut Vec<Block>) {
+    for child in node.children().filter(|child| child.is_element()) {
+        let name = child.tag_name().name();
+        match name {
+            "script" | "style" | "head" => {}
+            "hr" => blocks.push(Block {
+                kind: BlockKind::SceneBreak,
+                runs: Vec::new(),
+                words: None,
+            }),
+            "blockquote" => collect_blocks(child, true, blocks),
+            "ul" | "ol" | "dl" => collect_blocks(child, in_quote, blocks),
+            _ if !BLOCK_TAGS.contains(&name) => {}
+            _ if has_block_child(child) && kind_of(name, in_quote) != BlockKind::Heading => {
+                // Text beside nested blocks, like an item above a sub-list,
+                // is read as a block of its own.
+                push_block(child, kind_of(name, in_quote), blocks);
+                collect_blocks(child, in_quote, blocks);
+            }
+            _ => push_block(child, kind_of(name, in_quote), blocks),
+        }
+    }
+}
+
+fn kind_of(name: &str, in_quote: bool) -> BlockKind {
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => BlockKind::Heading,
+        "li" | "dt" | "dd" => BlockKind::ListItem,
+        _ if in_quote => BlockKind::Quote,
+        _ => BlockKind::Paragraph,
+    }
+}
+
+fn has_block_child(node: Node) -> bool {
+    node.children()
+        .any(|child| child.is_element() && BLOCK_TAGS.contains(&child.tag_name().name()))
+}
+
+fn is_spoken_text(node: Node) -> bool {
+    node.is_text() && node.text().is_some_and(|text| !text.trim().is_empty())
+}
+
+/// Adds `node`'s own text as a block, leaving out nested blocks. A block of
+/// only symbols, like `* * *` or `⁂`, is a scene break.
+fn push_block(node: Node, kind: BlockKind, blocks: &mut Vec<Block>) {
+    let mut runs: Vec<(String, bool)> = Vec::new();
+    collect_runs(node, false, &mut runs);
+    let text: String = runs.iter().map(|(text, _)| text.as_str()).collect();
+    if text.trim().is_empty() {
+        return;
+    }
+    let kind = if text.chars().any(char::is_alphanumeric) {
+        kind
+    } else {
+        BlockKind::SceneBreak
+    };
+    blocks.push(Block {
+        kind,
+        runs,
+        words: None,
+    });
+}
+
+fn collect_runs(node: Node, emphasis: bool, runs: &mut Vec<(String, bool)>) {
+    for child in node.children() {
+        if is_spoken_text(child) || (child.is_text() && !runs.is_empty()) {
+            let text = child.text().unwrap_or_default();
+            match runs.last_mut() {
+                Some((last, last_emphasis)) if *last_emphasis == emphasis => last.push_str(text),
+                _ => runs.push((text.to_string(), emphasis)),
+            }
+        } else if child.is_element() {
+            let name = child.tag_name().name();
+            if BLOCK_TAGS.contains(&name) || name == "script" || name == "style" {
+                continue;
+            }
+            let emphasized = emphasis || matches!(name, "em" | "strong");
+            collect_runs(child, emphasized, runs);
+        }
+    }
+}
+
+/// Character ranges of the words in the chapter text.
+fn words(chars: &[char]) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, c) in chars.iter().enumerate() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(word_start)) => {
+                words.push((word_start, index));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push((word_start, chars.len()));
+    }
+    words
+}
+
+fn block_words(block: &Block) -> Vec<String> {
+    let text: String = block.runs.iter().map(|(text, _)| text.as_str()).collect();
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Finds each block's words in the chapter text, in order. Blocks are
+/// matched on their first few words, since html2text adds markup such as
+/// `*` around emphasis that isn't in the HTML.
+fn locate(blocks: &mut [Block], chars: &[char], words: &[(usize, usize)]) {
+    let word_at = |index: usize| -> String {
+        let (start, end) = words[index];
+        chars[start..end].iter().collect()
+    };
+    let mut cursor = 0;
+    for block in blocks.iter_mut() {
+        let expected = block_words(block);
+        if expected.is_empty() {
+            continue;
+        }
+        let probe = expected.len().min(4);
+        let found = (cursor..words.len().saturating_sub(probe - 1)).find(|&position| {
+            (0..probe).all(|offset| word_at(position + offset) == expected[offset])
+        });
+        if let Some(position) = found {
+            let end = (position + expected.len()).min(words.len());
+            block.words = Some((position, end));
+            cursor = end;
+        }
+    }
+}
+
+/// Where a located block starts: at its first word, or the punctuation
+/// directly before it such as an opening quote.
+fn block_start(block: &Block, chars: &[char], words: &[(usize, usize)]) -> usize {
+    let mut start = words[block.words.unwrap().0].0;
+    while start > 0 && !chars[start - 1].is_whitespace() {
+        start -= 1;
+    }
+    while start < chars.len() && matches!(chars[start], '*' | '_' | '#' | '>') {
+        start += 1;
+    }
+    start
+}
+
+/// Where the block before `block` ends: at `block`'s start, less the list
+/// bullets and numbers html2text put in between.
+fn block_start_trimmed(block: &Block, chars: &[char], words: &[(usize, usize)]) -> usize {
+    let mut end = block_start(block, chars, words);
+    loop {
+        while end > 0
+            && (chars[end - 1].is_whitespace() || matches!(chars[end - 1], '*' | '-' | '#' | '>'))
+        {
+            end -= 1;
+        }
+        let before: String = chars[end.saturating_sub(8)..end].iter().collect();
+        match LIST_NUMBER.find(&before) {
+            Some(number) => end -= before[number.start()..].chars().count(),
+            None => return end,
+        }
+    }
+}
+
+/// The end of the word run containing `end`, taking in trailing punctuation.
+fn word_run_end(chars: &[char], mut end: usize) -> usize {
+    while end < chars.len() && !chars[end].is_whitespace() {
+        end += 1;
+    }
+    end
+}
+
+/// `from..to` of a block as text runs, split where its emphasis starts and
+/// ends.
+fn block_parts(
+    block: &Block,
+    from: usize,
+    to: usize,
+    chars: &[char],
+    words: &[(usize, usize)],
+) -> Vec<SpeechPart> {
+    let (first, last) = block.words.unwrap();
+    let mut emphasized: Vec<(usize, usize)> = Vec::new();
+    let mut cursor = first;
+    for (text, emphasis) in &block.runs {
+        let count = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .count();
+        if count == 0 {
+            continue;
+        }
+        let run_end = (cursor + count).min(last);
+        if *emphasis && cursor < run_end {
+            emphasized.push((words[cursor].0, words[run_end - 1].1));
+        }
+        cursor = run_end;
+    }
+
+    let mut parts = Vec::new();
+    let mut position = from;
+    for (start, end) in emphasized {
+        let start = start.clamp(from, to);
+        let end = end.clamp(from, to);
+        if start >= end {
+            continue;
+        }
+        parts.extend(text_part(&chars[position..start]));
+        parts.extend(text_part(&chars[start..end]).map(|part| match part {
+            SpeechPart::Text { text, .. } => SpeechPart::Text {
+                text,
+                emphasis: true,
+            },
+            pause => pause,
+        }));
+        position = end;
+    }
+    parts.extend(text_part(&chars[position..to]));
+    parts
+}
+
+/// A run of chapter text with its whitespace collapsed and the markup
+/// html2text adds removed: `*` around emphasis, heading and quote marks, and
+/// link references. Nothing if it is blank.
+fn text_part(chars: &[char]) -> Option<SpeechPart> {
+    let text: String = chars.iter().collect();
+    let text = LINK_REFERENCE.replace_all(&text, "$1");
+    let text = text
+        .split_whitespace()
+        .filter(|word| !word.chars().all(|c| matches!(c, '#' | '>' | '*')))
+        .map(strip_emphasis_marks)
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!text.is_empty()).then_some(SpeechPart::Text {
+        text,
+        emphasis: false,
+    })
+}
+
+/// Drops runs of `*` at the start of a word or before punctuation, as in
+/// `*very*` or `**cold**.`, keeping those inside a word.
+fn strip_emphasis_marks(word: &str) -> String {
+    let chars: Vec<char> = word.chars().collect();
+    let mut kept = String::new();
+    let mut index = 0;
+    while index < chars.len() {
+        if chars[index] != '*' {
+            kept.push(chars[index]);
+            index += 1;
+            continue;
+        }
+        let run_end = chars[index..]
+            .iter()
+            .position(|&c| c != '*')
+            .map_or(chars.len(), |offset| index + offset);
+        let inside_word =
+            !kept.is_empty() && chars.get(run_end).is_some_and(|c| c.is_alphanumeric());
+        if inside_word {
+            kept.extend(&chars[index..run_end]);
+        }
+        index = run_end;
+    }
+    kept
+}
@@ -0,0 +1,330 @@
+use crate::models::{
+    Book, BookEntry, Chapter, ChapterChange, ChapterChangeStatus, DuplicateMatch, DuplicateReason,
+    ImportedMetadata,
+};
+use std::collections::HashSet;
+
+pub fn find_duplicates(
+    library: &[BookEntry],
+    content_hash: Option<&str>,
+    identifier: Option<&str>,
+) -> Vec<DuplicateMatch> {
+    library
+        .iter()
+        .filter_map(|entry| {
+            let reason = if content_hash.is_some() && entry.content_hash.as_deref() == content_hash
+            {
+                DuplicateReason::SameFile
+            } else if identifier.is_some() && entry.identifier.as_deref() == identifier {
+                DuplicateReason::SameIdentifier
+            } else {
+                return None;
+            };
+            Some(DuplicateMatch {
+                book_id: entry.id.clone(),
+                title: entry.title.clone(),
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// Replaces `entry`'s contents with a freshly parsed edition while keeping its
+/// id and import date. Title, author, series and description the reader
+/// edited, that is, which differ from what the previous import recorded, are
+/// kept; books without such a record keep all four. Returns how each chapter
+/// maps from the old edition to the new one so stores keyed by chapter id can
+/// follow along.
+pub fn update_entry(entry: &mut BookEntry, book: Book) -> Vec<ChapterChange> {
+    let changes = diff_chapters(&entry.chapters, &book.chapters);
+    let recorded = entry.imported.replace(imported_metadata(&book));
+    let recorded = recorded.as_ref();
+
+    let author = entry.author.clone();
+    merge(&mut entry.title, recorded.map(|r| &r.title), book.title);
+    merge(&mut entry.author, recorded.map(|r| &r.author), book.author);
+    if entry.author != author {
+        entry.author_sort = None;
+    }
+    if book.series.is_some() {
+        let mut series = (entry.series.take(), entry.series_index.take());
+        let recorded_series = recorded.map(|r| (r.series.clone(), r.series_index));
+        merge(
+            &mut series,
+            recorded_series.as_ref(),
+            (book.series, book.series_index),
+        );
+        (entry.series, entry.series_index) = series;
+    }
+    if book.description.is_some() {
+        merge(
+            &mut entry.description,
+            recorded.map(|r| &r.description),
+            book.description,
+        );
+    }
+    entry.language = book.language.or(entry.language.take());
+    if book.cover_base64.is_some() {
+        entry.cover_base64 = book.cover_base64;
+        entry.cover_mime = book.cover_mime;
+    }
+    entry.chapters = book.chapters;
+    entry.content_hash = book.content_hash;
+    entry.identifier = book.identifier.or(entry.identifier.take());
+    entry.lexicon = book.lexicon;
+    changes
+}
+
+/// The metadata to record for `book`'s import.
+pub fn imported_metadata(book: &Book) -> ImportedMetadata {
+    ImportedMetadata {
+        title: book.title.clone(),
+        author: book.author.clone(),
+        series: book.series.clone(),
+        series_index: book.series_index,
+        description: book.description.clone(),
+    }
+}
+
+/// Takes the new edition's value unless the reader changed `current` from
+/// what the previous import recorded.
+fn merge<T: PartialEq>(current: &mut T, recorded: Option<&T>, incoming: T) {
+    if recorded == Some(&*current) {
+        *current = incoming;
+    }
+}
+
+/// Pairs chapters by `source_href` first, then by content hash for chapters
+/// whose file was renamed. Paired chapters with different hashes are reported
+/// as modified; everything else is added or removed.
+pub fn diff_chapters(old: &[Chapter], new: &[Chapter]) -> Vec<ChapterChange> {
+    let mut claimed = HashSet::new();
+    let mut changes = Vec::new();
+
+    for chapter in new {
+        let by_href = chapter.source_href.as_ref().and_then(|href| {
+            old.iter().position(|candidate| {
+                !claimed.contains(&candidate.id) && candidate.source_href.as_ref() == Some(href)
+            })
+        });
+        let by_hash = || {
+            chapter.content_hash.as_ref().and_then(|hash| {
+                old.iter().position(|candidate| {
+                    !claimed.contains(&candidate.id) && candidate.content_hash.as_ref() == Some(hash)
+                })
+            })
+        };
+
+        match by_href.or_else(by_hash) {
+            Some(index) => {
+                let previous = &old[index];
+                claimed.insert(previous.id.clone());
+                let unchanged = previous.content_hash.is_some()
+                    && previous.content_hash == chapter.content_hash;
+                changes.push(ChapterChange {
+                    old_id: Some(previous.id.clone()),
+                    new_id: Some(chapter.id.clone()),
+                    status: if unchanged {
+                        ChapterChangeStatus::Unchanged
+                    } else {
+                        ChapterChangeStatus::Modified
+                    },
+                });
+            }
+            None => changes.push(ChapterChange {
+                old_id: None,
+                new_id: Some(chapter.id.clone()),
+                status: ChapterChangeStatus::Added,
+            }),
+        }
+    }
+
+    for chapter in old.iter().filter(|chapter| !claimed.contains(&chapter.id)) {
+        changes.push(ChapterChange {
+            old_id: Some(chapter.id.clone()),
+            new_id: None,
+            status: ChapterChangeStatus::Removed,
+        });
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(id: &str, href: &str, hash: &str) -> Chapter {
+        Chapter {
+            id: id.to_string(),
+            title: id.to_string(),
+            text: String::new(),
+            html: None,
+            source_href: Some(href.to_string()),
+            word_count: 0,
+            content_hash: Some(hash.to_string()),
+            pronunciations: Vec::new(),
+        }
+    }
+
+    fn book(title: &str, author: &str, chapters: Vec<Chapter>) -> Book {
+        Book {
+            title: title.to_string(),
+            author: Some(author.to_string()),
+            chapters,
+            cover_base64: None,
+            cover_mime: None,
+            language: Some("en".to_string()),
+            series: Some("Discworld".to_string()),
+            series_index: Some(1.0),
+            content_hash: Some("edition-1".to_string()),
+            identifier: Some("urn:isbn:9780552166591".to_string()),
+            description: None,
+            lexicon: Vec::new(),
+        }
+    }
+
+    fn entry(book: Book) -> BookEntry {
+        BookEntry {
+            id: "book-1".to_string(),
+            imported: Some(imported_metadata(&book)),
+            title: book.title,
+            author: book.author,
+            author_sort: Some("Pratchett, Terry".to_string()),
+            cover_base64: None,
+            cover_mime: None,
+            chapters: book.chapters,
+            imported_at: "2024-01-01T00:00:00Z".to_string(),
+            language: book.language,
+            series: book.series,
+            series_index: book.series_index,
+            tags: vec!["fantasy".to_string()],
+            content_hash: book.content_hash,
+            identifier: book.identifier,
+            description: book.description,
+            lexicon: book.lexicon,
+        }
+    }
+
+    fn change(old: Option<&str>, new: Option<&str>, status: ChapterChangeStatus) -> ChapterChange {
+        ChapterChange {
+            old_id: old.map(str::to_string),
+            new_id: new.map(str::to_string),
+            status,
+        }
+    }
+
+    #[test]
+    fn chapters_pair_by_href_then_by_hash() {
+        let old = vec![
+            chapter("a", "one.xhtml", "1"),
+            chapter("b", "two.xhtml", "2"),
+            chapter("c", "three.xhtml", "3"),
+            chapter("d", "four.xhtml", "4"),
+        ];
+        let new = vec![
+            chapter("a2", "one.xhtml", "1"),
+            chapter("b2", "two.xhtml", "2-revised"),
+            chapter("c2", "renamed.xhtml", "3"),
+            chapter("e2", "five.xhtml", "5"),
+        ];
+        assert_eq!(
+            diff_chapters(&old, &new),
+            vec![
+                change(Some("a"), Some("a2"), ChapterChangeStatus::Unchanged),
+                change(Some("b"), Some("b2"), ChapterChangeStatus::Modified),
+                change(Some("c"), Some("c2"), ChapterChangeStatus::Unchanged),
+                change(None, Some("e2"), ChapterChangeStatus::Added),
+                change(Some("d"), None, ChapterChangeStatus::Removed),
+            ]
+        );
+    }
+
+    #[test]
+    fn chapters_without_hashes_are_modified() {
+        let mut old = chapter("a", "one.xhtml", "");
+        old.content_hash = None;
+        let mut new = chapter("a2", "one.xhtml", "");
+        new.content_hash = None;
+        assert_eq!(
+            diff_chapters(&[old], &[new]),
+            vec![change(Some("a"), Some("a2"), ChapterChangeStatus::Modified)]
+        );
+    }
+
+    #[test]
+    fn duplicates_match_the_file_before_the_identifier() {
+        let library = vec![entry(book(
+            "The Colour of Magic",
+            "Terry Pratchett",
+            Vec::new(),
+        ))];
+        let same_file = find_duplicates(&library, Some("edition-1"), None);
+        assert!(matches!(same_file[0].reason, DuplicateReason::SameFile));
+        let same_book =
+            find_duplicates(&library, Some("edition-2"), Some("urn:isbn:9780552166591"));
+        assert!(matches!(
+            same_book[0].reason,
+            DuplicateReason::SameIdentifier
+        ));
+        assert!(find_duplicates(&library, Some("edition-2"), None).is_empty());
+    }
+
+    #[test]
+    fn reimport_takes_the_new_editions_metadata() {
+        let mut entry = entry(book("The Colour of Magic", "Terry Pratchett", Vec::new()));
+        let changes = update_entry(
+            &mut entry,
+            book(
+                "The Colour Of Magic",
+                "Sir Terry Pratchett",
+                vec![chapter("a", "one.xhtml", "1")],
+            ),
+        );
+        assert_eq!(entry.id, "book-1");
+        assert_eq!(entry.title, "The Colour Of Magic");
+        assert_eq!(entry.author.as_deref(), Some("Sir Terry Pratchett"));
+        assert_eq!(entry.author_sort, None);
+        assert_eq!(entry.tags, ["fantasy"]);
+        assert_eq!(entry.imported.unwrap().title, "The Colour Of Magic");
+        assert_eq!(
+            changes,
+            vec![change(None, Some("a"), ChapterChangeStatus::Added)]
+        );
+    }
+
+    #[test]
+    fn reimport_keeps_the_readers_edits() {
+        let mut entry = entry(book("The Colour of Magic", "Terry Pratchett", Vec::new()));
+        entry.title = "Colour of Magic, The".to_string();
+        entry.series = Some("Rincewind".to_string());
+        entry.series_index = Some(1.0);
+        update_entry(
+            &mut entry,
+            book("The Colour Of Magic", "Sir Terry Pratchett", Vec::new()),
+        );
+        assert_eq!(entry.title, "Colour of Magic, The");
+        assert_eq!(entry.author.as_deref(), Some("Sir Terry Pratchett"));
+        assert_eq!(entry.series.as_deref(), Some("Rincewind"));
+
+        // The next edition compares against this import, not the first.
+        update_entry(
+            &mut entry,
+            book("The Colour of Magic", "Sir Terry Pratchett", Vec::new()),
+        );
+        assert_eq!(entry.title, "Colour of Magic, The");
+    }
+
+    #[test]
+    fn reimport_keeps_metadata_of_books_without_a_record() {
+        let mut entry = entry(book("The Colour of Magic", "Terry Pratchett", Vec::new()));
+        entry.imported = None;
+        update_entry(
+            &mut entry,
+            book("The Colour Of Magic", "Sir Terry Pratchett", Vec::new()),
+        );
+        assert_eq!(entry.title, "The Colour of Magic");
+        assert_eq!(entry.author.as_deref(), Some("Terry Pratchett"));
+        assert!(entry.imported.is_some());
+    }
+}
@@ -0,0 +1,703 @@
+use crate::config;
+use crate::epub;
+use crate::models::{BookEntry, SearchHit};
+use crate::storage;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+const INDEX_VERSION: u32 = 1;
+const DEFAULT_LIMIT: usize = 50;
+const SNIPPET_BEFORE: usize = 60;
+const SNIPPET_AFTER: usize = 120;
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+/// Lazily loaded index shared by the library and search commands.
+#[derive(Default)]
+pub struct SearchState(pub Mutex<Option<SearchIndex>>);
+
+/// Inverted index over chapter text. Postings are keyed by the normalized
+/// surface form of each word so prefix queries see what the reader typed;
+/// `stems` maps a stem back to every surface form that produced it.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchIndex {
+    version: u32,
+    next_doc: u32,
+    books: HashMap<String, IndexedBook>,
+    docs: HashMap<u32, IndexedDoc>,
+    terms: BTreeMap<String, Vec<Posting>>,
+    stems: BTreeMap<String, BTreeSet<String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexedBook {
+    signature: String,
+    language: Option<String>,
+    docs: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexedDoc {
+    book_id: String,
+    chapter_id: String,
+    length: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Posting {
+    doc: u32,
+    positions: Vec<u32>,
+}
+
+pub struct Token {
+    pub term: String,
+    /// Character (not byte) offsets into the source text.
+    pub start: usize,
+    pub end: usize,
+}
+
+enum Clause {
+    Word(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+struct ClauseMatches {
+    docs: HashMap<u32, Vec<u32>>,
+    span: u32,
+}
+
+impl SearchIndex {
+    pub fn load(path: &Path) -> SearchIndex {
+        let index = fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<SearchIndex>(&bytes).ok())
+            .filter(|index| index.version == INDEX_VERSION);
+        index.unwrap_or_else(|| SearchIndex {
+            version: INDEX_VERSION,
+            ..SearchIndex::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_vec(self)
+            .map_err(|e| format!("Failed to serialize search index: {}", e))?;
+        storage::write_atomic(path, &json)
+    }
+
+    /// Brings the index in line with `books`, re-indexing only books whose
+    /// chapters changed. Returns whether anything was modified.
+    pub fn sync(&mut self, books: &[BookEntry]) -> bool {
+        let wanted: HashSet<&str> = books.iter().map(|book| book.id.as_str()).collect();
+        let stale: Vec<String> = self
+            .books
+            .keys()
+            .filter(|id| !wanted.contains(id.as_str()))
+            .cloned()
+            .collect();
+        let mut changed = !stale.is_empty();
+        for id in stale {
+            self.remove_book(&id);
+        }
+
+        for book in books {
+            let signature = book_signature(book);
+            if self.books.get(&book.id).map(|indexed| &indexed.signature) == Some(&signature) {
+                continue;
+            }
+            self.remove_book(&book.id);
+            self.add_book(book, signature);
+            changed = true;
+        }
+        changed
+    }
+
+    fn add_book(&mut self, book: &BookEntry, signature: String) {
+        let stemmer = stemmer_for(book.language.as_deref());
+        let mut docs = Vec::with_capacity(book.chapters.len());
+        for chapter in &book.chapters {
+            let doc = self.next_doc;
+            self.next_doc += 1;
+            let tokens = tokenize(&chapter.text);
+            let mut positions: HashMap<&str, Vec<u32>> = HashMap::new();
+            for (position, token) in tokens.iter().enumerate() {
+                positions
+                    .entry(token.term.as_str())
+                    .or_default()
+                    .push(position as u32);
+            }
+            for (term, positions) in positions {
+                self.terms
+                    .entry(term.to_string())
+                    .or_default()
+                    .push(Posting { doc, positions });
+                if let Some(stemmer) = &stemmer {
+                    self.stems
+                        .entry(stemmer.stem(term).to_string())
+                        .or_default()
+                        .insert(term.to_string());
+                }
+            }
+            self.docs.insert(
+                doc,
+                IndexedDoc {
+                    book_id: book.id.clone(),
+                    chapter_id: chapter.id.clone(),
+                    length: tokens.len() as u32,
+                },
+            );
+            docs.push(doc);
+        }
+        self.books.insert(
+            book.id.clone(),
+            IndexedBook {
+                signature,
+                language: book.language.clone(),
+                docs,
+            },
+        );
+    }
+
+    fn remove_book(&mut self, book_id: &str) {
+        let Some(book) = self.books.remove(book_id) else {
+            return;
+        };
+        let removed: HashSet<u32> = book.docs.into_iter().collect();
+        for doc in &removed {
+            self.docs.remove(doc);
+        }
+        self.terms.retain(|_, postings| {
+            postings.retain(|posting| !removed.contains(&posting.doc));
+            !postings.is_empty()
+        });
+        let terms = &self.terms;
+        self.stems.retain(|_, surfaces| {
+            surfaces.retain(|surface| terms.contains_key(surface));
+            !surfaces.is_empty()
+        });
+    }
+
+    /// Runs `query` and returns hits ranked by BM25. Bare words match any
+    /// inflection sharing their stem, `word*` matches by prefix and
+    /// `"quoted words"` must appear consecutively. All clauses must match.
+    pub fn search(&self, library: &[BookEntry], query: &str, limit: Option<usize>) -> Vec<SearchHit> {
+        let clauses = parse_query(query);
+        if clauses.is_empty() || self.docs.is_empty() {
+            return Vec::new();
+        }
+        let stemmers: Vec<Stemmer> = self
+            .books
+            .values()
+            .map(|book| book.language.as_deref().map(str::to_lowercase))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter_map(|language| stemmer_for(language.as_deref()))
+            .collect();
+
+        let matches: Vec<ClauseMatches> = clauses
+            .iter()
+            .map(|clause| self.match_clause(clause, &stemmers))
+            .collect();
+        let Some((first, rest)) = matches.split_first() else {
+            return Vec::new();
+        };
+
+        let total_docs = self.docs.len() as f64;
+        let average_length = self
+            .docs
+            .values()
+            .map(|doc| doc.length as f64)
+            .sum::<f64>()
+            / total_docs;
+
+        let mut scored: Vec<(u32, f64)> = first
+            .docs
+            .keys()
+            .filter(|doc| rest.iter().all(|clause| clause.docs.contains_key(doc)))
+            .map(|doc| {
+                let length = self.docs.get(doc).map_or(0.0, |doc| doc.length as f64);
+                let score = matches
+                    .iter()
+                    .map(|clause| {
+                        let frequency = clause.docs.get(doc).map_or(0, Vec::len) as f64;
+                        let document_frequency = clause.docs.len() as f64;
+                        let idf = (1.0
+                            + (total_docs - document_frequency + 0.5)
+                                / (document_frequency + 0.5))
+                            .ln();
+                        let norm = BM25_K1 * (1.0 - BM25_B + BM25_B * length / average_length);
+                        idf * frequency * (BM25_K1 + 1.0) / (frequency + norm)
+                    })
+                    .sum();
+                (*doc, score)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored.truncate(limit.unwrap_or(DEFAULT_LIMIT));
+
+        scored
+            .into_iter()
+            .filter_map(|(doc, score)| {
+                let indexed = self.docs.get(&doc)?;
+                let book = library.iter().find(|book| book.id == indexed.book_id)?;
+                let chapter = book
+                    .chapters
+                    .iter()
+                    .find(|chapter| chapter.id == indexed.chapter_id)?;
+                let (position, span) = matches
+                    .iter()
+                    .filter_map(|clause| {
+                        clause.docs.get(&doc)?.first().map(|position| (*position, clause.span))
+                    })
+                    .min()?;
+                let match_count = matches
+                    .iter()
+                    .map(|clause| clause.docs.get(&doc).map_or(0, Vec::len))
+                    .sum();
+                let tokens = tokenize(&chapter.text);
+                let start = tokens.get(position as usize)?.start;
+                let end = tokens
+                    .get((position + span - 1) as usize)
+                    .map_or(start, |token| token.end);
+                let (snippet, snippet_match_start, snippet_match_end) =
+                    build_snippet(&chapter.text, start, end);
+                Some(SearchHit {
+                    book_id: book.id.clone(),
+                    book_title: book.title.clone(),
+                    chapter_id: chapter.id.clone(),
+                    chapter_title: chapter.title.clone(),
+                    offset: start,
+                    length: end - start,
+                    snippet,
+                    snippet_match_start,
+                    snippet_match_end,
+                    match_count,
+                    score,
+                })
+            })
+            .collect()
+    }
+
+    fn match_clause(&self, clause: &Clause, stemmers: &[Stemmer]) -> ClauseMatches {
+        match clause {
+            Clause::Word(word) => self.single_term_matches(self.expand_word(word, stemmers)),
+            Clause::Prefix(prefix) => self.single_term_matches(self.expand_prefix(prefix)),
+            Clause::Phrase(words) => {
+                let expansions: Vec<Vec<&String>> = words
+                    .iter()
+                    .map(|word| match word.strip_suffix('*') {
+                        Some(prefix) => self.expand_prefix(prefix),
+                        None => self.expand_word(word, stemmers),
+                    })
+                    .collect();
+                self.phrase_matches(&expansions)
+            }
+        }
+    }
+
+    fn expand_word(&self, word: &str, stemmers: &[Stemmer]) -> Vec<&String> {
+        let mut surfaces: BTreeSet<&String> = BTreeSet::new();
+        if let Some((term, _)) = self.terms.get_key_value(word) {
+            surfaces.insert(term);
+        }
+        for stemmer in stemmers {
+            if let Some(stemmed) = self.stems.get(stemmer.stem(word).as_ref()) {
+                surfaces.extend(stemmed.iter());
+            }
+        }
+        surfaces.into_iter().collect()
+    }
+
+    fn expand_prefix(&self, prefix: &str) -> Vec<&String> {
+        self.terms
+            .range(prefix.to_string()..)
+            .take_while(|(term, _)| term.starts_with(prefix))
+            .map(|(term, _)| term)
+            .collect()
+    }
+
+    fn positions_for(&self, terms: &[&String]) -> HashMap<u32, Vec<u32>> {
+        let mut docs: HashMap<u32, Vec<u32>> = HashMap::new();
+        for term in terms {
+            for posting in self.terms.get(*term).into_iter().flatten() {
+                docs.entry(posting.doc)
+                    .or_default()
+                    .extend(posting.positions.iter().copied());
+            }
+        }
+        for positions in docs.values_mut() {
+            positions.sort_unstable();
+            positions.dedup();
+        }
+        docs
+    }
+
+    fn single_term_matches(&self, terms: Vec<&String>) -> ClauseMatches {
+        ClauseMatches {
+            docs: self.positions_for(&terms),
+            span: 1,
+        }
+    }
+
+    fn phrase_matches(&self, expansions: &[Vec<&String>]) -> ClauseMatches {
+        let per_word: Vec<HashMap<u32, Vec<u32>>> = expansions
+            .iter()
+            .map(|terms| self.positions_for(terms))
+            .collect();
+        let mut docs = HashMap::new();
+        if let Some((first, rest)) = per_word.split_first() {
+            for (doc, starts) in first {
+                let hits: Vec<u32> = starts
+                    .iter()
+                    .copied()
+                    .filter(|start| {
+                        rest.iter().enumerate().all(|(offset, word)| {
+                            word.get(doc).is_some_and(|positions| {
+                                positions.binary_search(&(start + offset as u32 + 1)).is_ok()
+                            })
+                        })
+                    })
+                    .collect();
+                if !hits.is_empty() {
+                    docs.insert(*doc, hits);
+                }
+            }
+        }
+        ClauseMatches {
+            docs,
+            span: expansions.len().max(1) as u32,
+        }
+    }
+}
+
+pub fn sync_and_save(
+    state: &SearchState,
+    index_path: &Path,
+    books: &[BookEntry],
+) -> Result<(), String> {
+    let mut guard = state
+        .0
+        .lock()
+        .map_err(|_| "Search index lock poisoned".to_string())?;
+    let index = guard.get_or_insert_with(|| SearchIndex::load(index_path));
+    if index.sync(books) {
+        index.save(index_path)?;
+    }
+    Ok(())
+}
+
+/// Brings the index in line with `books` after the library changed.
+/// Indexing failures are logged rather than returned so that a broken
+/// index never prevents the library itself from being saved.
+pub fn update_index(app: &AppHandle, books: &[BookEntry]) {
+    let result = config::get_search_index_path(app)
+        .and_then(|index_path| sync_and_save(&app.state::<SearchState>(), &index_path, books));
+    if let Err(error) = result {
+        println!("DEBUG WARNING: Failed to update search index: {}", error);
+    }
+}
+
+/// Splits text into normalized words using Unicode word boundaries. Terms are
+/// lowercased with diacritics folded, so "Eärendil" matches "earendil".
+pub fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut last_byte = 0;
+    let mut last_char = 0;
+    for (byte_index, word) in text.unicode_word_indices() {
+        last_char += text[last_byte..byte_index].chars().count();
+        last_byte = byte_index;
+        let term = normalize_term(word);
+        if term.is_empty() {
+            continue;
+        }
+        let length = word.chars().count();
+        tokens.push(Token {
+            term,
+            start: last_char,
+            end: last_char + length,
+        });
+    }
+    tokens
+}
+
+fn normalize_term(word: &str) -> String {
+    word.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .map(|c| if c == '\u{2019}' { '\'' } else { c })
+        .collect()
+}
+
+fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    for (index, part) in query.split('"').enumerate() {
+        let quoted = index % 2 == 1;
+        let words: Vec<String> = part
+            .split_whitespace()
+            .flat_map(|raw| {
+                let prefix = raw.ends_with('*');
+                let terms: Vec<String> = tokenize(raw).into_iter().map(|token| token.term).collect();
+                let count = terms.len();
+                terms.into_iter().enumerate().map(move |(position, term)| {
+                    if prefix && position + 1 == count {
+                        format!("{term}*")
+                    } else {
+                        term
+                    }
+                })
+            })
+            .collect();
+        if quoted && words.len() > 1 {
+            clauses.push(Clause::Phrase(words));
+            continue;
+        }
+        for word in words {
+            clauses.push(match word.strip_suffix('*') {
+                Some(prefix) => Clause::Prefix(prefix.to_string()),
+                None => Clause::Word(word),
+            });
+        }
+    }
+    clauses
+}
+
+/// Untagged books are assumed to be English; languages without a Snowball
+/// stemmer (CJK among them) are matched on exact words only.
+fn stemmer_for(language: Option<&str>) -> Option<Stemmer> {
+    let code = language
+        .unwrap_or("en")
+        .split(['-', '_'])
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    let algorithm = match code.as_str() {
+        "en" | "eng" => Algorithm::English,
+        "ar" => Algorithm::Arabic,
+        "da" => Algorithm::Danish,
+        "nl" => Algorithm::Dutch,
+        "fi" => Algorithm::Finnish,
+        "fr" => Algorithm::French,
+        "de" => Algorithm::German,
+        "el" => Algorithm::Greek,
+        "hu" => Algorithm::Hungarian,
+        "it" => Algorithm::Italian,
+        "no" | "nb" | "nn" => Algorithm::Norwegian,
+        "pt" => Algorithm::Portuguese,
+        "ro" => Algorithm::Romanian,
+        "ru" => Algorithm::Russian,
+        "es" => Algorithm::Spanish,
+        "sv" => Algorithm::Swedish,
+        "ta" => Algorithm::Tamil,
+        "tr" => Algorithm::Turkish,
+        _ => return None,
+    };
+    Some(Stemmer::create(algorithm))
+}
+
+fn book_signature(book: &BookEntry) -> String {
+    let mut parts = vec![book.language.clone().unwrap_or_default()];
+    for chapter in &book.chapters {
+        parts.push(chapter.id.clone());
+        parts.push(
+            chapter
+                .content_hash
+                .clone()
+                .unwrap_or_else(|| epub::hash_text(&chapter.text)),
+        );
+    }
+    epub::hash_bytes(parts.join("\n").as_bytes())
+}
+
+/// Cuts a window of text around `[start, end)` (character offsets) and
+/// returns it with the match position relative to the snippet.
+fn build_snippet(text: &str, start: usize, end: usize) -> (String, usize, usize) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut from = start.saturating_sub(SNIPPET_BEFORE);
+    let mut to = (end + SNIPPET_AFTER).min(chars.len());
+    while from > 0 && from < start && !chars[from - 1].is_whitespace() {
+        from += 1;
+    }
+    while to < chars.len() && to > end && !chars[to].is_whitespace() {
+        to -= 1;
+    }
+
+    let mut snippet = String::new();
+    if from > 0 {
+        snippet.push('\u{2026}');
+    }
+    let lead = snippet.chars().count();
+    let body: String = chars[from..to]
+        .iter()
+        .map(|c| if c.is_whitespace() { ' ' } else { *c })
+        .collect();
+    snippet.push_str(&body);
+    if to < chars.len() {
+        snippet.push('\u{2026}');
+    }
+    (snippet, lead + start - from, lead + end - from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn book(id: &str, language: &str, chapters: &[(&str, &str)]) -> BookEntry {
+        let chapters: Vec<_> = chapters
+            .iter()
+            .map(|(chapter_id, text)| {
+                json!({
+                    "id": chapter_id,
+                    "title": chapter_id,
+                    "text": text,
+                    "html": null,
+                    "sourceHref": null,
+                    "wordCount": 0,
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "id": id,
+            "title": id,
+            "author": null,
+            "coverBase64": null,
+            "coverMime": null,
+            "chapters": chapters,
+            "importedAt": "",
+            "language": language,
+        }))
+        .unwrap()
+    }
+
+    fn library() -> Vec<BookEntry> {
+        vec![
+            book(
+                "hobbit",
+                "en",
+                &[
+                    ("h1", "In a hole in the ground there lived a hobbit."),
+                    ("h2", "The dragon slept on the gold. Dragons are greedy."),
+                    ("h3", "He found a silver ring, and the ring was silver."),
+                ],
+            ),
+            book(
+                "silmarillion",
+                "en",
+                &[("s1", "Eärendil sailed west. He runs before the wind.")],
+            ),
+        ]
+    }
+
+    fn indexed(books: &[BookEntry]) -> SearchIndex {
+        let mut index = SearchIndex {
+            version: INDEX_VERSION,
+            ..SearchIndex::default()
+        };
+        index.sync(books);
+        index
+    }
+
+    fn chapters(index: &SearchIndex, books: &[BookEntry], query: &str) -> Vec<String> {
+        index
+            .search(books, query, None)
+            .into_iter()
+            .map(|hit| hit.chapter_id)
+            .collect()
+    }
+
+    #[test]
+    fn tokens_are_folded_with_character_offsets() {
+        let tokens: Vec<(String, usize, usize)> = tokenize("Café  Crème, Eärendil’s")
+            .into_iter()
+            .map(|token| (token.term, token.start, token.end))
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                ("cafe".to_string(), 0, 4),
+                ("creme".to_string(), 6, 11),
+                ("earendil's".to_string(), 13, 23),
+            ]
+        );
+    }
+
+    #[test]
+    fn queries_match_words_stems_prefixes_and_phrases() {
+        let books = library();
+        let index = indexed(&books);
+        let cases: &[(&str, &[&str])] = &[
+            ("hobbit", &["h1"]),
+            ("earendil", &["s1"]),
+            ("running", &["s1"]),
+            ("drag*", &["h2"]),
+            ("\"silver ring\"", &["h3"]),
+            ("\"ring silver\"", &[]),
+            ("dragon gold", &["h2"]),
+            ("dragon hobbit", &[]),
+            ("", &[]),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(chapters(&index, &books, query), *expected, "{query}");
+        }
+    }
+
+    #[test]
+    fn more_matches_rank_higher() {
+        let books = vec![book(
+            "b",
+            "en",
+            &[
+                ("once", "The ring. Then a long walk over hills and rivers."),
+                ("twice", "The ring, the ring."),
+            ],
+        )];
+        let index = indexed(&books);
+        assert_eq!(chapters(&index, &books, "ring"), ["twice", "once"]);
+    }
+
+    #[test]
+    fn hits_locate_the_match_in_the_chapter_and_snippet() {
+        let books = library();
+        let index = indexed(&books);
+        let hits = index.search(&books, "\"silver ring\"", None);
+        let hit = &hits[0];
+        assert_eq!(hit.book_id, "hobbit");
+        let text: Vec<char> = books[0].chapters[2].text.chars().collect();
+        let matched: String = text[hit.offset..hit.offset + hit.length].iter().collect();
+        assert_eq!(matched, "silver ring");
+        let snippet: Vec<char> = hit.snippet.chars().collect();
+        let in_snippet: String = snippet[hit.snippet_match_start..hit.snippet_match_end]
+            .iter()
+            .collect();
+        assert_eq!(in_snippet, "silver ring");
+        assert_eq!(hit.match_count, 1);
+    }
+
+    #[test]
+    fn sync_only_reindexes_changed_books() {
+        let mut books = library();
+        let mut index = indexed(&books);
+        assert!(!index.sync(&books));
+
+        books[1].chapters[0].text = "Eärendil sailed east.".to_string();
+        assert!(index.sync(&books));
+        assert!(chapters(&index, &books, "west").is_empty());
+        assert_eq!(chapters(&index, &books, "east"), ["s1"]);
+
+        books.remove(0);
+        assert!(index.sync(&books));
+        assert!(chapters(&index, &books, "hobbit").is_empty());
+        assert!(index.terms.keys().all(|term| term != "dragon"));
+    }
+}
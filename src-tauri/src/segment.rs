@@ -0,0 +1,443 @@
+use crate::models::{NarrationChunk, SentenceSpan};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Chunks aim for about this many characters unless the caller asks for
+/// another size: long enough to sound natural, short enough to start quickly.
+pub const DEFAULT_TARGET_CHARACTERS: usize = 400;
+
+/// Abbreviations that never end a sentence, lowercased without their final
+/// period.
+const ABBREVIATIONS_EN: &[&str] = &[
+    "mr", "mrs", "ms", "mx", "dr", "prof", "sr", "jr", "st", "mt", "vs", "e.g", "i.e", "cf", "inc",
+    "ltd", "co", "corp", "gen", "col", "capt", "lt", "sgt", "rev", "hon", "approx", "dept", "est",
+    "jan", "feb", "mar", "apr", "jun", "jul", "aug", "sep", "sept", "oct", "nov", "dec", "a.m",
+    "p.m",
+];
+const ABBREVIATIONS_DE: &[&str] = &[
+    "hr", "fr", "dr", "prof", "z.b", "u.a", "usw", "bzw", "ca", "vgl", "evtl", "ggf", "d.h",
+    "inkl", "sog", "str", "jh", "u.ä", "o.ä",
+];
+const ABBREVIATIONS_FR: &[&str] = &[
+    "m", "mm", "mme", "mmes", "mlle", "dr", "pr", "me", "st", "ste", "cf", "env", "av", "bd",
+    "p.ex",
+];
+const ABBREVIATIONS_ES: &[&str] = &[
+    "sr", "sra", "srta", "sres", "dr", "dra", "ud", "uds", "d", "dña", "av", "p.ej", "ee.uu",
+];
+const ABBREVIATIONS_IT: &[&str] = &[
+    "sig", "sigg", "sig.ra", "dott", "prof", "avv", "ing", "p.es",
+];
+const ABBREVIATIONS_PT: &[&str] = &[
+    "sr", "sra", "srta", "dr", "dra", "prof", "profa", "p.ex", "av",
+];
+
+/// Abbreviations that only bind to a following number, like "No. 5", so a
+/// sentence can still end with the word itself.
+const NUMBER_ABBREVIATIONS: &[&str] = &[
+    "no", "nos", "nr", "n", "p", "pp", "vol", "ch", "fig", "art", "s", "pág", "pag", "núm",
+];
+
+/// Splits chapter text into sentences. Paragraphs are the text's lines and
+/// sentences never span them; offsets count characters into `text`.
+pub fn sentences(text: &str, language: Option<&str>) -> Vec<SentenceSpan> {
+    let abbreviations = abbreviations_for(language);
+    let mut spans = Vec::new();
+    let mut paragraph_start = 0;
+    let mut paragraph = 0;
+
+    for line in text.split('\n') {
+        let mut found = false;
+        for (start, end) in split_paragraph(line, abbreviations) {
+            let sentence = &line[start..end];
+            let start = paragraph_start + line[..start].chars().count();
+            spans.push(SentenceSpan {
+                index: spans.len(),
+                paragraph,
+                start,
+                end: start + sentence.chars().count(),
+                text: sentence.to_string(),
+            });
+            found = true;
+        }
+        if found {
+            paragraph += 1;
+        }
+        paragraph_start += line.chars().count() + 1;
+    }
+    spans
+}
+
+/// Byte ranges of the sentences in one paragraph, trimmed of whitespace.
+fn split_paragraph(line: &str, abbreviations: &[&str]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut pending: Option<(usize, usize)> = None;
+
+    for (start, segment) in line.split_sentence_bound_indices() {
+        let end = start + segment.len();
+        let range = match pending.take() {
+            Some((pending_start, _)) => (pending_start, end),
+            None => (start, end),
+        };
+        if continues_after(&line[range.0..range.1], &line[end..], abbreviations) {
+            pending = Some(range);
+        } else {
+            ranges.push(range);
+        }
+    }
+    ranges.extend(pending);
+
+    ranges
+        .into_iter()
+        .filter_map(|(start, end)| {
+            let segment = &line[start..end];
+            let trimmed = segment.trim();
+            if trimmed.is_empty() {
+                return None;
+            }
+            let offset = start + (segment.len() - segment.trim_start().len());
+            Some((offset, offset + trimmed.len()))
+        })
+        .collect()
+}
+
+/// Whether a Unicode sentence break after `segment` is really inside a
+/// sentence: after an abbreviation, an initial such as the "J." in
+/// "J. Smith", or a quoted question followed by lowercase, as in
+/// "“Really?” she asked."
+fn continues_after(segment: &str, rest: &str, abbreviations: &[&str]) -> bool {
+    let trimmed = segment.trim_end();
+    let next = rest.trim_start();
+    if next.is_empty() {
+        return false;
+    }
+    let unquoted = trimmed.trim_end_matches(['"', '\'', '”', '’', '»', ')']);
+    if unquoted.len() < trimmed.len()
+        && unquoted.ends_with(['?', '!'])
+        && next.starts_with(char::is_lowercase)
+    {
+        return true;
+    }
+    let Some(word) = trimmed.strip_suffix('.') else {
+        return false;
+    };
+    let word = word
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or("")
+        .trim_start_matches(|c: char| !c.is_alphanumeric());
+    if word.is_empty() {
+        return false;
+    }
+
+    let mut letters = word.chars();
+    if let (Some(first), None) = (letters.next(), letters.next()) {
+        if first.is_uppercase() {
+            return true;
+        }
+    }
+    let word = word.to_lowercase();
+    if abbreviations.contains(&word.as_str()) {
+        return true;
+    }
+    NUMBER_ABBREVIATIONS.contains(&word.as_str()) && next.starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn abbreviations_for(language: Option<&str>) -> &'static [&'static str] {
+    let primary = language
+        .and_then(|language| language.split(['-', '_']).next())
+        .map(|language| language.trim().to_lowercase());
+    match primary.as_deref() {
+        Some("de") => ABBREVIATIONS_DE,
+        Some("fr") => ABBREVIATIONS_FR,
+        Some("es") => ABBREVIATIONS_ES,
+        Some("it") => ABBREVIATIONS_IT,
+        Some("pt") => ABBREVIATIONS_PT,
+        _ => ABBREVIATIONS_EN,
+    }
+}
+
+/// Groups sentences into chunks of about `target` characters, never more
+/// than `max_characters`. A chunk ends early at a paragraph break once it is
+/// half full, and sentences longer than the limit are split at clause or
+/// word boundaries. Chunk ids come from the chapter and offsets, so the same
+/// text always plans to the same ids.
+pub fn plan(
+    chapter_id: &str,
+    sentences: &[SentenceSpan],
+    target: usize,
+    max_characters: Option<usize>,
+) -> Vec<NarrationChunk> {
+    let max = max_characters.unwrap_or(usize::MAX).max(1);
+    let target = target.clamp(1, max);
+    let mut chunks = Vec::new();
+    let mut current: Option<ChunkBuilder> = None;
+
+    for sentence in sentences {
+        for piece in pieces(sentence, max) {
+            if let Some(chunk) = current.take() {
+                let too_long = chunk.length + 1 + piece.length > target;
+                let new_paragraph = chunk.paragraph != sentence.paragraph;
+                if too_long || (new_paragraph && chunk.length * 2 >= target) {
+                    chunks.push(chunk.finish(chapter_id, chunks.len()));
+                } else {
+                    current = Some(chunk);
+                }
+            }
+            match current.as_mut() {
+                Some(chunk) => chunk.push(sentence, piece),
+                None => current = Some(ChunkBuilder::new(sentence, piece)),
+            }
+        }
+    }
+    if let Some(chunk) = current {
+        chunks.push(chunk.finish(chapter_id, chunks.len()));
+    }
+    chunks
+}
+
+struct Piece {
+    start: usize,
+    end: usize,
+    text: String,
+    length: usize,
+}
+
+struct ChunkBuilder {
+    start: usize,
+    end: usize,
+    parts: Vec<String>,
+    length: usize,
+    paragraph: usize,
+    first_sentence: usize,
+    last_sentence: usize,
+}
+
+impl ChunkBuilder {
+    fn new(sentence: &SentenceSpan, piece: Piece) -> Self {
+        ChunkBuilder {
+            start: piece.start,
+            end: piece.end,
+            length: piece.length,
+            parts: vec![piece.text],
+            paragraph: sentence.paragraph,
+            first_sentence: sentence.index,
+            last_sentence: sentence.index,
+        }
+    }
+
+    fn push(&mut self, sentence: &SentenceSpan, piece: Piece) {
+        self.end = piece.end;
+        self.length += 1 + piece.length;
+        self.parts.push(piece.text);
+        self.paragraph = sentence.paragraph;
+        self.last_sentence = sentence.index;
+    }
+
+    fn finish(self, chapter_id: &str, index: usize) -> NarrationChunk {
+        NarrationChunk {
+            id: format!("{}:{}-{}", chapter_id, self.start, self.end),
+            index,
+            start: self.start,
+            end: self.end,
+            text: self.parts.join(" "),
+            first_sentence: self.first_sentence,
+            sentence_count: self.last_sentence - self.first_sentence + 1,
+        }
+    }
+}
+
+/// A sentence as one piece, or several when it is longer than `max`.
+fn pieces(sentence: &SentenceSpan, max: usize) -> Vec<Piece> {
+    let chars: Vec<char> = sentence.text.chars().collect();
+    let mut pieces = Vec::new();
+    let mut position = 0;
+
+    while position < chars.len() {
+        let remaining = chars.len() - position;
+        let cut = if remaining <= max {
+            chars.len()
+        } else {
+            position + break_point(&chars[position..position + max], max)
+        };
+        let start = position
+            + chars[position..cut]
+                .iter()
+                .take_while(|c| c.is_whitespace())
+                .count();
+        let end = cut
+            - chars[start..cut]
+                .iter()
+                .rev()
+                .take_while(|c| c.is_whitespace())
+                .count();
+        if start < end {
+            let text: String = chars[start..end].iter().collect();
+            pieces.push(Piece {
+                start: sentence.start + start,
+                end: sentence.start + end,
+                text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+                length: end - start,
+            });
+        }
+        position = cut;
+    }
+    pieces
+}
+
+/// Where to cut a window of an overlong sentence: after the last clause
+/// punctuation, else at the last space, else at the limit.
+fn break_point(window: &[char], max: usize) -> usize {
+    let minimum = max / 3;
+    let after_clause = (minimum..window.len().saturating_sub(1))
+        .rev()
+        .find(|&index| {
+            matches!(
+                window[index],
+                ',' | ';' | ':' | '–' | '—' | '、' | '，' | '；'
+            ) && (window[index + 1].is_whitespace() || !window[index].is_ascii())
+        });
+    if let Some(index) = after_clause {
+        return index + 1;
+    }
+    window
+        .iter()
+        .rposition(|c| c.is_whitespace())
+        .filter(|&index| index > 0)
+        .unwrap_or(window.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(text: &str, language: Option<&str>) -> Vec<String> {
+        sentences(text, language)
+            .into_iter()
+            .map(|span| span.text)
+            .collect()
+    }
+
+    #[test]
+    fn sentences_end_at_terminal_punctuation() {
+        assert_eq!(
+            texts("It rained. Did it stop? No! It went on", None),
+            ["It rained.", "Did it stop?", "No!", "It went on"]
+        );
+    }
+
+    #[test]
+    fn abbreviations_and_initials_do_not_end_sentences() {
+        let cases: &[(&str, Option<&str>, &[&str])] = &[
+            (
+                "Mr. Smith met Dr. Jones. They talked.",
+                None,
+                &["Mr. Smith met Dr. Jones.", "They talked."],
+            ),
+            (
+                "J. R. R. Tolkien wrote it. Then he rested.",
+                Some("en"),
+                &["J. R. R. Tolkien wrote it.", "Then he rested."],
+            ),
+            (
+                "He lived at No. 4 Privet Drive. The answer was no. Then he left.",
+                None,
+                &[
+                    "He lived at No. 4 Privet Drive.",
+                    "The answer was no.",
+                    "Then he left.",
+                ],
+            ),
+            (
+                "Das ist z.B. gut. Ja.",
+                Some("de-DE"),
+                &["Das ist z.B. gut.", "Ja."],
+            ),
+            (
+                "M. Dupont est là. Il attend.",
+                Some("fr"),
+                &["M. Dupont est là.", "Il attend."],
+            ),
+        ];
+        for (text, language, expected) in cases {
+            assert_eq!(texts(text, *language), *expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn quoted_questions_continue_before_lowercase() {
+        assert_eq!(
+            texts("“Really?” she asked. “Yes!” He nodded.", None),
+            ["“Really?” she asked.", "“Yes!”", "He nodded."]
+        );
+    }
+
+    #[test]
+    fn spans_count_characters_and_paragraphs() {
+        let spans = sentences("Café au lait. Très bon.\n\n  Encore?\n", Some("fr"));
+        let found: Vec<(usize, usize, usize)> = spans
+            .iter()
+            .map(|span| (span.paragraph, span.start, span.end))
+            .collect();
+        assert_eq!(found, [(0, 0, 13), (0, 14, 23), (1, 27, 34)]);
+        assert_eq!(spans[2].text, "Encore?");
+        assert_eq!(
+            spans.iter().map(|span| span.index).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn chunks_group_sentences_up_to_the_target() {
+        let text = "One two three. Four five six. Seven eight nine. Ten eleven.";
+        let spans = sentences(text, None);
+        let chunks = plan("ch1", &spans, 30, None);
+        let found: Vec<(&str, usize, usize)> = chunks
+            .iter()
+            .map(|chunk| {
+                (
+                    chunk.id.as_str(),
+                    chunk.first_sentence,
+                    chunk.sentence_count,
+                )
+            })
+            .collect();
+        assert_eq!(found, [("ch1:0-29", 0, 2), ("ch1:30-59", 2, 2)]);
+        assert_eq!(chunks[0].text, "One two three. Four five six.");
+        assert_eq!(chunks[1].text, "Seven eight nine. Ten eleven.");
+
+        // The same text always plans to the same ids.
+        assert_eq!(
+            plan("ch1", &sentences(text, None), 30, None)[1].id,
+            chunks[1].id
+        );
+    }
+
+    #[test]
+    fn chunks_end_at_paragraphs_once_half_full() {
+        let spans = sentences("A fairly long first paragraph.\nShort.", None);
+        let chunks = plan("ch1", &spans, 40, None);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].text, "Short.");
+    }
+
+    #[test]
+    fn long_sentences_split_at_clauses_within_the_limit() {
+        let text =
+            "The road wound on, past the mill and the river, until at last it reached the town.";
+        let chunks = plan("ch1", &sentences(text, None), 40, Some(40));
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.text.chars().count() <= 40, "{}", chunk.text);
+            assert_eq!(chunk.first_sentence, 0);
+            assert_eq!(
+                chunk.text,
+                text.chars()
+                    .skip(chunk.start)
+                    .take(chunk.end - chunk.start)
+                    .collect::<String>()
+            );
+        }
+        assert_eq!(chunks[0].text, "The road wound on,");
+        assert_eq!(chunks.last().unwrap().end, text.chars().count());
+    }
+}
@@ -0,0 +1,236 @@
+use crate::models::{
+    BookActivity, BookEntry, Collection, ReadingStatus, ShelfQuery, ShelfSort, Shelves,
+    SmartShelf, SortKey,
+};
+use crate::storage;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use tauri::{AppHandle, Manager};
+
+/// Progress at or above this fraction counts as finished.
+const FINISHED_THRESHOLD: f64 = 0.98;
+
+/// Kept in Tauri state so that shelf commands and backup restores do not
+/// overwrite each other's changes to `shelves.json`. Hold the guard from
+/// `lock` from reading the shelves until they are written.
+#[derive(Default)]
+pub struct ShelfState(Mutex<()>);
+
+pub fn lock(app: &AppHandle) -> Result<MutexGuard<'_, ()>, String> {
+    app.state::<ShelfState>()
+        .inner()
+        .0
+        .lock()
+        .map_err(|_| "Shelf lock poisoned".to_string())
+}
+
+pub struct ShelfContext<'a> {
+    pub shelves: &'a Shelves,
+    pub activity: &'a HashMap<String, BookActivity>,
+    pub cached_audio: &'a HashSet<String>,
+}
+
+pub fn create_collection(shelves: &mut Shelves, name: String) -> Collection {
+    let collection = Collection {
+        id: new_id("collection", &name),
+        name,
+        book_ids: Vec::new(),
+        created_at: storage::now_iso(),
+    };
+    shelves.collections.push(collection.clone());
+    collection
+}
+
+pub fn create_smart_shelf(
+    shelves: &mut Shelves,
+    name: String,
+    query: ShelfQuery,
+    sort: ShelfSort,
+) -> SmartShelf {
+    let shelf = SmartShelf {
+        id: new_id("shelf", &name),
+        name,
+        query,
+        sort,
+    };
+    shelves.smart_shelves.push(shelf.clone());
+    shelf
+}
+
+/// Returns the ids of books matching `query`, ordered by `sort`.
+pub fn evaluate(
+    books: &[BookEntry],
+    context: &ShelfContext,
+    query: &ShelfQuery,
+    sort: ShelfSort,
+) -> Result<Vec<String>, String> {
+    let collection = match &query.collection_id {
+        Some(id) => Some(
+            context
+                .shelves
+                .collections
+                .iter()
+                .find(|collection| &collection.id == id)
+                .ok_or_else(|| format!("Collection {} not found", id))?,
+        ),
+        None => None,
+    };
+
+    let mut matched: Vec<&BookEntry> = books
+        .iter()
+        .filter(|book| {
+            collection.is_none_or(|collection| collection.book_ids.contains(&book.id))
+        })
+        .filter(|book| matches(book, context, query))
+        .collect();
+    matched.sort_by(|a, b| {
+        let ordering = compare(a, b, context, sort.key);
+        if sort.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    Ok(matched.into_iter().map(|book| book.id.clone()).collect())
+}
+
+pub fn reading_status(activity: Option<&BookActivity>) -> ReadingStatus {
+    match activity.map(|activity| activity.progress) {
+        Some(progress) if progress >= FINISHED_THRESHOLD => ReadingStatus::Finished,
+        Some(progress) if progress > 0.0 => ReadingStatus::InProgress,
+        _ => ReadingStatus::Unread,
+    }
+}
+
+/// Normalizes tags to trimmed, case-insensitively unique values in the order
+/// they were given.
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tags.into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty() && seen.insert(tag.to_lowercase()))
+        .collect()
+}
+
+fn matches(book: &BookEntry, context: &ShelfContext, query: &ShelfQuery) -> bool {
+    if let Some(author) = query.author.as_deref().filter(|value| !value.is_empty()) {
+        if !contains_ignore_case(book.author.as_deref(), author) {
+            return false;
+        }
+    }
+    if let Some(series) = query.series.as_deref().filter(|value| !value.is_empty()) {
+        if !contains_ignore_case(book.series.as_deref(), series) {
+            return false;
+        }
+    }
+    if let Some(language) = query.language.as_deref().filter(|value| !value.is_empty()) {
+        if !language_matches(book.language.as_deref(), language) {
+            return false;
+        }
+    }
+    if !query.tags.iter().all(|wanted| {
+        book.tags
+            .iter()
+            .any(|tag| tag.eq_ignore_ascii_case(wanted.trim()))
+    }) {
+        return false;
+    }
+    if let Some(status) = query.status {
+        if reading_status(context.activity.get(&book.id)) != status {
+            return false;
+        }
+    }
+    if let Some(wanted) = query.has_cached_audio {
+        if context.cached_audio.contains(&book.id) != wanted {
+            return false;
+        }
+    }
+    true
+}
+
+fn compare(a: &BookEntry, b: &BookEntry, context: &ShelfContext, key: SortKey) -> Ordering {
+    let primary = match key {
+        SortKey::Title => compare_text(Some(&a.title), Some(&b.title)),
+        SortKey::Author => compare_text(
+            author_sort_key(a.author.as_deref()).as_deref(),
+            author_sort_key(b.author.as_deref()).as_deref(),
+        ),
+        SortKey::LastRead => {
+            let last_read = |book: &BookEntry| {
+                context
+                    .activity
+                    .get(&book.id)
+                    .and_then(|activity| activity.last_read_at.clone())
+            };
+            last_read(a).cmp(&last_read(b))
+        }
+        SortKey::ImportedAt => a.imported_at.cmp(&b.imported_at),
+        SortKey::Progress => {
+            let progress = |book: &BookEntry| {
+                context
+                    .activity
+                    .get(&book.id)
+                    .map_or(0.0, |activity| activity.progress)
+            };
+            progress(a).total_cmp(&progress(b))
+        }
+    };
+    primary.then_with(|| compare_text(Some(&a.title), Some(&b.title)))
+}
+
+fn compare_text(a: Option<&str>, b: Option<&str>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// "J. R. R. Tolkien" sorts as "tolkien j. r. r."; names already written as
+/// "Last, First" are left alone.
+fn author_sort_key(author: Option<&str>) -> Option<String> {
+    let author = author?.trim();
+    if author.is_empty() {
+        return None;
+    }
+    if author.contains(',') {
+        return Some(author.to_lowercase());
+    }
+    let mut parts: Vec<&str> = author.split_whitespace().collect();
+    let last = parts.pop()?;
+    Some(format!("{} {}", last, parts.join(" ")).trim().to_lowercase())
+}
+
+fn contains_ignore_case(value: Option<&str>, needle: &str) -> bool {
+    value.is_some_and(|value| value.to_lowercase().contains(&needle.trim().to_lowercase()))
+}
+
+/// "en" matches "en-US" and "en_GB" as well as an exact tag.
+fn language_matches(value: Option<&str>, wanted: &str) -> bool {
+    let Some(value) = value else {
+        return false;
+    };
+    let value = value.to_lowercase().replace('_', "-");
+    let wanted = wanted.trim().to_lowercase().replace('_', "-");
+    value == wanted || value.starts_with(&format!("{wanted}-"))
+}
+
+fn new_id(prefix: &str, name: &str) -> String {
+    let slug: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    format!(
+        "{}-{}-{}",
+        prefix,
+        chrono::Utc::now().timestamp_millis(),
+        slug
+    )
+}
@@ -0,0 +1,16 @@
+use crate::storage;
+use std::path::{Path, PathBuf};
+
+/// Original EPUB files are kept by content hash so that backups can carry
+/// them and the same file imported twice is stored once.
+pub fn path_for(dir: &Path, content_hash: &str) -> PathBuf {
+    dir.join(format!("{}.epub", content_hash))
+}
+
+pub fn store(dir: &Path, content_hash: &str, bytes: &[u8]) -> Result<PathBuf, String> {
+    let path = path_for(dir, content_hash);
+    if !path.exists() {
+        storage::write_atomic(&path, bytes)?;
+    }
+    Ok(path)
+}
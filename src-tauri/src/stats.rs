@@ -0,0 +1,437 @@
+use crate::models::{
+    ActivityTotals, BookActivity, BookEntry, BookStats, DatedTotals, NowPlaying, ProviderUsage,
+    Session, SessionKind, StatsOverview,
+};
+use crate::storage;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// Narration reports every chunk, so a short gap already means playback
+/// stopped without the frontend saying so.
+const LISTENING_IDLE_SECONDS: i64 = 120;
+/// Readers report page turns; a page can take a while.
+const READING_IDLE_SECONDS: i64 = 600;
+/// Faster "reading" is a jump through the book rather than reading.
+const MAX_READING_WPM: f64 = 1000.0;
+/// Below this much history a book's own speed is too noisy for estimates.
+const MIN_ESTIMATE_SECONDS: f64 = 300.0;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct StatsStore {
+    #[serde(default)]
+    pub sessions: Vec<Session>,
+}
+
+/// Sessions still receiving events, at most one per kind. Finished and open
+/// sessions alike are saved to the store after every event.
+#[derive(Default)]
+pub struct StatsState(Mutex<HashMap<SessionKind, OpenSession>>);
+
+struct OpenSession {
+    id: String,
+    book_id: String,
+    last_event: DateTime<Utc>,
+    last_progress: Option<f64>,
+}
+
+/// Records that a narration chunk started playing, or that narration
+/// stopped when `now_playing` is `None`.
+pub fn record_listening(
+    state: &StatsState,
+    path: &Path,
+    now_playing: Option<&NowPlaying>,
+) -> Result<(), String> {
+    let mut open = lock(state)?;
+    let mut store: StatsStore = storage::read_json(path)?;
+    let now = Utc::now();
+    let Some(now_playing) = now_playing else {
+        if let Some(session) = open.remove(&SessionKind::Listening) {
+            if let Some(saved) = find_session(&mut store, &session.id) {
+                saved.seconds += active_seconds(&session, now, LISTENING_IDLE_SECONDS);
+                saved.ended_at = now.to_rfc3339();
+            }
+        }
+        return storage::write_json(path, &store);
+    };
+
+    let saved = continue_session(
+        &mut open,
+        &mut store,
+        SessionKind::Listening,
+        &now_playing.book_id,
+        now,
+        LISTENING_IDLE_SECONDS,
+    );
+    saved.sentences += now_playing.sentence_count.unwrap_or(1);
+    saved.words += now_playing
+        .word_count
+        .unwrap_or_else(|| now_playing.sentence_text.split_whitespace().count());
+    saved.characters += now_playing
+        .character_count
+        .unwrap_or_else(|| now_playing.sentence_text.chars().count());
+    if now_playing.provider.is_some() {
+        saved.provider = now_playing.provider.clone();
+    }
+    storage::write_json(path, &store)
+}
+
+/// Records a reading position save. Positions saved while the same book is
+/// being narrated count as listening, not reading.
+pub fn record_reading(
+    state: &StatsState,
+    path: &Path,
+    book: &BookEntry,
+    progress: f64,
+) -> Result<(), String> {
+    let mut open = lock(state)?;
+    if open
+        .get(&SessionKind::Listening)
+        .is_some_and(|session| session.book_id == book.id)
+    {
+        return Ok(());
+    }
+    let mut store: StatsStore = storage::read_json(path)?;
+    let now = Utc::now();
+    let last_progress = open
+        .get(&SessionKind::Reading)
+        .filter(|session| session.book_id == book.id)
+        .and_then(|session| session.last_progress);
+
+    let saved = continue_session(
+        &mut open,
+        &mut store,
+        SessionKind::Reading,
+        &book.id,
+        now,
+        READING_IDLE_SECONDS,
+    );
+    let elapsed = saved.seconds;
+    if let Some(last_progress) = last_progress.filter(|last| progress > *last) {
+        let words = ((progress - last_progress) * total_words(book) as f64).round();
+        let minutes = elapsed / 60.0;
+        if minutes > 0.0 && (saved.words as f64 + words) / minutes <= MAX_READING_WPM {
+            saved.words += words as usize;
+        }
+    }
+    if let Some(session) = open.get_mut(&SessionKind::Reading) {
+        session.last_progress = Some(progress);
+    }
+    storage::write_json(path, &store)
+}
+
+/// Extends the open session of `kind` when it is for the same book and
+/// recent enough, otherwise starts a new one, and returns its stored record.
+fn continue_session<'a>(
+    open: &mut HashMap<SessionKind, OpenSession>,
+    store: &'a mut StatsStore,
+    kind: SessionKind,
+    book_id: &str,
+    now: DateTime<Utc>,
+    idle_seconds: i64,
+) -> &'a mut Session {
+    let continuing = open.get(&kind).filter(|session| {
+        session.book_id == book_id
+            && (now - session.last_event).num_seconds() <= idle_seconds
+            && store.sessions.iter().any(|saved| saved.id == session.id)
+    });
+    let (id, seconds) = match continuing {
+        Some(session) => (
+            session.id.clone(),
+            active_seconds(session, now, idle_seconds),
+        ),
+        None => {
+            let mut millis = now.timestamp_millis();
+            while find_session(store, &format!("session-{}", millis)).is_some() {
+                millis += 1;
+            }
+            let id = format!("session-{}", millis);
+            store.sessions.push(Session {
+                id: id.clone(),
+                book_id: book_id.to_string(),
+                kind,
+                started_at: now.to_rfc3339(),
+                ended_at: now.to_rfc3339(),
+                seconds: 0.0,
+                words: 0,
+                sentences: 0,
+                characters: 0,
+                provider: None,
+            });
+            (id, 0.0)
+        }
+    };
+    let last_progress = open
+        .get(&kind)
+        .filter(|session| session.id == id)
+        .and_then(|session| session.last_progress);
+    open.insert(
+        kind,
+        OpenSession {
+            id: id.clone(),
+            book_id: book_id.to_string(),
+            last_event: now,
+            last_progress,
+        },
+    );
+
+    let saved = find_session(store, &id).expect("session was just stored");
+    saved.seconds += seconds;
+    saved.ended_at = now.to_rfc3339();
+    saved
+}
+
+fn active_seconds(session: &OpenSession, now: DateTime<Utc>, idle_seconds: i64) -> f64 {
+    let elapsed = (now - session.last_event).num_milliseconds() as f64 / 1000.0;
+    elapsed.clamp(0.0, idle_seconds as f64)
+}
+
+fn find_session<'a>(store: &'a mut StatsStore, id: &str) -> Option<&'a mut Session> {
+    store.sessions.iter_mut().find(|session| session.id == id)
+}
+
+fn lock(state: &StatsState) -> Result<MutexGuard<'_, HashMap<SessionKind, OpenSession>>, String> {
+    state
+        .0
+        .lock()
+        .map_err(|_| "Statistics lock poisoned".to_string())
+}
+
+pub fn overview(store: &StatsStore, days: u32) -> StatsOverview {
+    let today = Local::now().date_naive();
+    let by_day = totals_by(store, |date| date);
+    let first_day = today - Duration::days(i64::from(days.max(1)) - 1);
+    let days = first_day
+        .iter_days()
+        .take_while(|date| *date <= today)
+        .map(|date| DatedTotals {
+            date: date.to_string(),
+            totals: by_day.get(&date).cloned().unwrap_or_default(),
+        })
+        .collect();
+
+    let active: Vec<NaiveDate> = by_day.keys().copied().collect();
+    let (current_streak, longest_streak) = streaks(&active, today);
+    let totals = sum(store.sessions.iter());
+
+    let mut providers: BTreeMap<String, ProviderUsage> = BTreeMap::new();
+    for session in &store.sessions {
+        let Some(provider) = &session.provider else {
+            continue;
+        };
+        let usage = providers
+            .entry(provider.clone())
+            .or_insert_with(|| ProviderUsage {
+                provider: provider.clone(),
+                sessions: 0,
+                seconds: 0.0,
+                characters: 0,
+            });
+        usage.sessions += 1;
+        usage.seconds += session.seconds;
+        usage.characters += session.characters;
+    }
+
+    StatsOverview {
+        listening_wpm: wpm(totals.words_listened, totals.listening_seconds),
+        reading_wpm: wpm(totals.words_read, totals.reading_seconds),
+        totals,
+        days,
+        current_streak,
+        longest_streak,
+        providers: providers.into_values().collect(),
+    }
+}
+
+/// Totals per week, most recent last, with weeks starting on Monday.
+pub fn weekly(store: &StatsStore, weeks: u32) -> Vec<DatedTotals> {
+    let week_start =
+        |date: NaiveDate| date - Duration::days(i64::from(date.weekday().num_days_from_monday()));
+    let by_week = totals_by(store, week_start);
+    let this_week = week_start(Local::now().date_naive());
+    (0..i64::from(weeks.max(1)))
+        .rev()
+        .map(|ago| this_week - Duration::weeks(ago))
+        .map(|date| DatedTotals {
+            date: date.to_string(),
+            totals: by_week.get(&date).cloned().unwrap_or_default(),
+        })
+        .collect()
+}
+
+pub fn book_stats(
+    store: &StatsStore,
+    book: &BookEntry,
+    activity: Option<&BookActivity>,
+) -> BookStats {
+    let sessions: Vec<&Session> = store
+        .sessions
+        .iter()
+        .filter(|session| session.book_id == book.id)
+        .collect();
+    let totals = sum(sessions.iter().copied());
+    let overall = sum(store.sessions.iter());
+    let progress = activity.map_or(0.0, |activity| activity.progress.clamp(0.0, 1.0));
+    let remaining_words = (total_words(book) as f64 * (1.0 - progress)).round() as usize;
+
+    let speed = |words: usize, seconds: f64, all_words: usize, all_seconds: f64| {
+        if seconds >= MIN_ESTIMATE_SECONDS {
+            wpm(words, seconds)
+        } else {
+            wpm(all_words, all_seconds)
+        }
+    };
+    let estimate = |wpm: Option<f64>| wpm.map(|wpm| remaining_words as f64 / wpm * 60.0);
+    let listening_wpm = speed(
+        totals.words_listened,
+        totals.listening_seconds,
+        overall.words_listened,
+        overall.listening_seconds,
+    );
+    let reading_wpm = speed(
+        totals.words_read,
+        totals.reading_seconds,
+        overall.words_read,
+        overall.reading_seconds,
+    );
+
+    BookStats {
+        book_id: book.id.clone(),
+        title: book.title.clone(),
+        last_session_at: sessions
+            .iter()
+            .map(|session| session.ended_at.clone())
+            .max(),
+        totals,
+        progress,
+        remaining_words,
+        estimated_listening_seconds: estimate(listening_wpm),
+        estimated_reading_seconds: estimate(reading_wpm),
+    }
+}
+
+/// Books with the most time spent on them, optionally only counting the
+/// last `days` days.
+pub fn top_books(
+    store: &StatsStore,
+    books: &[BookEntry],
+    activity: &HashMap<String, BookActivity>,
+    limit: usize,
+    days: Option<u32>,
+) -> Vec<BookStats> {
+    let since = days.map(|days| Local::now().date_naive() - Duration::days(i64::from(days)));
+    let mut seconds: HashMap<&str, f64> = HashMap::new();
+    for session in &store.sessions {
+        if since
+            .is_some_and(|since| local_date(&session.started_at).is_none_or(|date| date <= since))
+        {
+            continue;
+        }
+        *seconds.entry(session.book_id.as_str()).or_default() += session.seconds;
+    }
+    let mut ranked: Vec<(&BookEntry, f64)> = books
+        .iter()
+        .filter_map(|book| {
+            seconds
+                .get(book.id.as_str())
+                .map(|seconds| (book, *seconds))
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+        .into_iter()
+        .take(limit)
+        .map(|(book, _)| book_stats(store, book, activity.get(&book.id)))
+        .collect()
+}
+
+/// Most recent sessions first.
+pub fn sessions(store: &StatsStore, book_id: Option<&str>, limit: usize) -> Vec<Session> {
+    let mut sessions: Vec<Session> = store
+        .sessions
+        .iter()
+        .filter(|session| book_id.is_none_or(|id| session.book_id == id))
+        .cloned()
+        .collect();
+    sessions.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+    sessions.truncate(limit);
+    sessions
+}
+
+/// Sessions are counted on the local day they started.
+fn totals_by(
+    store: &StatsStore,
+    bucket: impl Fn(NaiveDate) -> NaiveDate,
+) -> HashMap<NaiveDate, ActivityTotals> {
+    let mut grouped: HashMap<NaiveDate, Vec<&Session>> = HashMap::new();
+    for session in &store.sessions {
+        if let Some(date) = local_date(&session.started_at) {
+            grouped.entry(bucket(date)).or_default().push(session);
+        }
+    }
+    grouped
+        .into_iter()
+        .map(|(date, sessions)| (date, sum(sessions.into_iter())))
+        .collect()
+}
+
+fn sum<'a>(sessions: impl Iterator<Item = &'a Session>) -> ActivityTotals {
+    let mut totals = ActivityTotals::default();
+    for session in sessions {
+        totals.sessions += 1;
+        match session.kind {
+            SessionKind::Listening => {
+                totals.listening_seconds += session.seconds;
+                totals.words_listened += session.words;
+                totals.sentences_narrated += session.sentences;
+                totals.characters_narrated += session.characters;
+            }
+            SessionKind::Reading => {
+                totals.reading_seconds += session.seconds;
+                totals.words_read += session.words;
+            }
+        }
+    }
+    totals
+}
+
+fn streaks(active: &[NaiveDate], today: NaiveDate) -> (usize, usize) {
+    let days: HashSet<NaiveDate> = active.iter().copied().collect();
+    let mut longest = 0;
+    for day in &days {
+        if days.contains(&(*day - Duration::days(1))) {
+            continue;
+        }
+        let length = day
+            .iter_days()
+            .take_while(|date| days.contains(date))
+            .count();
+        longest = longest.max(length);
+    }
+    let end = if days.contains(&today) {
+        today
+    } else {
+        today - Duration::days(1)
+    };
+    let current = (0..)
+        .map(|ago| end - Duration::days(ago))
+        .take_while(|date| days.contains(date))
+        .count();
+    (current, longest)
+}
+
+fn wpm(words: usize, seconds: f64) -> Option<f64> {
+    (seconds > 0.0 && words > 0).then(|| words as f64 / (seconds / 60.0))
+}
+
+fn total_words(book: &BookEntry) -> usize {
+    book.chapters.iter().map(|chapter| chapter.word_count).sum()
+}
+
+fn local_date(timestamp: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|time| time.with_timezone(&Local).date_naive())
+}
@@ -0,0 +1,126 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Current time in the same ISO 8601 form the frontend uses for `importedAt`.
+pub fn now_iso() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Reads a JSON store, returning the default value when it does not exist yet.
+pub fn read_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let json = fs::read(path)
+        .map_err(|error| format!("Failed to read {}: {error}", path.display()))?;
+    serde_json::from_slice(&json)
+        .map_err(|error| format!("Failed to parse {}: {error}", path.display()))
+}
+
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let json = serde_json::to_vec(value)
+        .map_err(|error| format!("Failed to serialize {}: {error}", path.display()))?;
+    write_atomic(path, &json)
+}
+
+/// Writes `bytes` to `path` so that readers only ever observe the old or the
+/// new contents: the data goes to a sibling temp file, is fsynced, and is then
+/// renamed over the destination.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let tmp_path = sibling_path(path, "tmp");
+    {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)
+            .map_err(|error| format!("Failed to create {}: {error}", tmp_path.display()))?;
+        file.write_all(bytes)
+            .map_err(|error| format!("Failed to write {}: {error}", tmp_path.display()))?;
+        file.sync_all()
+            .map_err(|error| format!("Failed to sync {}: {error}", tmp_path.display()))?;
+    }
+    fs::rename(&tmp_path, path)
+        .map_err(|error| format!("Failed to replace {}: {error}", path.display()))?;
+    sync_parent_dir(path);
+    Ok(())
+}
+
+/// Keeps the last `keep` versions of `path` as `<name>.1` (newest) through
+/// `<name>.<keep>` (oldest). Call before overwriting `path`.
+pub fn rotate_backups(path: &Path, keep: usize) -> Result<(), String> {
+    if keep == 0 || !path.exists() {
+        return Ok(());
+    }
+    for index in (1..keep).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            let to = backup_path(path, index + 1);
+            fs::rename(&from, &to)
+                .map_err(|error| format!("Failed to rotate backup {}: {error}", from.display()))?;
+        }
+    }
+    let newest = backup_path(path, 1);
+    fs::copy(path, &newest)
+        .map_err(|error| format!("Failed to back up {}: {error}", path.display()))?;
+    Ok(())
+}
+
+/// Existing backups of `path`, newest first.
+pub fn existing_backups(path: &Path, keep: usize) -> Vec<PathBuf> {
+    (1..=keep)
+        .map(|index| backup_path(path, index))
+        .filter(|candidate| candidate.exists())
+        .collect()
+}
+
+pub fn backup_path(path: &Path, index: usize) -> PathBuf {
+    sibling_path(path, &index.to_string())
+}
+
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Every file below `dir`, recursively. A missing directory has no files.
+pub fn list_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    Ok(files)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    let entries = fs::read_dir(dir)
+        .map_err(|error| format!("Failed to read {}: {error}", dir.display()))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn sync_parent_dir(path: &Path) {
+    // Directory fsync makes the rename itself durable on POSIX filesystems.
+    // Windows cannot open directories this way, so failures are ignored.
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+}
@@ -1,285 +1,600 @@
+use crate::audio_cache::{self, AudioCacheState};
+use crate::cancellation::Cancellation;
 use crate::config;
-use crate::models::{AudioClip, ExternalTtsConfig, TtsRequest, VoiceMode};
+use crate::lexicon;
+use crate::library;
+use crate::models::{
+    AudioClip, AudioStreamEvent, BookEntry, ClonedVoice, LexiconEntry, NormalizationSettings,
+    ProviderVoice, TtsCapabilities, TtsProviderInfo, TtsRequest, VoiceCloneRequest,
+};
+use crate::normalize;
+use crate::prosody::{self, Markup};
+use async_trait::async_trait;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
-use hex::FromHex;
-use reqwest::header::CONTENT_TYPE;
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct ExternalTtsPayload<'a> {
-    text: &'a str,
-    voice_id: Option<&'a str>,
-    format: Option<&'a str>,
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Manager};
+
+/// The frontend's built-in voice, spoken by the webview rather than a provider.
+const LOCAL_PLAYBACK: &str = "neutral";
+
+/// Audio returned by a provider, before it is encoded for the frontend.
+pub struct SynthesizedAudio {
+    pub bytes: Vec<u8>,
+    pub mime: String,
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct ExternalTtsResponse {
-    audio_base64: String,
-    mime: Option<String>,
+/// What a provider is asked to speak: normalized text with the pauses and
+/// emphasis of the chapter's structure, plus the lexicon entries that occur
+/// in it. Each provider renders these in whatever form it supports.
+pub struct SpeechInput {
+    pub parts: Vec<SpeechPart>,
+    pub pronunciations: Vec<LexiconEntry>,
 }
 
-pub async fn synthesize(request: TtsRequest) -> Result<AudioClip, String> {
-    match request.voice_mode {
-        VoiceMode::Neutral => Err(
-            "Neutral voice uses local playback. Use the Play button for speech synthesis."
-                .to_string(),
-        ),
-        VoiceMode::External => synthesize_external(request).await,
-        VoiceMode::Minimax => synthesize_minimax(request).await,
-        VoiceMode::Elevenlabs => synthesize_elevenlabs(request).await,
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpeechPart {
+    Text {
+        text: String,
+        emphasis: bool,
+    },
+    /// A pause in milliseconds, e.g. after a heading or paragraph.
+    Pause(u32),
 }
 
-async fn synthesize_external(request: TtsRequest) -> Result<AudioClip, String> {
-    let external = request
-        .external
-        .ok_or_else(|| "External voice configuration missing.".to_string())?;
-    let url = build_endpoint(&external);
-    let payload = ExternalTtsPayload {
-        text: &request.text,
-        voice_id: external.voice_id.as_deref(),
-        format: external.output_format.as_deref(),
-    };
+impl SpeechInput {
+    /// The text without markup, with pauses as line breaks.
+    pub fn text(&self) -> String {
+        prosody::render(self, Markup::Plain, None)
+    }
+}
 
-    let client = reqwest::Client::new();
-    let mut req = client.post(url).json(&payload);
-    if let Some(key) = config::external_api_key().as_ref().filter(|value| !value.is_empty()) {
-        req = req.bearer_auth(key);
+/// Receives audio from a streaming synthesis as it arrives.
+pub type AudioSink<'a> = dyn Fn(SynthesizedAudio) -> Result<(), String> + Send + Sync + 'a;
+
+/// A speech synthesis engine. Each provider declares the options it reads
+/// from a request, and only implements voice listing or cloning if its
+/// capabilities say it supports them.
+#[async_trait]
+pub trait TtsProvider: Send + Sync {
+    type Options: DeserializeOwned + Send;
+
+    /// The id requests use to pick this provider, e.g. `"minimax"`.
+    fn id(&self) -> &'static str;
+
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> TtsCapabilities;
+
+    async fn synthesize(
+        &self,
+        input: &SpeechInput,
+        options: Self::Options,
+    ) -> Result<SynthesizedAudio, String>;
+
+    /// Sends audio to `sink` as the provider produces it, so playback can
+    /// start before the whole clip is ready. Providers without a streaming
+    /// API send the finished clip once.
+    async fn synthesize_stream(
+        &self,
+        input: &SpeechInput,
+        options: Self::Options,
+        sink: &AudioSink<'_>,
+    ) -> Result<(), String> {
+        sink(self.synthesize(input, options).await?)
     }
 
-    let response = req
-        .send()
-        .await
-        .map_err(|error| format!("TTS request failed: {error}"))?;
-
-    let status = response.status();
-    let content_type = response
-        .headers()
-        .get(CONTENT_TYPE)
-        .and_then(|value| value.to_str().ok())
-        .unwrap_or("")
-        .to_string();
-
-    if !status.is_success() {
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!(
-            "TTS service returned {}: {}",
-            status,
-            body
-        ));
+    async fn list_voices(&self, _options: Self::Options) -> Result<Vec<ProviderVoice>, String> {
+        Err(format!("{} does not list voices.", self.name()))
     }
 
-    if content_type.starts_with("audio/") {
-        let bytes = response
-            .bytes()
-            .await
-            .map_err(|error| format!("Failed reading audio response: {error}"))?;
-        return Ok(AudioClip {
-            chapter_id: request.chapter_id,
-            audio_base64: STANDARD.encode(&bytes),
-            mime: content_type,
-        });
+    async fn clone_voice(&self, _request: VoiceCloneRequest) -> Result<ClonedVoice, String> {
+        Err(format!("{} does not support voice cloning.", self.name()))
     }
+}
 
-    let body: ExternalTtsResponse = response
-        .json()
-        .await
-        .map_err(|error| format!("Failed parsing TTS JSON response: {error}"))?;
+/// `TtsProvider` with its options still as JSON, so providers with different
+/// option types can share the registry.
+#[async_trait]
+trait RegisteredProvider: Send + Sync {
+    fn info(&self) -> TtsProviderInfo;
 
-    Ok(AudioClip {
-        chapter_id: request.chapter_id,
-        audio_base64: body.audio_base64,
-        mime: body.mime.unwrap_or_else(|| "audio/mpeg".to_string()),
-    })
+    async fn synthesize(
+        &self,
+        input: &SpeechInput,
+        options: Value,
+    ) -> Result<SynthesizedAudio, String>;
+
+    async fn synthesize_stream(
+        &self,
+        input: &SpeechInput,
+        options: Value,
+        sink: &AudioSink<'_>,
+    ) -> Result<(), String>;
+
+    async fn list_voices(&self, options: Value) -> Result<Vec<ProviderVoice>, String>;
+
+    async fn clone_voice(&self, request: VoiceCloneRequest) -> Result<ClonedVoice, String>;
 }
 
-fn build_endpoint(config: &ExternalTtsConfig) -> String {
-    let base = config.api_base_url.trim_end_matches('/');
-    format!("{base}/synthesize")
+#[async_trait]
+impl<P: TtsProvider> RegisteredProvider for P {
+    fn info(&self) -> TtsProviderInfo {
+        TtsProviderInfo {
+            id: self.id().to_string(),
+            name: self.name().to_string(),
+            capabilities: self.capabilities(),
+        }
+    }
+
+    async fn synthesize(
+        &self,
+        input: &SpeechInput,
+        options: Value,
+    ) -> Result<SynthesizedAudio, String> {
+        let options = parse_options::<P>(self, options)?;
+        TtsProvider::synthesize(self, input, options).await
+    }
+
+    async fn synthesize_stream(
+        &self,
+        input: &SpeechInput,
+        options: Value,
+        sink: &AudioSink<'_>,
+    ) -> Result<(), String> {
+        let options = parse_options::<P>(self, options)?;
+        TtsProvider::synthesize_stream(self, input, options, sink).await
+    }
+
+    async fn list_voices(&self, options: Value) -> Result<Vec<ProviderVoice>, String> {
+        let options = parse_options::<P>(self, options)?;
+        TtsProvider::list_voices(self, options).await
+    }
+
+    async fn clone_voice(&self, request: VoiceCloneRequest) -> Result<ClonedVoice, String> {
+        TtsProvider::clone_voice(self, request).await
+    }
 }
 
-#[derive(Serialize)]
-struct MinimaxVoiceSetting<'a> {
-    voice_id: &'a str,
+fn parse_options<P: TtsProvider>(provider: &P, options: Value) -> Result<P::Options, String> {
+    // A request without options gets each provider's defaults.
+    let options = match options {
+        Value::Null => Value::Object(Default::default()),
+        options => options,
+    };
+    serde_json::from_value(options)
+        .map_err(|error| format!("Invalid {} options: {error}", provider.name()))
 }
 
-#[derive(Serialize)]
-struct MinimaxAudioSetting<'a> {
-    format: &'a str,
+/// The TTS providers available to the app, kept in Tauri state.
+#[derive(Default)]
+pub struct TtsRegistry {
+    providers: Vec<(&'static str, Arc<dyn RegisteredProvider>)>,
 }
 
-#[derive(Serialize)]
-struct MinimaxTtsPayload<'a> {
-    model: &'a str,
-    text: &'a str,
-    stream: bool,
-    voice_setting: MinimaxVoiceSetting<'a>,
-    audio_setting: MinimaxAudioSetting<'a>,
-    output_format: &'a str,
+impl TtsRegistry {
+    /// Adds `provider`, replacing any registered under the same id.
+    pub fn with<P: TtsProvider + 'static>(mut self, provider: P) -> Self {
+        let id = provider.id();
+        self.providers.retain(|(existing, _)| *existing != id);
+        self.providers.push((id, Arc::new(provider)));
+        self
+    }
+
+    fn provider(&self, id: &str) -> Result<Arc<dyn RegisteredProvider>, String> {
+        if id == LOCAL_PLAYBACK {
+            return Err(
+                "Neutral voice uses local playback. Use the Play button for speech synthesis."
+                    .to_string(),
+            );
+        }
+        self.providers
+            .iter()
+            .find(|(existing, _)| *existing == id)
+            .map(|(_, provider)| provider.clone())
+            .ok_or_else(|| format!("Unknown TTS provider: {}", id))
+    }
 }
 
-#[derive(Deserialize)]
-struct MinimaxTtsResponse {
-    data: Option<MinimaxTtsData>,
-    base_resp: Option<MinimaxBaseResp>,
+pub fn capabilities(registry: &TtsRegistry, provider: &str) -> Result<TtsCapabilities, String> {
+    Ok(registry.provider(provider)?.info().capabilities)
 }
 
-#[derive(Deserialize)]
-struct MinimaxTtsData {
-    audio: String,
-    status: Option<i64>,
+pub fn providers(registry: &TtsRegistry) -> Vec<TtsProviderInfo> {
+    registry
+        .providers
+        .iter()
+        .map(|(_, provider)| provider.info())
+        .collect()
 }
 
-#[derive(Deserialize)]
-struct MinimaxBaseResp {
-    status_code: i64,
-    status_msg: Option<String>,
+/// Synthesizes `request` as prepared by `speech_input`, answering from the
+/// audio cache when the same text was already spoken with the same provider
+/// options and lexicon.
+pub async fn synthesize(
+    app: &AppHandle,
+    registry: &TtsRegistry,
+    cache: &AudioCacheState,
+    request: TtsRequest,
+    cancellation: &Cancellation,
+) -> Result<AudioClip, String> {
+    let provider = registry.provider(&request.provider)?;
+    let book_id = request.book_id.as_deref();
+    let input = speech_input(app, &request);
+    let key = audio_cache::key(&request.provider, &request.options, &input);
+
+    let cached = audio_cache::get(app, cache, &key, book_id).unwrap_or_else(|error| {
+        println!("DEBUG WARNING: Failed to read audio cache: {}", error);
+        None
+    });
+    let audio = match cached {
+        Some(audio) => audio,
+        None => {
+            let synthesized = provider.synthesize(&input, request.options);
+            let audio = cancellation
+                .run(synthesized)
+                .await
+                .ok_or_else(|| "Synthesis cancelled".to_string())??;
+            let stored = audio_cache::put(app, cache, &key, &request.provider, book_id, &audio);
+            if let Err(error) = stored {
+                println!("DEBUG WARNING: Failed to cache audio: {}", error);
+            }
+            audio
+        }
+    };
+    Ok(AudioClip {
+        chapter_id: request.chapter_id,
+        audio_base64: STANDARD.encode(&audio.bytes),
+        mime: audio.mime,
+    })
 }
 
-async fn synthesize_minimax(request: TtsRequest) -> Result<AudioClip, String> {
-    let config = request
-        .minimax
-        .ok_or_else(|| "Minimax configuration missing.".to_string())?;
-    let api_key = config::minimax_api_key()
-        .filter(|value| !value.is_empty())
-        .ok_or_else(|| "Missing REBOOK_MINIMAX_API_KEY in environment.".to_string())?;
-    let voice_id = config
-        .voice_id
-        .as_ref()
-        .filter(|value| !value.is_empty())
-        .ok_or_else(|| "Minimax voice_id required.".to_string())?;
-    let model = config
-        .model
-        .as_deref()
-        .filter(|value| !value.is_empty())
-        .unwrap_or("speech-2.6-hd");
-    let format = config
-        .output_format
-        .as_deref()
-        .filter(|value| !value.is_empty())
-        .unwrap_or("mp3");
-
-    let payload = MinimaxTtsPayload {
-        model,
-        text: &request.text,
-        stream: false,
-        voice_setting: MinimaxVoiceSetting { voice_id },
-        audio_setting: MinimaxAudioSetting { format },
-        output_format: "hex",
+/// Synthesizes `request` like `synthesize`, sending audio over `channel` as
+/// the provider streams it. A cancelled stream ends with `Cancelled` rather
+/// than an error, and only finished streams are cached.
+pub async fn stream(
+    app: &AppHandle,
+    registry: &TtsRegistry,
+    cache: &AudioCacheState,
+    request: TtsRequest,
+    channel: &Channel<AudioStreamEvent>,
+    cancellation: &Cancellation,
+) -> Result<(), String> {
+    let provider = registry.provider(&request.provider)?;
+    let book_id = request.book_id.as_deref();
+    let input = speech_input(app, &request);
+    let key = audio_cache::key(&request.provider, &request.options, &input);
+    let send = |event: AudioStreamEvent| {
+        channel
+            .send(event)
+            .map_err(|error| format!("Failed to send audio: {}", error))
     };
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post("https://api.minimaxi.com/v1/t2a_v2")
-        .bearer_auth(api_key)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(|error| format!("Minimax TTS request failed: {error}"))?;
-
-    let status = response.status();
-    if !status.is_success() {
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!(
-            "Minimax TTS returned {}: {}",
-            status,
-            body
-        ));
+    let cached = audio_cache::get(app, cache, &key, book_id).unwrap_or_else(|error| {
+        println!("DEBUG WARNING: Failed to read audio cache: {}", error);
+        None
+    });
+    if let Some(audio) = cached {
+        send(AudioStreamEvent::Chunk {
+            audio_base64: STANDARD.encode(&audio.bytes),
+            mime: audio.mime,
+        })?;
+        return send(AudioStreamEvent::Finished { cached: true });
+    }
+
+    let received = Mutex::new(SynthesizedAudio {
+        bytes: Vec::new(),
+        mime: String::new(),
+    });
+    let sink = |audio: SynthesizedAudio| {
+        if let Ok(mut received) = received.lock() {
+            received.bytes.extend_from_slice(&audio.bytes);
+            received.mime.clone_from(&audio.mime);
+        }
+        send(AudioStreamEvent::Chunk {
+            audio_base64: STANDARD.encode(&audio.bytes),
+            mime: audio.mime,
+        })
+    };
+    let streamed = provider.synthesize_stream(&input, request.options, &sink);
+    match cancellation.run(streamed).await {
+        Some(result) => result?,
+        None => return send(AudioStreamEvent::Cancelled),
     }
 
-    let body: MinimaxTtsResponse = response
-        .json()
-        .await
-        .map_err(|error| format!("Minimax TTS response parse failed: {error}"))?;
-    if let Some(base_resp) = body.base_resp {
-        if base_resp.status_code != 0 {
-            let msg = base_resp
-                .status_msg
-                .unwrap_or_else(|| "Minimax TTS failed".to_string());
-            return Err(msg);
+    let audio = received
+        .into_inner()
+        .map_err(|_| "Streamed audio lock poisoned".to_string())?;
+    if !audio.bytes.is_empty() {
+        let stored = audio_cache::put(app, cache, &key, &request.provider, book_id, &audio);
+        if let Err(error) = stored {
+            println!("DEBUG WARNING: Failed to cache audio: {}", error);
         }
     }
+    send(AudioStreamEvent::Finished { cached: false })
+}
 
-    let audio_hex = body
-        .data
-        .ok_or_else(|| "Minimax TTS response missing data.".to_string())?
-        .audio;
-    let bytes = Vec::from_hex(audio_hex)
-        .map_err(|error| format!("Invalid audio hex: {error}"))?;
-    let mime = minimax_mime(format);
+/// What the provider is asked to speak for `request`: the text, or the
+/// chapter's own text and structure for a narration plan's chunk, normalized
+/// and with the reader's and publisher's pronunciations that occur in it.
+fn speech_input(app: &AppHandle, request: &TtsRequest) -> SpeechInput {
+    let book_id = request.book_id.as_deref();
+    let settings = normalize::load_settings(app, book_id).unwrap_or_else(|error| {
+        println!(
+            "DEBUG WARNING: Failed to load normalization settings: {}",
+            error
+        );
+        NormalizationSettings::default()
+    });
+    let book = match book_id {
+        Some(book_id) => find_book(app, book_id).unwrap_or_else(|error| {
+            println!("DEBUG WARNING: Failed to load book {}: {}", book_id, error);
+            None
+        }),
+        None => None,
+    };
+    compose(request, book.as_deref(), &settings, |publisher| {
+        lexicon::for_book(app, book_id, publisher).unwrap_or_else(|error| {
+            println!(
+                "DEBUG WARNING: Failed to load pronunciation lexicon: {}",
+                error
+            );
+            Vec::new()
+        })
+    })
+}
+
+/// Builds the speech input for `request` from its book, if it has one.
+/// `effective` layers the reader's pronunciations over the publisher's.
+fn compose(
+    request: &TtsRequest,
+    book: Option<&BookEntry>,
+    settings: &NormalizationSettings,
+    effective: impl FnOnce(Vec<LexiconEntry>) -> Vec<LexiconEntry>,
+) -> SpeechInput {
+    let chapter = book.and_then(|book| {
+        book.chapters
+            .iter()
+            .find(|chapter| chapter.id == request.chapter_id)
+    });
+
+    // Text from a narration plan is read from the chapter itself, so its
+    // headings and paragraphs can be paced.
+    let parts = match (chapter, request.start, request.end) {
+        (Some(chapter), Some(start), Some(end)) => prosody::parts(chapter, start, end),
+        _ => vec![SpeechPart::Text {
+            text: request.text.clone(),
+            emphasis: false,
+        }],
+    };
+    let parts = parts
+        .into_iter()
+        .filter_map(|part| match part {
+            SpeechPart::Text { text, emphasis } => {
+                let text = normalize::normalize(&text, request.language.as_deref(), settings);
+                (!text.is_empty()).then_some(SpeechPart::Text { text, emphasis })
+            }
+            pause => Some(pause),
+        })
+        .collect();
+
+    let publisher = book
+        .map(|book| lexicon::from_publisher(book, &request.chapter_id, request.start, request.end))
+        .unwrap_or_default();
+    let lexicon = effective(publisher);
+    let mut input = SpeechInput {
+        parts,
+        pronunciations: Vec::new(),
+    };
+    input.pronunciations = lexicon::matching(&input.text(), &lexicon);
+    input
+}
 
+/// Speaks a single word with one lexicon entry, so it can be tried before
+/// saving. Auditions skip normalization and the audio cache.
+pub async fn audition(
+    registry: &TtsRegistry,
+    provider: &str,
+    options: Value,
+    entry: LexiconEntry,
+) -> Result<AudioClip, String> {
+    let input = SpeechInput {
+        parts: vec![SpeechPart::Text {
+            text: entry.grapheme.clone(),
+            emphasis: false,
+        }],
+        pronunciations: vec![entry],
+    };
+    let audio = registry
+        .provider(provider)?
+        .synthesize(&input, options)
+        .await?;
     Ok(AudioClip {
-        chapter_id: request.chapter_id,
-        audio_base64: STANDARD.encode(&bytes),
-        mime,
+        chapter_id: "pronunciation-audition".to_string(),
+        audio_base64: STANDARD.encode(&audio.bytes),
+        mime: audio.mime,
     })
 }
 
-fn minimax_mime(format: &str) -> String {
-    match format {
-        "wav" => "audio/wav",
-        "flac" => "audio/flac",
-        "pcm" => "audio/pcm",
-        _ => "audio/mpeg",
+/// The book last narrated, kept until `library.json` changes so that the
+/// chunks of a narration plan don't each load the whole library.
+#[derive(Default)]
+pub struct NarratedBookState(Mutex<Option<NarratedBook>>);
+
+struct NarratedBook {
+    library_modified: SystemTime,
+    book: Arc<BookEntry>,
+}
+
+/// The book with `book_id`, from the cache while `library.json` is unchanged.
+pub fn find_book(app: &AppHandle, book_id: &str) -> Result<Option<Arc<BookEntry>>, String> {
+    let path = config::get_library_path(app)?;
+    let modified = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let state = app.state::<NarratedBookState>();
+    let mut cached = state
+        .0
+        .lock()
+        .map_err(|_| "Narrated book lock poisoned".to_string())?;
+    if let Some(narrated) = cached.as_ref().filter(|narrated| {
+        narrated.book.id == book_id && Some(narrated.library_modified) == modified
+    }) {
+        return Ok(Some(narrated.book.clone()));
     }
-    .to_string()
+    let book = library::load(&path)?
+        .books
+        .into_iter()
+        .find(|entry| entry.id == book_id)
+        .map(Arc::new);
+    *cached = match (&book, modified) {
+        (Some(book), Some(library_modified)) => Some(NarratedBook {
+            library_modified,
+            book: book.clone(),
+        }),
+        _ => None,
+    };
+    Ok(book)
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct ElevenLabsTtsPayload<'a> {
-    text: &'a str,
-    model_id: &'a str,
+pub async fn list_voices(
+    registry: &TtsRegistry,
+    provider: &str,
+    options: Value,
+) -> Result<Vec<ProviderVoice>, String> {
+    registry.provider(provider)?.list_voices(options).await
 }
 
-async fn synthesize_elevenlabs(request: TtsRequest) -> Result<AudioClip, String> {
-    let config = request
-        .elevenlabs
-        .ok_or_else(|| "ElevenLabs configuration missing.".to_string())?;
-    let api_key = config::elevenlabs_api_key()
-        .filter(|value| !value.is_empty())
-        .ok_or_else(|| "Missing REBOOK_ELEVENLABS_API_KEY in environment.".to_string())?;
-    let voice_id = config
-        .voice_id
-        .as_ref()
-        .filter(|value| !value.is_empty())
-        .ok_or_else(|| "ElevenLabs voice_id required.".to_string())?;
-    let model_id = config
-        .model
-        .as_deref()
-        .filter(|value| !value.is_empty())
-        .unwrap_or("eleven_multilingual_v2");
-
-    let payload = ElevenLabsTtsPayload {
-        text: &request.text,
-        model_id,
-    };
-    let url = format!("https://api.elevenlabs.io/v1/text-to-speech/{voice_id}");
-    let response = reqwest::Client::new()
-        .post(url)
-        .header("xi-api-key", api_key)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(|error| format!("ElevenLabs TTS request failed: {error}"))?;
-
-    let status = response.status();
-    if !status.is_success() {
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!("ElevenLabs TTS returned {}: {}", status, body));
+pub async fn clone_voice(
+    registry: &TtsRegistry,
+    provider: &str,
+    request: VoiceCloneRequest,
+) -> Result<ClonedVoice, String> {
+    registry.provider(provider)?.clone_voice(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Chapter, PronunciationHint};
+
+    const TEXT: &str = "# Chapter One\n\nHermione looked up.\n\nHermione smiled.\n";
+
+    fn book() -> BookEntry {
+        let chapter = Chapter {
+            id: "chapter-1".to_string(),
+            title: "Chapter One".to_string(),
+            text: TEXT.to_string(),
+            html: Some(
+                "<html><body><h1>Chapter One</h1><p>Hermione looked up.</p>\
+                 <p>Hermione smiled.</p></body></html>"
+                    .to_string(),
+            ),
+            source_href: None,
+            word_count: 7,
+            content_hash: None,
+            // Only the first "Hermione" is marked.
+            pronunciations: vec![PronunciationHint {
+                start: 15,
+                end: 23,
+                grapheme: "Hermione".to_string(),
+                ipa: "hɝˈmaɪ.ə.ni".to_string(),
+            }],
+        };
+        BookEntry {
+            id: "book-1".to_string(),
+            title: "Book".to_string(),
+            author: None,
+            author_sort: None,
+            cover_base64: None,
+            cover_mime: None,
+            chapters: vec![chapter],
+            imported_at: String::new(),
+            language: Some("en".to_string()),
+            series: None,
+            series_index: None,
+            tags: Vec::new(),
+            content_hash: None,
+            identifier: None,
+            description: None,
+            lexicon: Vec::new(),
+            imported: None,
+        }
     }
 
-    let bytes = response
-        .bytes()
-        .await
-        .map_err(|error| format!("ElevenLabs audio read failed: {error}"))?;
+    fn request(chapter_id: &str, range: Option<(usize, usize)>) -> TtsRequest {
+        let text: String = match range {
+            Some((start, end)) => TEXT.chars().skip(start).take(end - start).collect(),
+            None => "Hermione looked up.".to_string(),
+        };
+        TtsRequest {
+            chapter_id: chapter_id.to_string(),
+            text,
+            provider: "minimax".to_string(),
+            options: Value::Null,
+            book_id: Some("book-1".to_string()),
+            language: Some("en".to_string()),
+            start: range.map(|(start, _)| start),
+            end: range.map(|(_, end)| end),
+        }
+    }
 
-    Ok(AudioClip {
-        chapter_id: request.chapter_id,
-        audio_base64: STANDARD.encode(&bytes),
-        mime: "audio/mpeg".to_string(),
-    })
+    fn input(request: &TtsRequest) -> SpeechInput {
+        compose(
+            request,
+            Some(&book()),
+            &NormalizationSettings::default(),
+            |publisher| publisher,
+        )
+    }
+
+    #[test]
+    fn plan_chunks_pause_after_headings() {
+        let input = input(&request("chapter-1", Some((0, TEXT.chars().count()))));
+        assert_eq!(
+            input.parts[..2],
+            [
+                SpeechPart::Text {
+                    text: "Chapter One.".to_string(),
+                    emphasis: false,
+                },
+                SpeechPart::Pause(1200),
+            ]
+        );
+        assert!(input.parts[2..].contains(&SpeechPart::Pause(600)));
+    }
+
+    #[test]
+    fn text_without_a_range_is_read_as_is() {
+        let input = input(&request("chapter-1", None));
+        assert_eq!(
+            input.parts,
+            [SpeechPart::Text {
+                text: "Hermione looked up.".to_string(),
+                emphasis: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn inline_hints_apply_within_the_chunk() {
+        let marked = input(&request("chapter-1", Some((15, 34))));
+        assert_eq!(marked.pronunciations.len(), 1);
+        assert_eq!(marked.pronunciations[0].id, "chapter-1:15-23");
+        assert_eq!(marked.pronunciations[0].ipa.as_deref(), Some("hɝˈmaɪ.ə.ni"));
+
+        let unmarked = input(&request("chapter-1", Some((36, 52))));
+        assert!(unmarked.pronunciations.is_empty());
+    }
+
+    #[test]
+    fn inline_hints_need_the_chapter_id() {
+        let input = input(&request("chunk-0", Some((15, 34))));
+        assert!(input.pronunciations.is_empty());
+    }
 }
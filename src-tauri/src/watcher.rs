@@ -0,0 +1,337 @@
+use crate::config;
+use crate::importer::{self, ImportOutcome};
+use crate::models::{BookEntry, ImportFailure, LibraryChange, LibraryChangeKind, WatchedFolder};
+use crate::storage;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+
+pub const LIBRARY_CHANGED_EVENT: &str = "library-changed";
+pub const IMPORT_FAILED_EVENT: &str = "library-import-failed";
+
+/// Synced folders often write a file in several passes, so a path is only
+/// imported once it has had no events for this long.
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The active folder watcher. Replacing it drops the previous watcher, which
+/// also ends the worker thread fed by its events.
+#[derive(Default)]
+pub struct WatchState {
+    watcher: Mutex<Option<RecommendedWatcher>>,
+    /// Serializes changes to `watch-folders.json` between commands and the
+    /// worker thread.
+    store: Mutex<()>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchStore {
+    #[serde(default)]
+    pub folders: Vec<WatchedFolder>,
+    /// Files imported from watched folders, keyed by path, so that changes
+    /// made while the app was closed are picked up by the startup scan.
+    #[serde(default)]
+    files: HashMap<String, WatchedFile>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WatchedFile {
+    book_id: String,
+    size: u64,
+    modified_ms: u64,
+}
+
+/// Applies `change` to the stored watch configuration and saves it.
+pub fn update_store<T>(
+    app: &AppHandle,
+    change: impl FnOnce(&mut WatchStore) -> Result<T, String>,
+) -> Result<T, String> {
+    let state = app.state::<WatchState>();
+    let _guard = state
+        .store
+        .lock()
+        .map_err(|_| "Watch folder lock poisoned".to_string())?;
+    let path = config::get_watch_folders_path(app)?;
+    let mut store: WatchStore = storage::read_json(&path)?;
+    let result = change(&mut store)?;
+    storage::write_json(&path, &store)?;
+    Ok(result)
+}
+
+pub fn add_folder(
+    store: &mut WatchStore,
+    path: &str,
+    remove_on_delete: bool,
+) -> Result<WatchedFolder, String> {
+    let canonical = fs::canonicalize(path)
+        .map_err(|error| format!("Failed to open folder {}: {error}", path))?;
+    if !canonical.is_dir() {
+        return Err(format!("{} is not a folder", path));
+    }
+    let path = canonical.to_string_lossy().to_string();
+    if store.folders.iter().any(|folder| folder.path == path) {
+        return Err(format!("{} is already watched", path));
+    }
+    let folder = WatchedFolder {
+        path,
+        remove_on_delete,
+    };
+    store.folders.push(folder.clone());
+    Ok(folder)
+}
+
+pub fn update_folder(
+    store: &mut WatchStore,
+    folder: WatchedFolder,
+) -> Result<WatchedFolder, String> {
+    let existing = store
+        .folders
+        .iter_mut()
+        .find(|existing| existing.path == folder.path)
+        .ok_or_else(|| format!("{} is not a watched folder", folder.path))?;
+    existing.remove_on_delete = folder.remove_on_delete;
+    Ok(existing.clone())
+}
+
+/// Stops tracking a folder. Books imported from it stay in the library.
+pub fn remove_folder(store: &mut WatchStore, path: &str) -> Result<(), String> {
+    let before = store.folders.len();
+    store.folders.retain(|folder| folder.path != path);
+    if store.folders.len() == before {
+        return Err(format!("{} is not a watched folder", path));
+    }
+    store
+        .files
+        .retain(|file, _| !Path::new(file).starts_with(path));
+    Ok(())
+}
+
+/// Starts watching the configured folders, replacing any previous watcher,
+/// and scans them in the background to catch up on changes made while they
+/// were not being watched.
+pub fn restart(app: &AppHandle) -> Result<(), String> {
+    let store: WatchStore = storage::read_json(&config::get_watch_folders_path(app)?)?;
+    let state = app.state::<WatchState>();
+    let mut guard = state
+        .watcher
+        .lock()
+        .map_err(|_| "Folder watcher lock poisoned".to_string())?;
+    *guard = None;
+    if store.folders.is_empty() {
+        return Ok(());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        match result {
+            Ok(event) => {
+                for path in event.paths {
+                    let _ = sender.send(path);
+                }
+            }
+            Err(error) => println!("DEBUG WARNING: Folder watcher error: {}", error),
+        }
+    })
+    .map_err(|error| format!("Failed to start folder watcher: {error}"))?;
+    for folder in &store.folders {
+        if let Err(error) = watcher.watch(Path::new(&folder.path), RecursiveMode::Recursive) {
+            println!("DEBUG WARNING: Failed to watch {}: {}", folder.path, error);
+        }
+    }
+    *guard = Some(watcher);
+
+    let app = app.clone();
+    thread::spawn(move || run(app, receiver));
+    Ok(())
+}
+
+fn run(app: AppHandle, receiver: Receiver<PathBuf>) {
+    sync(&app, None);
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(path) => {
+                pending.insert(path, Instant::now());
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            // The watcher was replaced; its successor rescans everything.
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, last_event)| last_event.elapsed() >= SETTLE_DELAY)
+            .map(|(path, _)| path.clone())
+            .collect();
+        if settled.is_empty() {
+            continue;
+        }
+        for path in &settled {
+            pending.remove(path);
+        }
+        sync(&app, Some(&settled));
+    }
+}
+
+/// Brings the library in line with `paths`, or with every watched folder
+/// when `paths` is `None`.
+fn sync(app: &AppHandle, paths: Option<&[PathBuf]>) {
+    let result = update_store(app, |store| {
+        let (present, missing) = candidates(store, paths);
+        for path in present {
+            import_file(app, store, &path);
+        }
+        for path in missing {
+            forget_file(app, store, &path);
+        }
+        Ok(())
+    });
+    if let Err(error) = result {
+        println!("DEBUG WARNING: Failed to sync watched folders: {}", error);
+    }
+}
+
+/// Splits the affected paths into EPUBs that exist and tracked files that
+/// are gone. Existing files are handled first so that a rename, which
+/// arrives as a delete plus a create, keeps the book.
+fn candidates(store: &WatchStore, paths: Option<&[PathBuf]>) -> (Vec<PathBuf>, Vec<String>) {
+    let roots: Vec<PathBuf> = match paths {
+        Some(paths) => paths.to_vec(),
+        None => store
+            .folders
+            .iter()
+            .map(|folder| PathBuf::from(&folder.path))
+            .collect(),
+    };
+    let mut present = Vec::new();
+    let mut missing = Vec::new();
+    for root in roots {
+        if folder_for(store, &root).is_none() {
+            continue;
+        }
+        if root.is_dir() {
+            present.extend(storage::list_files(&root).unwrap_or_default());
+        } else if root.is_file() {
+            present.push(root.clone());
+        }
+        missing.extend(
+            store
+                .files
+                .keys()
+                .filter(|file| Path::new(file).starts_with(&root) && !Path::new(file).exists())
+                .cloned(),
+        );
+    }
+    present.retain(|path| is_epub(path));
+    present.sort();
+    present.dedup();
+    missing.sort();
+    missing.dedup();
+    (present, missing)
+}
+
+fn import_file(app: &AppHandle, store: &mut WatchStore, path: &Path) {
+    let key = path.to_string_lossy().to_string();
+    let Some((size, modified_ms)) = file_stamp(path) else {
+        return;
+    };
+    let previous = store.files.get(&key);
+    if previous.is_some_and(|file| file.size == size && file.modified_ms == modified_ms) {
+        return;
+    }
+    let previous_id = previous.map(|file| file.book_id.clone());
+
+    let result = fs::read(path)
+        .map_err(|error| format!("Failed to read {}: {error}", path.display()))
+        .and_then(|bytes| importer::import_epub(app, bytes, previous_id.as_deref()));
+    match result {
+        Ok(outcome) => {
+            let kind = match &outcome {
+                ImportOutcome::Added(_) => Some(LibraryChangeKind::Added),
+                ImportOutcome::Updated(_) => Some(LibraryChangeKind::Updated),
+                ImportOutcome::Unchanged(_) => None,
+            };
+            let book = outcome.book();
+            store.files.insert(
+                key.clone(),
+                WatchedFile {
+                    book_id: book.id.clone(),
+                    size,
+                    modified_ms,
+                },
+            );
+            if let Some(kind) = kind {
+                emit_change(app, kind, book, &key);
+            }
+        }
+        Err(error) => {
+            println!("DEBUG WARNING: Failed to import {}: {}", key, error);
+            let failure = ImportFailure {
+                source_path: key,
+                error,
+            };
+            if let Err(error) = app.emit(IMPORT_FAILED_EVENT, failure) {
+                println!("DEBUG WARNING: Failed to emit {}: {}", IMPORT_FAILED_EVENT, error);
+            }
+        }
+    }
+}
+
+/// Drops a deleted file from tracking and, when its folder asks for it,
+/// removes the book unless another watched file still provides it.
+fn forget_file(app: &AppHandle, store: &mut WatchStore, key: &str) {
+    let Some(file) = store.files.remove(key) else {
+        return;
+    };
+    let remove = folder_for(store, Path::new(key)).is_some_and(|folder| folder.remove_on_delete)
+        && !store
+            .files
+            .values()
+            .any(|other| other.book_id == file.book_id);
+    if !remove {
+        return;
+    }
+    match importer::remove_book(app, &file.book_id) {
+        Ok(Some(book)) => emit_change(app, LibraryChangeKind::Removed, &book, key),
+        Ok(None) => {}
+        Err(error) => println!("DEBUG WARNING: Failed to remove {}: {}", file.book_id, error),
+    }
+}
+
+fn emit_change(app: &AppHandle, kind: LibraryChangeKind, book: &BookEntry, source_path: &str) {
+    let change = LibraryChange {
+        kind,
+        book_id: book.id.clone(),
+        title: book.title.clone(),
+        source_path: Some(source_path.to_string()),
+    };
+    if let Err(error) = app.emit(LIBRARY_CHANGED_EVENT, change) {
+        println!("DEBUG WARNING: Failed to emit {}: {}", LIBRARY_CHANGED_EVENT, error);
+    }
+}
+
+fn folder_for<'a>(store: &'a WatchStore, path: &Path) -> Option<&'a WatchedFolder> {
+    store
+        .folders
+        .iter()
+        .find(|folder| path.starts_with(&folder.path))
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_millis() as u64))
+}
+
+fn is_epub(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("epub"))
+}
@@ -1,4 +1,5 @@
-const { invoke } = window.__TAURI__.core;
+const { invoke, convertFileSrc } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
 
 const state = {
   book: null,
@@ -13,6 +14,7 @@ const state = {
     model: "eleven_multilingual_v2",
   },
   elevenlabsVoices: [],
+  localVoices: [],
   activeVoice: null,
   activeBookId: null,
   readingPositions: {},
@@ -116,6 +118,7 @@ function setStatus(text, tone = "idle") {
 }
 
 function resetPlayback() {
+  cancelSynthesis();
   if (state.activeAudio) {
     state.activeAudio.pause();
     state.activeAudio.currentTime = 0;
@@ -427,7 +430,8 @@ function applySettingsToUI() {
   if (state.ui.leftCollapsed) leftPanel.classList.add('collapsed');
   if (state.ui.rightCollapsed) rightPanel.classList.add('collapsed');
   
-  voiceProvider.value = state.voiceMode;
+  // The clone form only lists providers that can clone voices.
+  if (state.voiceMode !== "local") voiceProvider.value = state.voiceMode;
   renderVoiceList();
   renderBookGrid();
 }
@@ -446,6 +450,10 @@ function setActiveVoice(provider, voiceId, label) {
   pauseReaderPlayback();
 }
 
+function coverUrl(bookId, size) {
+  return convertFileSrc(`${bookId}/${size}`, "cover");
+}
+
 function renderBookGrid() {
   bookGrid.innerHTML = "";
   if (!state.library || state.library.length === 0) {
@@ -459,13 +467,8 @@ function renderBookGrid() {
     card.className = `book-card ${book.id === state.activeBookId ? 'active' : ''}`;
     
     const displayTitle = book.title || "Untitled";
-    const initial = displayTitle[0] || "?";
-    
-    let coverHtml = `<div class="book-cover" style="background: var(--border-color); display: flex; align-items: center; justify-content: center; font-size: 2rem; font-weight: 700; color: var(--text-muted);">${initial}</div>`;
-    if (book.coverBase64) {
-      const mime = book.coverMime || "image/jpeg";
-      coverHtml = `<img src="data:${mime};base64,${book.coverBase64}" class="book-cover" alt="${displayTitle}" />`;
-    }
+    // Thumbnails (or generated placeholders) come from the backend's cover scheme
+    const coverHtml = `<img src="${coverUrl(book.id, "grid")}" class="book-cover" alt="${displayTitle}" loading="lazy" />`;
 
     card.innerHTML = `
       <div class="book-cover-wrapper">
@@ -543,14 +546,8 @@ function removeBook(bookId, isConfirmed = false) {
     delete state.readingPositions[bookId];
   }
   
-  // Save to localStorage
-  try {
-    localStorage.setItem("rebook-library", JSON.stringify(state.library));
-    saveSettings();
-    console.log("Library saved to localStorage");
-  } catch (error) {
-    console.error("Error saving library:", error);
-  }
+  saveSettings();
+  saveLibrary();
   
   // Re-render
   renderBookGrid();
@@ -558,11 +555,14 @@ function removeBook(bookId, isConfirmed = false) {
   setStatus("Book removed", "success");
 }
 
+const PROVIDER_LABELS = { minimax: "Minimax", elevenlabs: "Eleven Labs", local: "Local" };
+
 function renderVoiceList() {
   voiceList.innerHTML = "";
   const combined = [
     ...(state.minimaxVoices || []).map((v) => ({ ...v, provider: "minimax" })),
     ...(state.elevenlabsVoices || []).map((v) => ({ ...v, provider: "elevenlabs" })),
+    ...state.localVoices.map((v) => ({ ...v, provider: "local" })),
   ];
 
   if (combined.length === 0) {
@@ -583,7 +583,7 @@ function renderVoiceList() {
       </div>
       <div class="voice-item-details">
         <div class="voice-item-name">${voice.label || voice.voiceId || "Unnamed Voice"}</div>
-        <div class="voice-item-provider">${voice.provider === 'minimax' ? 'Minimax' : 'Eleven Labs'}</div>
+        <div class="voice-item-provider">${PROVIDER_LABELS[voice.provider] || voice.provider}</div>
       </div>
     `;
     
@@ -891,7 +891,7 @@ function parseAuthorName(name) {
   return cleanName;
 }
 
-function setActiveBook(bookId) {
+async function setActiveBook(bookId) {
   persistReadingPosition();
   const book = state.library.find((item) => item.id === bookId) || null;
   state.activeBookId = book ? book.id : null;
@@ -900,7 +900,10 @@ function setActiveBook(bookId) {
   renderBookGrid();
   
   if (book) {
-    buildReaderData(book);
+    const narration = await loadNarration(book);
+    // Another book may have been opened while the plans loaded.
+    if (state.activeBookId !== book.id) return;
+    buildReaderData(book, narration);
     const savedPosition = state.readingPositions && state.readingPositions[book.id];
     if (savedPosition) {
       const maxPageIndex = Math.max(0, state.reader.pages.length - 1);
@@ -918,6 +921,7 @@ function setActiveBook(bookId) {
         state.reader.sentenceIndex = 0;
       }
     }
+    restoreBackendPosition(book.id);
     // Autofill author name for voice cloning
     if (book.author) {
       voiceNameInput.value = parseAuthorName(book.author);
@@ -929,20 +933,64 @@ function setActiveBook(bookId) {
 }
 
 function persistReadingPosition() {
-  if (!state.activeBookId || !state.reader.pages.length) return;
+  const bookId = state.reader.bookId;
+  if (!bookId || !state.reader.pages.length) return;
   if (!state.readingPositions) state.readingPositions = {};
-  state.readingPositions[state.activeBookId] = {
+  state.readingPositions[bookId] = {
     pageIndex: state.reader.pageIndex,
     sentenceIndex: state.reader.sentenceIndex,
   };
   saveSettings();
+  const total = state.reader.sentences.length;
+  const position = {
+    sentenceText: state.reader.sentences[state.reader.sentenceIndex] || null,
+    progress: total > 1 ? state.reader.sentenceIndex / (total - 1) : 0,
+  };
+  invoke("save_reading_position", { bookId, position }).catch((error) => {
+    console.error("Failed to save reading position:", error);
+  });
+}
+
+async function restoreBackendPosition(bookId) {
+  let resolved = null;
+  try {
+    resolved = await invoke("restore_reading_position", { bookId });
+  } catch (error) {
+    console.error("Failed to restore reading position:", error);
+    return;
+  }
+  if (!resolved || state.activeBookId !== bookId || state.reader.isPlaying) return;
+  const index = findSentenceIndexForLocator(resolved.locator);
+  if (index < 0) return;
+  state.reader.sentenceIndex = index;
+  const pageIndex = state.reader.sentencePageMap[index];
+  if (Number.isInteger(pageIndex)) state.reader.pageIndex = pageIndex;
+  renderReader();
+}
+
+function findSentenceIndexForLocator(locator) {
+  const sentences = state.reader.sentences;
+  const exact = normalizeForMatch(locator.quote ? locator.quote.exact : "");
+  const target = Math.round((locator.progress || 0) * Math.max(0, sentences.length - 1));
+  if (!exact) return Math.min(target, sentences.length - 1);
+  let best = -1;
+  sentences.forEach((sentence, i) => {
+    const normalized = normalizeForMatch(sentence);
+    if (!normalized || !(normalized.includes(exact) || exact.includes(normalized))) return;
+    if (best < 0 || Math.abs(i - target) < Math.abs(best - target)) best = i;
+  });
+  return best;
 }
 
 function resetReaderState() {
   state.reader = {
+    bookId: null,
     pages: [],
     pageIndex: 0,
     sentences: [],
+    sentenceSpans: [],
+    narrationChunks: [],
+    nextChunk: null,
     sentenceIndex: 0,
     sentencePageMap: [],
     highlightRange: null,
@@ -954,7 +1002,69 @@ function resetReaderState() {
   };
 }
 
-function buildReaderData(book) {
+// The book's sentences and narration chunks, from the backend's plan for each
+// chapter so that every chunk names its chapter and character range.
+async function loadNarration(book) {
+  const plans = await Promise.all(book.chapters.map((chapter) =>
+    invoke("plan_chapter_narration", { bookId: book.id, chapterId: chapter.id }).catch((error) => {
+      console.warn(`Failed to plan narration for chapter ${chapter.id}:`, error);
+      return null;
+    })
+  ));
+  const sentences = [];
+  const sentenceSpans = [];
+  const narrationChunks = [];
+  book.chapters.forEach((chapter, chapterIndex) => {
+    const plan = plans[chapterIndex];
+    const offset = sentences.length;
+    if (plan) {
+      plan.sentences.forEach((span) => {
+        sentences.push(displaySentence(span.text));
+        sentenceSpans.push({ chapterId: chapter.id, start: span.start, end: span.end });
+      });
+      plan.chunks.forEach((chunk) => {
+        narrationChunks.push({
+          ...chunk,
+          chapterId: chapter.id,
+          firstSentence: offset + chunk.firstSentence,
+          position: narrationChunks.length,
+        });
+      });
+      return;
+    }
+    // Without a plan the chapter is read two sentences at a time as plain text.
+    const chapterSentences = splitIntoSentences(chapter.text);
+    chapterSentences.forEach((sentence) => {
+      sentences.push(sentence);
+      sentenceSpans.push(null);
+    });
+    for (let i = 0; i < chapterSentences.length; i += 2) {
+      narrationChunks.push({
+        id: `${chapter.id}:sentences-${i}`,
+        chapterId: chapter.id,
+        start: null,
+        end: null,
+        text: chapterSentences.slice(i, i + 2).join(" "),
+        firstSentence: offset + i,
+        sentenceCount: Math.min(2, chapterSentences.length - i),
+        position: narrationChunks.length,
+      });
+    }
+  });
+  return { sentences, sentenceSpans, narrationChunks };
+}
+
+// A sentence of the chapter text as it reads on the page, without the
+// markup html2text adds for links, emphasis, headings and quotes.
+function displaySentence(text) {
+  return text
+    .replace(/\[([^\]]*)\]\[\d+\]/g, "$1")
+    .replace(/^[#>\s]+/, "")
+    .replace(/\*+(?=\w)|([\w.,;:!?'"”’])\*+/g, "$1")
+    .trim();
+}
+
+function buildReaderData(book, narration) {
   const paragraphs = [];
   const parser = new DOMParser();
 
@@ -999,13 +1109,7 @@ function buildReaderData(book) {
     }
   });
 
-  const combined = paragraphs.map(p => {
-    const div = document.createElement('div');
-    div.innerHTML = p;
-    return div.textContent || "";
-  }).join(" ");
-
-  const sentences = splitIntoSentences(combined);
+  const { sentences, sentenceSpans, narrationChunks } = narration;
   const pages = [];
   const wordLimit = 250;
   let current = [];
@@ -1091,9 +1195,13 @@ function buildReaderData(book) {
 
   state.reader = {
     ...state.reader,
+    bookId: book.id,
     pages,
     pageIndex: 0,
     sentences,
+    sentenceSpans,
+    narrationChunks,
+    nextChunk: null,
     sentenceIndex: 0,
     sentencePageMap,
     highlightRange: null,
@@ -1119,6 +1227,24 @@ async function handleEpubImport(file) {
   try {
     const base64 = await readFileAsBase64(file);
     const book = await invoke("parse_epub", { base64 });
+    const duplicates = await invoke("find_duplicate_books", {
+      contentHash: book.contentHash || null,
+      identifier: book.identifier || null,
+    });
+    const existing = duplicates[0];
+    if (existing && existing.reason === "sameFile") {
+      setActiveBook(existing.bookId);
+      setStatus("Book already in library", "success");
+      return;
+    }
+    if (existing && confirm(`"${existing.title}" is already in your library. Update the existing book and keep your progress?`)) {
+      const update = await invoke("update_existing_book", { bookId: existing.bookId, book });
+      state.library = state.library.map((item) => (item && item.id === update.book.id ? update.book : item));
+      if (state.activeBookId === update.book.id) state.activeBookId = null;
+      setActiveBook(update.book.id);
+      setStatus("Book updated", "success");
+      return;
+    }
     const entry = {
       id: `book-${Date.now()}`,
       title: book.title,
@@ -1127,6 +1253,21 @@ async function handleEpubImport(file) {
       coverMime: book.coverMime || null,
       chapters: book.chapters,
       importedAt: new Date().toISOString(),
+      language: book.language || null,
+      series: book.series || null,
+      seriesIndex: book.seriesIndex ?? null,
+      tags: [],
+      contentHash: book.contentHash || null,
+      identifier: book.identifier || null,
+      description: book.description || null,
+      lexicon: book.lexicon || [],
+      imported: {
+        title: book.title,
+        author: book.author || null,
+        series: book.series || null,
+        seriesIndex: book.seriesIndex ?? null,
+        description: book.description || null,
+      },
     };
     state.library.unshift(entry);
     await saveLibrary();
@@ -1140,38 +1281,185 @@ async function handleEpubImport(file) {
   }
 }
 
-async function generateAudio(index, text) {
+function providerOptions(provider, voiceId) {
+  switch (provider) {
+    case "minimax":
+      return { voiceId, model: "speech-2.6-hd", outputFormat: "mp3" };
+    case "elevenlabs":
+      return { voiceId, model: "eleven_multilingual_v2" };
+    default:
+      return { voiceId };
+  }
+}
+
+function ttsRequest(chunk) {
+  return {
+    chapterId: chunk.chapterId,
+    text: chunk.text,
+    start: chunk.start,
+    end: chunk.end,
+    provider: state.voiceMode,
+    options: providerOptions(state.voiceMode, state.activeVoice.voiceId),
+    bookId: state.activeBookId,
+    language: state.library.find((item) => item.id === state.activeBookId)?.language || null
+  };
+}
+
+async function generateAudio(chunk, jobId = null) {
   if (!state.activeVoice) throw new Error("No active voice");
   
-  return await invoke("tts_generate", {
-    request: {
-      chapterId: `chunk-${index}`,
-      text: text,
-      voiceMode: state.voiceMode,
-      minimax: state.voiceMode === 'minimax' ? { voiceId: state.activeVoice.voiceId, model: "speech-2.6-hd", outputFormat: "mp3" } : null,
-      elevenlabs: state.voiceMode === 'elevenlabs' ? { voiceId: state.activeVoice.voiceId, model: "eleven_multilingual_v2" } : null,
-      external: null
+  return await invoke("tts_generate", { jobId, request: ttsRequest(chunk) });
+}
+
+const READER_JOB_ID = "reader";
+const PREFETCH_JOB_ID = "prefetch";
+let ttsProviders = null;
+
+async function loadTtsProviders() {
+  if (!ttsProviders) {
+    try {
+      ttsProviders = await invoke("tts_providers");
+    } catch (error) {
+      console.warn("Failed to load TTS providers:", error);
+      return [];
     }
+  }
+  return ttsProviders;
+}
+
+async function providerStreams(provider) {
+  const providers = await loadTtsProviders();
+  return providers.some((item) => item.id === provider && item.streaming);
+}
+
+// A configured local engine speaks with the voice set in its environment,
+// unless a request names another.
+async function loadLocalVoices() {
+  const providers = await loadTtsProviders();
+  const local = providers.find((item) => item.id === "local" && item.configured);
+  state.localVoices = local ? [{ voiceId: "", label: local.name }] : [];
+  renderVoiceList();
+}
+
+function base64ToBytes(value) {
+  const binary = atob(value);
+  const bytes = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) bytes[i] = binary.charCodeAt(i);
+  return bytes;
+}
+
+// Resolves with an audio URL as soon as the first chunk arrives, feeding the
+// rest through a MediaSource. Without MediaSource support for the format it
+// resolves once the whole clip is in.
+function streamAudio(chunk) {
+  if (!state.activeVoice) return Promise.reject(new Error("No active voice"));
+
+  return new Promise((resolve, reject) => {
+    const channel = new window.__TAURI__.core.Channel();
+    const pending = [];
+    const parts = [];
+    let mediaSource = null;
+    let sourceBuffer = null;
+    let finished = false;
+    let mime = "audio/mpeg";
+
+    const flush = () => {
+      if (!sourceBuffer || sourceBuffer.updating) return;
+      if (pending.length) {
+        sourceBuffer.appendBuffer(pending.shift());
+      } else if (finished && mediaSource.readyState === "open") {
+        mediaSource.endOfStream();
+      }
+    };
+
+    channel.onmessage = (message) => {
+      if (message.event === "chunk") {
+        const bytes = base64ToBytes(message.audioBase64);
+        mime = message.mime;
+        if (!mediaSource && !parts.length && window.MediaSource?.isTypeSupported(mime)) {
+          mediaSource = new MediaSource();
+          mediaSource.addEventListener("sourceopen", () => {
+            sourceBuffer = mediaSource.addSourceBuffer(mime);
+            sourceBuffer.addEventListener("updateend", flush);
+            flush();
+          }, { once: true });
+          resolve(URL.createObjectURL(mediaSource));
+        }
+        if (mediaSource) {
+          pending.push(bytes);
+          flush();
+        } else {
+          parts.push(bytes);
+        }
+      } else if (message.event === "finished") {
+        finished = true;
+        if (mediaSource) {
+          flush();
+        } else {
+          resolve(URL.createObjectURL(new Blob(parts, { type: mime })));
+        }
+      } else if (message.event === "cancelled") {
+        reject(new Error("Synthesis cancelled"));
+      }
+    };
+
+    invoke("tts_stream", { jobId: READER_JOB_ID, request: ttsRequest(chunk), channel })
+      .catch((error) => {
+        if (mediaSource?.readyState === "open") mediaSource.endOfStream("network");
+        reject(error);
+      });
+  });
+}
+
+function cancelSynthesis() {
+  invoke("cancel_tts", { jobId: null }).catch((error) => {
+    console.warn("Failed to cancel synthesis:", error);
   });
 }
 
-async function prefetchNextChunk(nextIndex) {
-  if (nextIndex >= state.reader.sentences.length) return;
-  if (state.reader.prefetchQueue.some(item => item.index === nextIndex)) return;
+// The chunk of the narration plan to read from `sentenceIndex`: the one
+// queued after the last chunk read, or the chunk holding the sentence. Starting
+// partway into a chunk reads from that sentence to the chunk's end.
+function narrationChunkAt(sentenceIndex) {
+  const { narrationChunks, sentenceSpans, nextChunk } = state.reader;
+  const queued = narrationChunks[nextChunk];
+  if (queued && queued.firstSentence === sentenceIndex) return queued;
+  const chunk = narrationChunks.find((item) =>
+    sentenceIndex >= item.firstSentence && sentenceIndex < item.firstSentence + item.sentenceCount
+  );
+  if (!chunk || sentenceIndex === chunk.firstSentence) return chunk || null;
+  const lastSentence = chunk.firstSentence + chunk.sentenceCount;
+  const text = state.reader.sentences.slice(sentenceIndex, lastSentence).join(" ");
+  const span = sentenceSpans[sentenceIndex];
+  if (!span || chunk.start === null) {
+    return { ...chunk, id: `${chunk.id}+${sentenceIndex}`, text, firstSentence: sentenceIndex, sentenceCount: lastSentence - sentenceIndex };
+  }
+  return {
+    ...chunk,
+    id: `${chunk.chapterId}:${span.start}-${chunk.end}`,
+    start: span.start,
+    text,
+    firstSentence: sentenceIndex,
+    sentenceCount: lastSentence - sentenceIndex,
+  };
+}
+
+async function prefetchNextChunk(chunk) {
+  if (!chunk) return;
+  if (state.reader.prefetchQueue.some(item => item.id === chunk.id)) return;
   if (state.reader.isPrefetching) return;
   if (!state.activeVoice) return;
 
   state.reader.isPrefetching = true;
   
   try {
-    const chunk = state.reader.sentences.slice(nextIndex, nextIndex + 2).join(" ");
-    if (!chunk.trim()) {
+    if (!chunk.text.trim()) {
       state.reader.isPrefetching = false;
       return;
     }
     
-    const clip = await generateAudio(nextIndex, chunk);
-    state.reader.prefetchQueue.push({ index: nextIndex, clip });
+    const clip = await generateAudio(chunk, PREFETCH_JOB_ID);
+    state.reader.prefetchQueue.push({ id: chunk.id, clip });
   } catch (error) {
     console.warn("Prefetch failed:", error);
   } finally {
@@ -1188,35 +1476,53 @@ async function playNextChunk() {
     return;
   }
 
-  state.reader.isAdvancing = true;
-  const count = 2;
   const currentIndex = state.reader.sentenceIndex;
+  const chunk = narrationChunkAt(currentIndex);
+  if (!chunk) {
+    state.reader.isPlaying = false;
+    setStatus("Playback complete", "success");
+    renderReader();
+    return;
+  }
+
+  state.reader.isAdvancing = true;
+  const count = chunk.sentenceCount;
   state.reader.highlightRange = { start: currentIndex, count };
   highlightCurrentSentence();
   
   try {
-    let audioClip;
-    const prefetched = state.reader.prefetchQueue.find(item => item.index === currentIndex);
+    let audioUrl;
+    const prefetched = state.reader.prefetchQueue.find(item => item.id === chunk.id);
     
     if (prefetched) {
-      audioClip = prefetched.clip;
-      state.reader.prefetchQueue = state.reader.prefetchQueue.filter(item => item.index !== currentIndex);
+      audioUrl = `data:${prefetched.clip.mime};base64,${prefetched.clip.audioBase64}`;
+      state.reader.prefetchQueue = state.reader.prefetchQueue.filter(item => item.id !== chunk.id);
     } else {
       state.reader.isGenerating = true;
       updateReaderPlayButton();
-      const chunkSentences = state.reader.sentences.slice(currentIndex, currentIndex + count);
-      const chunk = chunkSentences.join(" ");
-      console.log("Audio chunk:", { currentIndex, count, chunkSentences, chunk });
-      audioClip = await generateAudio(currentIndex, chunk);
+      console.log("Audio chunk:", { currentIndex, count, id: chunk.id, text: chunk.text });
+      if (await providerStreams(state.voiceMode)) {
+        audioUrl = await streamAudio(chunk);
+      } else {
+        const audioClip = await generateAudio(chunk, READER_JOB_ID);
+        audioUrl = `data:${audioClip.mime};base64,${audioClip.audioBase64}`;
+      }
+    }
+    if (!state.reader.isPlaying) {
+      state.reader.isAdvancing = false;
+      return;
     }
     state.reader.isGenerating = false;
     updateReaderPlayButton();
     
-    const audio = new Audio(`data:${audioClip.mime};base64,${audioClip.audioBase64}`);
+    const audio = new Audio(audioUrl);
     state.activeAudio = audio;
     
+    const following = state.reader.narrationChunks[chunk.position + 1] || null;
     audio.addEventListener("ended", () => {
-      state.reader.sentenceIndex += count;
+      // A chunk can start with the end of a sentence the one before split.
+      state.reader.nextChunk = following ? following.position : null;
+      state.reader.sentenceIndex = following ? following.firstSentence : state.reader.sentences.length;
       const nextPageIndex = state.reader.sentencePageMap[state.reader.sentenceIndex] ?? state.reader.pageIndex;
       if (nextPageIndex !== state.reader.pageIndex) {
         state.reader.pageIndex = nextPageIndex;
@@ -1231,20 +1537,49 @@ async function playNextChunk() {
     
     audio.play();
     setStatus("Playing", "playing");
+    reportNarration(currentIndex, count, chunk.chapterId);
     
     // Background prefetch for the NEXT chunk
-    prefetchNextChunk(currentIndex + count);
+    prefetchNextChunk(following);
     
   } catch (error) {
-    console.error(error);
     state.reader.isGenerating = false;
     updateReaderPlayButton();
+    // Pausing or seeking cancels the synthesis it was waiting on.
+    if (!state.reader.isPlaying) {
+      state.reader.isAdvancing = false;
+      return;
+    }
+    console.error(error);
     setStatus("Playback failed", "error");
     pauseReaderPlayback();
   }
 }
 
+function reportNarration(sentenceIndex, count = 1, chapterId = null) {
+  const sentenceText = sentenceIndex === null ? null : state.reader.sentences[sentenceIndex];
+  const total = state.reader.sentences.length;
+  const chunk = sentenceIndex === null ? [] : state.reader.sentences.slice(sentenceIndex, sentenceIndex + count);
+  const chunkText = chunk.join(" ");
+  const nowPlaying = sentenceText && state.activeBookId
+    ? {
+        bookId: state.activeBookId,
+        chapterId,
+        sentenceText,
+        progress: total > 1 ? sentenceIndex / (total - 1) : 0,
+        sentenceCount: chunk.length,
+        wordCount: chunkText.split(/\s+/).filter(Boolean).length,
+        characterCount: chunkText.length,
+        provider: state.voiceMode,
+      }
+    : null;
+  invoke("report_narration", { nowPlaying }).catch((error) => {
+    console.error("Failed to report narration:", error);
+  });
+}
+
 function pauseReaderPlayback() {
+  reportNarration(null);
   state.reader.isPlaying = false;
   state.reader.isAdvancing = false;
   state.reader.highlightRange = null;
@@ -1298,15 +1633,11 @@ async function handleVoiceClone() {
 
   try {
     const audioBase64 = await readFileAsBase64(file);
-    let voiceId;
+    const clone = await invoke("tts_clone_voice", { provider, request: { name, filename: file.name, audioBase64 } });
+    const voiceId = clone.voiceId;
     if (provider === "minimax") {
-      const upload = await invoke("minimax_upload_clone_audio", { request: { filename: file.name, audioBase64 } });
-      voiceId = `rebook_${name.toLowerCase().replace(/\s+/g, '_')}_${Date.now().toString(36)}`;
-      await invoke("minimax_create_clone", { request: { fileId: upload.fileId, voiceId } });
       state.minimaxVoices.push({ voiceId, label: name, isCloned: true });
     } else {
-      const clone = await invoke("elevenlabs_create_clone", { request: { name, filename: file.name, audioBase64 } });
-      voiceId = clone.voiceId;
       state.elevenlabsVoices.push({ voiceId, label: name, isCloned: true });
     }
     
@@ -1350,8 +1681,12 @@ async function saveLibrary() {
 async function loadLibrary() {
   try {
     const saved = await invoke("load_library");
-    if (Array.isArray(saved)) {
-      state.library = saved;
+    if (saved && saved.recovery) {
+      console.warn("Library recovered from backup:", saved.recovery);
+      setStatus(`Library restored from ${saved.recovery.source} (${saved.recovery.bookCount} books)`, "error");
+    }
+    if (saved && Array.isArray(saved.books)) {
+      state.library = saved.books;
       renderBookGrid();
       if (state.activeBookId) {
         setActiveBook(state.activeBookId);
@@ -1362,6 +1697,27 @@ async function loadLibrary() {
   }
 }
 
+// The backend changes the library on its own when watched folders change.
+async function handleLibraryChange(change) {
+  try {
+    const saved = await invoke("load_library");
+    state.library = saved.books;
+  } catch (error) {
+    console.error("Failed to reload library:", error);
+    return;
+  }
+  if (state.activeBookId === change.bookId) {
+    state.activeBookId = null;
+    state.book = null;
+    resetReaderState();
+    if (change.kind !== "removed") setActiveBook(change.bookId);
+  }
+  renderBookGrid();
+  renderReader();
+  const verb = { added: "Added", updated: "Updated", removed: "Removed" }[change.kind];
+  setStatus(`${verb} "${change.title}" from watched folder`, "success");
+}
+
 function backfillVoices() {
   const minimaxId = "ray-dalio";
   if (!state.minimaxVoices.some((voice) => voice.voiceId === minimaxId)) {
@@ -1379,11 +1735,18 @@ loadSettings();
 loadLibrary().then(() => {
   backfillVoices();
   applySettingsToUI();
+  loadLocalVoices();
   if (state.activeBookId) {
     setActiveBook(state.activeBookId);
   }
 });
 
+listen("library-changed", (event) => handleLibraryChange(event.payload));
+listen("library-import-failed", (event) => {
+  console.error("Watched folder import failed:", event.payload);
+  setStatus(`Import failed: ${event.payload.error}`, "error");
+});
+
 // Event Listeners
 toggleLeftBtn.addEventListener('click', () => {
   state.ui.leftCollapsed = !state.ui.leftCollapsed;